@@ -9,7 +9,11 @@
 //! that these aggregation types manipulate. And the output type is bounded by `Display` so the tool can write
 //! the outputs to standard output.
 
-use crate::parsing::DecimalWrapper;
+use crate::parsing::{BooleanWrapper, DecimalWrapper};
+use rand::rngs::StdRng;
+use rand::{FromEntropy, Rng, SeedableRng};
+use regex::Regex;
+use rust_decimal::prelude::ToPrimitive;
 use rust_decimal::Decimal;
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::marker::PhantomData;
@@ -26,6 +30,163 @@ pub trait Accumulate<I, O> {
     /// Computes the final value. Returns an option value, which is usually guaranteed to be Some(val)
     /// (with the exception of `StdDev`.)
     fn compute(&self) -> Option<O>;
+    /// Combines this accumulator with one holding the values that came after this one's
+    /// (like the values in the next file), as though they had all been added to this one.
+    fn merge(&mut self, other: Self);
+}
+
+/// An approximate median, using the
+/// [P² algorithm](https://www.cse.wustl.edu/~jain/papers/ftp/psqr.pdf) by Jain and Chlamtac.
+///
+/// Unlike `Median`, this uses a constant amount of memory (five markers) and runs in `N` time,
+/// no matter how many unique values there are. The tradeoff is that the result is an estimate:
+/// it's exact for five or fewer values, and it typically falls within a fraction of a percent of the
+/// true median on large, smoothly distributed data, but it can be further off on small or oddly shaped samples.
+///
+/// Values that aren't finite (like `NaN` or `inf`) can't be placed between the markers, so they're skipped.
+pub struct ApproxMedian {
+    /// The estimated minimum, 1st quartile, median, 3rd quartile and maximum
+    heights: [f64; 5],
+    /// The actual positions of each marker (starting at 1)
+    positions: [f64; 5],
+    /// The positions each marker would have if its height were exact
+    desired: [f64; 5],
+    num: usize,
+}
+
+impl ApproxMedian {
+    /// How much each marker's desired position increases with every new value
+    const INCREMENTS: [f64; 5] = [0., 0.25, 0.5, 0.75, 1.];
+
+    /// Predicts a marker's new height with the piecewise-parabolic (P²) formula
+    fn parabolic(&self, i: usize, d: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + d / (n[i + 1] - n[i - 1])
+            * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]))
+    }
+
+    /// Predicts a marker's new height by linear interpolation with its neighbor
+    fn linear(&self, i: usize, d: f64) -> f64 {
+        let j = if d > 0. { i + 1 } else { i - 1 };
+        let (q, n) = (&self.heights, &self.positions);
+        q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+    }
+}
+
+impl Accumulate<f64, f64> for ApproxMedian {
+    fn new(item: f64) -> ApproxMedian {
+        let mut median = ApproxMedian {
+            heights: [0.; 5],
+            positions: [1., 2., 3., 4., 5.],
+            desired: [1., 2., 3., 4., 5.],
+            num: 0,
+        };
+        median.update(item);
+        median
+    }
+
+    fn update(&mut self, item: f64) {
+        if !item.is_finite() {
+            return;
+        }
+        // until there are five values, the markers are just the sorted values
+        if self.num < 5 {
+            self.heights[self.num] = item;
+            self.num += 1;
+            self.heights[..self.num].sort_by(f64::total_cmp);
+            return;
+        }
+        self.num += 1;
+        let k = if item < self.heights[0] {
+            self.heights[0] = item;
+            0
+        } else if item >= self.heights[4] {
+            self.heights[4] = item;
+            3
+        } else {
+            // the cell (between two markers) the new value falls into
+            (1..5)
+                .find(|&i| item < self.heights[i])
+                .map_or(3, |i| i - 1)
+        };
+        for position in self.positions.iter_mut().skip(k + 1) {
+            *position += 1.;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(Self::INCREMENTS.iter()) {
+            *desired += increment;
+        }
+        // moves the three middle markers towards their desired positions
+        for i in 1..4 {
+            let d = self.desired[i] - self.positions[i];
+            if (d >= 1. && self.positions[i + 1] - self.positions[i] > 1.)
+                || (d <= -1. && self.positions[i - 1] - self.positions[i] < -1.)
+            {
+                let d = d.signum();
+                let parabolic = self.parabolic(i, d);
+                self.heights[i] =
+                    if self.heights[i - 1] < parabolic && parabolic < self.heights[i + 1] {
+                        parabolic
+                    } else {
+                        self.linear(i, d)
+                    };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: ApproxMedian) {
+        // a handful of values can be added exactly, but two sets of markers can only be blended
+        if other.num < 5 {
+            for &item in &other.heights[..other.num] {
+                self.update(item);
+            }
+        } else if self.num < 5 {
+            let mut merged = other;
+            for &item in &self.heights[..self.num] {
+                merged.update(item);
+            }
+            *self = merged;
+        } else {
+            let num = self.num + other.num;
+            let weight = self.num as f64 / num as f64;
+            let (min, max) = (
+                self.heights[0].min(other.heights[0]),
+                self.heights[4].max(other.heights[4]),
+            );
+            for (height, other_height) in self.heights.iter_mut().zip(other.heights.iter()) {
+                *height = *height * weight + other_height * (1. - weight);
+            }
+            self.heights[0] = min;
+            self.heights[4] = max;
+            for ((desired, position), increment) in self
+                .desired
+                .iter_mut()
+                .zip(self.positions.iter_mut())
+                .zip(Self::INCREMENTS.iter())
+            {
+                *desired = 1. + (num - 1) as f64 * increment;
+                *position = desired.round();
+            }
+            self.num = num;
+        }
+    }
+
+    fn compute(&self) -> Option<f64> {
+        if self.num == 0 {
+            return None;
+        }
+        if self.num >= 5 {
+            return Some(self.heights[2]);
+        }
+        // the heights are the sorted values, so this is the exact median
+        let mid = self.num / 2;
+        if self.num.is_multiple_of(2) {
+            Some((self.heights[mid - 1] + self.heights[mid]) / 2.)
+        } else {
+            Some(self.heights[mid])
+        }
+    }
 }
 
 /// The total number of records added to the accumulator.
@@ -40,11 +201,39 @@ impl<I> Accumulate<I, usize> for Count<I> {
         self.0 += 1;
     }
 
+    fn merge(&mut self, other: Count<I>) {
+        self.0 += other.0;
+    }
+
     fn compute(&self) -> Option<usize> {
         Some(self.0)
     }
 }
 
+/// A count where each record counts as its weight instead of 1, for data that's already been
+/// counted (like a field with the number of times each record occurred).
+///
+/// Its input is the weight, from `Aggregator::set_weight`, rather than the value.
+pub struct WeightedCount(Decimal);
+
+impl Accumulate<DecimalWrapper, DecimalWrapper> for WeightedCount {
+    fn new(item: DecimalWrapper) -> WeightedCount {
+        WeightedCount(item.item)
+    }
+
+    fn update(&mut self, item: DecimalWrapper) {
+        self.0 += item.item;
+    }
+
+    fn merge(&mut self, other: WeightedCount) {
+        self.0 += other.0;
+    }
+
+    fn compute(&self) -> Option<DecimalWrapper> {
+        Some(DecimalWrapper { item: self.0 })
+    }
+}
+
 /// The total number of *unique* records.
 pub struct CountUnique<I>(HashSet<I>);
 
@@ -63,11 +252,149 @@ where
         self.0.insert(item);
     }
 
+    fn merge(&mut self, other: CountUnique<I>) {
+        self.0.extend(other.0);
+    }
+
     fn compute(&self) -> Option<usize> {
         Some(self.0.len())
     }
 }
 
+/// The number of unique records, along with the ratio of unique records to records,
+/// formatted like `3 (0.75)`.
+///
+/// A ratio near 1 means almost every record is unique, while a ratio near 0
+/// means the records are highly repetitive. The ratio is rounded to four decimal places.
+pub struct Cardinality<I> {
+    values: HashSet<I>,
+    num: usize,
+}
+
+impl<I> Accumulate<I, String> for Cardinality<I>
+where
+    I: std::cmp::Eq,
+    I: std::hash::Hash,
+{
+    fn new(item: I) -> Cardinality<I> {
+        let mut values = HashSet::new();
+        values.insert(item);
+        Cardinality { values, num: 1 }
+    }
+
+    fn update(&mut self, item: I) {
+        self.values.insert(item);
+        self.num += 1;
+    }
+
+    fn merge(&mut self, other: Cardinality<I>) {
+        self.values.extend(other.values);
+        self.num += other.num;
+    }
+
+    fn compute(&self) -> Option<String> {
+        let ratio = Decimal::from(self.values.len()) / Decimal::from(self.num);
+        Some(format!("{} ({})", self.values.len(), ratio.round_dp(4)))
+    }
+}
+
+/// The shortest and longest lengths of the values, along with their mean length,
+/// formatted like `2 - 7 (4.5)`.
+///
+/// Lengths are counted in characters rather than bytes, so `café` has a length of 4.
+/// The mean is rounded to four decimal places.
+pub struct LengthStats {
+    min_len: usize,
+    max_len: usize,
+    total_len: usize,
+    num: usize,
+}
+
+impl Accumulate<String, String> for LengthStats {
+    fn new(item: String) -> LengthStats {
+        let len = item.chars().count();
+        LengthStats {
+            min_len: len,
+            max_len: len,
+            total_len: len,
+            num: 1,
+        }
+    }
+
+    fn update(&mut self, item: String) {
+        let len = item.chars().count();
+        self.min_len = self.min_len.min(len);
+        self.max_len = self.max_len.max(len);
+        self.total_len += len;
+        self.num += 1;
+    }
+
+    fn merge(&mut self, other: LengthStats) {
+        self.min_len = self.min_len.min(other.min_len);
+        self.max_len = self.max_len.max(other.max_len);
+        self.total_len += other.total_len;
+        self.num += other.num;
+    }
+
+    fn compute(&self) -> Option<String> {
+        let mean = Decimal::from(self.total_len) / Decimal::from(self.num);
+        Some(format!(
+            "{}{}{} ({})",
+            self.min_len,
+            DEFAULT_MINMAX_SEPARATOR,
+            self.max_len,
+            mean.round_dp(4)
+        ))
+    }
+}
+
+/// The fraction of values that are true, like a conversion rate. The fraction is rounded to
+/// four decimal places, or displayed as a percentage rounded to two decimal places (like `62.5%`)
+/// with `Rate::with_percent`.
+pub struct Rate {
+    trues: usize,
+    total: usize,
+    percent: bool,
+}
+
+impl Rate {
+    /// Creates a new `Rate` that displays the rate as a percentage if `percent` is true.
+    pub fn with_percent(item: BooleanWrapper, percent: bool) -> Rate {
+        Rate {
+            trues: item.0 as usize,
+            total: 1,
+            percent,
+        }
+    }
+}
+
+impl Accumulate<BooleanWrapper, String> for Rate {
+    fn new(item: BooleanWrapper) -> Rate {
+        Rate::with_percent(item, false)
+    }
+
+    fn update(&mut self, item: BooleanWrapper) {
+        self.trues += item.0 as usize;
+        self.total += 1;
+    }
+
+    fn merge(&mut self, other: Rate) {
+        self.trues += other.trues;
+        self.total += other.total;
+    }
+
+    fn compute(&self) -> Option<String> {
+        if self.total == 0 {
+            return None;
+        }
+        let rate = Decimal::from(self.trues) / Decimal::from(self.total);
+        Some(match self.percent {
+            true => format!("{}%", (rate * Decimal::from(100)).round_dp(2).normalize()),
+            false => rate.round_dp(4).normalize().to_string(),
+        })
+    }
+}
+
 /// The largest value (or the value that would appear last in a sorted array)
 pub struct Maximum<I>(I);
 
@@ -86,6 +413,10 @@ where
         }
     }
 
+    fn merge(&mut self, other: Maximum<I>) {
+        self.update(other.0);
+    }
+
     fn compute(&self) -> Option<I> {
         Some(self.0.clone())
     }
@@ -111,6 +442,11 @@ impl Accumulate<DecimalWrapper, DecimalWrapper> for Mean {
         self.running_count += 1;
     }
 
+    fn merge(&mut self, other: Mean) {
+        self.running_sum.item += other.running_sum.item;
+        self.running_count += other.running_count;
+    }
+
     fn compute(&self) -> Option<DecimalWrapper> {
         let decimal_count = Decimal::new(self.running_count as i64, 0);
         let result = self.running_sum.item / decimal_count;
@@ -144,23 +480,30 @@ impl Accumulate<DecimalWrapper, DecimalWrapper> for Median {
         self.num += 1;
     }
 
+    fn merge(&mut self, other: Median) {
+        for (item, count) in other.values {
+            *self.values.entry(item).or_insert(0) += count;
+        }
+        self.num += other.num;
+    }
+
     fn compute(&self) -> Option<DecimalWrapper> {
-        let mut cur_count = 0;
+        let mut cur_count: usize = 0;
         let mut cur_val = DecimalWrapper {
             item: Decimal::new(0, 0),
         };
-        // creating an iter bc we're stopping at N/2
+        let half = self.num / 2;
+        // creating an iter bc we're stopping at N/2 (rounded up), using integers so
+        // this stays exact for any number of records
         let mut iter = self.values.iter();
-        while (cur_count as f64) < (self.num as f64 / 2.) {
+        while cur_count < self.num - half {
             // should break before iter.next().is_none()
             let (result, count) = iter.next().unwrap();
             cur_count += count;
             cur_val = *result;
         }
         // -- take the mean if we have an even number of records and end at *exactly* the midpoint.
-        if (self.num % 2) == 0
-            && ((cur_count as f64) - (self.num as f64 / 2.)).abs() < std::f64::EPSILON
-        {
+        if self.num.is_multiple_of(2) && cur_count == half {
             // iter.next() will always be Some(_) because this is always initialized with
             let median = (cur_val + *iter.next().unwrap().0)
                 / DecimalWrapper {
@@ -191,30 +534,214 @@ where
         }
     }
 
+    fn merge(&mut self, other: Minimum<I>) {
+        self.update(other.0);
+    }
+
     fn compute(&self) -> Option<I> {
         Some(self.0.clone())
     }
 }
 
+/// Separates a value from the value of the picked field in the strings `Picked` parses.
+pub const PICK_SEPARATOR: &str = "_<pick>_";
+
+/// A value along with the value of another field (the "pick" field) in the same record.
+/// This is the input of `MaxBy` and `MinBy`, parsed from the value and the picked value
+/// joined by `PICK_SEPARATOR`.
+pub struct Picked<I> {
+    pub value: I,
+    pub pick: String,
+}
+
+impl<I: std::str::FromStr> std::str::FromStr for Picked<I> {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Picked::parse_with(s, str::parse).ok_or(())
+    }
+}
+
+impl<I> Picked<I> {
+    /// Parses a value joined to the value of the pick field, parsing the value with `parse_value`
+    /// instead of `FromStr`
+    pub fn parse_with<F, E>(s: &str, parse_value: F) -> Option<Picked<I>>
+    where
+        F: Fn(&str) -> Result<I, E>,
+    {
+        let (value, pick) = s.split_once(PICK_SEPARATOR)?;
+        Some(Picked {
+            value: parse_value(value).ok()?,
+            pick: pick.to_string(),
+        })
+    }
+}
+
+/// The value of the pick field in the record with the maximum value (an argmax).
+/// Ties go to the first record.
+pub struct MaxBy<I>(Picked<I>);
+
+impl<I: std::cmp::PartialOrd> Accumulate<Picked<I>, String> for MaxBy<I> {
+    fn new(item: Picked<I>) -> MaxBy<I> {
+        MaxBy(item)
+    }
+
+    fn update(&mut self, item: Picked<I>) {
+        if item.value > self.0.value {
+            self.0 = item;
+        }
+    }
+
+    fn merge(&mut self, other: MaxBy<I>) {
+        self.update(other.0);
+    }
+
+    fn compute(&self) -> Option<String> {
+        Some(self.0.pick.clone())
+    }
+}
+
+/// The value of the pick field in the record with the minimum value (an argmin).
+/// Ties go to the first record.
+pub struct MinBy<I>(Picked<I>);
+
+impl<I: std::cmp::PartialOrd> Accumulate<Picked<I>, String> for MinBy<I> {
+    fn new(item: Picked<I>) -> MinBy<I> {
+        MinBy(item)
+    }
+
+    fn update(&mut self, item: Picked<I>) {
+        if item.value < self.0.value {
+            self.0 = item;
+        }
+    }
+
+    fn merge(&mut self, other: MinBy<I>) {
+        self.update(other.0);
+    }
+
+    fn compute(&self) -> Option<String> {
+        Some(self.0.pick.clone())
+    }
+}
+
+/// The first value, in input order. With a `Picked` input (from `--order-by`), this is instead
+/// the value of the picked field in the record with the smallest order key, with ties going to
+/// the first of the tied records.
+pub struct First<I>(I);
+
+impl Accumulate<String, String> for First<String> {
+    fn new(item: String) -> First<String> {
+        First(item)
+    }
+
+    fn update(&mut self, _item: String) {}
+
+    fn merge(&mut self, _other: First<String>) {}
+
+    fn compute(&self) -> Option<String> {
+        Some(self.0.clone())
+    }
+}
+
+impl<I: std::cmp::PartialOrd> Accumulate<Picked<I>, String> for First<Picked<I>> {
+    fn new(item: Picked<I>) -> First<Picked<I>> {
+        First(item)
+    }
+
+    fn update(&mut self, item: Picked<I>) {
+        if item.value < self.0.value {
+            self.0 = item;
+        }
+    }
+
+    fn merge(&mut self, other: First<Picked<I>>) {
+        self.update(other.0);
+    }
+
+    fn compute(&self) -> Option<String> {
+        Some(self.0.pick.clone())
+    }
+}
+
+/// The last value, in input order. With a `Picked` input (from `--order-by`), this is instead
+/// the value of the picked field in the record with the largest order key, with ties going to
+/// the last of the tied records.
+pub struct Last<I>(I);
+
+impl Accumulate<String, String> for Last<String> {
+    fn new(item: String) -> Last<String> {
+        Last(item)
+    }
+
+    fn update(&mut self, item: String) {
+        self.0 = item;
+    }
+
+    fn merge(&mut self, other: Last<String>) {
+        self.0 = other.0;
+    }
+
+    fn compute(&self) -> Option<String> {
+        Some(self.0.clone())
+    }
+}
+
+impl<I: std::cmp::PartialOrd> Accumulate<Picked<I>, String> for Last<Picked<I>> {
+    fn new(item: Picked<I>) -> Last<Picked<I>> {
+        Last(item)
+    }
+
+    fn update(&mut self, item: Picked<I>) {
+        if item.value >= self.0.value {
+            self.0 = item;
+        }
+    }
+
+    fn merge(&mut self, other: Last<Picked<I>>) {
+        self.update(other.0);
+    }
+
+    fn compute(&self) -> Option<String> {
+        Some(self.0.pick.clone())
+    }
+}
+
+/// The separator `MinMax` uses by default.
+pub const DEFAULT_MINMAX_SEPARATOR: &str = " - ";
+
 /// A combination of the minimum and maximum values, producing a string concatenating
-/// the minimum value and the maximum value together, separated by a hyphen.
+/// the minimum value and the maximum value together, separated by a hyphen
+/// (or by a custom separator, using `MinMax::with_separator`).
 pub struct MinMax<I> {
     max_val: I,
     min_val: I,
+    separator: String,
 }
 
-impl<I> Accumulate<I, String> for MinMax<I>
+impl<I> MinMax<I>
 where
-    I: std::fmt::Display,
-    I: std::cmp::PartialOrd,
     I: std::clone::Clone,
 {
-    fn new(item: I) -> MinMax<I> {
+    /// Creates a new `MinMax` that separates the minimum and maximum values with `separator`.
+    pub fn with_separator(item: I, separator: &str) -> MinMax<I> {
         MinMax {
             min_val: item.clone(),
             max_val: item,
+            separator: separator.to_string(),
         }
     }
+}
+
+impl<I> Accumulate<I, String> for MinMax<I>
+where
+    I: std::fmt::Display,
+    I: std::cmp::PartialOrd,
+    I: std::clone::Clone,
+{
+    fn new(item: I) -> MinMax<I> {
+        MinMax::with_separator(item, DEFAULT_MINMAX_SEPARATOR)
+    }
 
     fn update(&mut self, item: I) {
         if self.min_val > item {
@@ -224,8 +751,144 @@ where
         }
     }
 
+    fn merge(&mut self, other: MinMax<I>) {
+        if self.min_val > other.min_val {
+            self.min_val = other.min_val;
+        }
+        if self.max_val < other.max_val {
+            self.max_val = other.max_val;
+        }
+    }
+
     fn compute(&self) -> Option<String> {
-        Some(format!("{} - {}", self.min_val, self.max_val))
+        Some(format!(
+            "{}{}{}",
+            self.min_val, self.separator, self.max_val
+        ))
+    }
+}
+
+/// The separator `Sample` uses between the sampled values.
+pub const SAMPLE_SEPARATOR: &str = "; ";
+
+/// The number of items that match a regular expression. Items that don't match aren't counted.
+///
+/// `CountIf::new` doesn't have a pattern, so it counts every item, like `Count`.
+pub struct CountIf {
+    pattern: Option<Regex>,
+    count: usize,
+}
+
+impl CountIf {
+    /// Creates a new `CountIf` that counts the items matching `pattern`.
+    pub fn with_pattern(item: String, pattern: Regex) -> CountIf {
+        let mut count_if = CountIf {
+            pattern: Some(pattern),
+            count: 0,
+        };
+        count_if.update(item);
+        count_if
+    }
+}
+
+impl Accumulate<String, usize> for CountIf {
+    fn new(item: String) -> CountIf {
+        let mut count_if = CountIf {
+            pattern: None,
+            count: 0,
+        };
+        count_if.update(item);
+        count_if
+    }
+
+    fn update(&mut self, item: String) {
+        let matches = match &self.pattern {
+            Some(re) => re.is_match(&item),
+            None => true,
+        };
+        if matches {
+            self.count += 1;
+        }
+    }
+
+    fn merge(&mut self, other: CountIf) {
+        self.count += other.count;
+    }
+
+    fn compute(&self) -> Option<usize> {
+        Some(self.count)
+    }
+}
+
+/// A random sample of up to `k` of the items (in the order they were sampled), separated by semicolons.
+///
+/// This uses reservoir sampling, so it only stores `k` items regardless of how many
+/// items there are. `Sample::new` takes a single item, with a random seed.
+pub struct Sample {
+    reservoir: Vec<String>,
+    num_seen: usize,
+    k: usize,
+    rng: StdRng,
+}
+
+impl Sample {
+    /// Creates a new `Sample` of up to `k` items. If there's a `seed`, every sample with
+    /// the same seed and the same items picks the same items.
+    pub fn with_config(item: String, k: usize, seed: Option<u64>) -> Sample {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let mut sample = Sample {
+            reservoir: Vec::with_capacity(k),
+            num_seen: 0,
+            k,
+            rng,
+        };
+        sample.update(item);
+        sample
+    }
+}
+
+impl Accumulate<String, String> for Sample {
+    fn new(item: String) -> Sample {
+        Sample::with_config(item, 1, None)
+    }
+
+    fn update(&mut self, item: String) {
+        self.num_seen += 1;
+        if self.reservoir.len() < self.k {
+            self.reservoir.push(item);
+        } else {
+            // each item replaces one in the reservoir with a probability of k / num_seen
+            let idx = self.rng.gen_range(0, self.num_seen);
+            if idx < self.k {
+                self.reservoir[idx] = item;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Sample) {
+        // draws from each reservoir in proportion to the number of items it stands for
+        let mut mine = std::mem::take(&mut self.reservoir);
+        let mut theirs = other.reservoir;
+        let (mut my_seen, mut their_seen) = (self.num_seen, other.num_seen);
+        while self.reservoir.len() < self.k && !(mine.is_empty() && theirs.is_empty()) {
+            let pool = if self.rng.gen_range(0, my_seen + their_seen) < my_seen {
+                my_seen -= 1;
+                &mut mine
+            } else {
+                their_seen -= 1;
+                &mut theirs
+            };
+            let idx = self.rng.gen_range(0, pool.len());
+            self.reservoir.push(pool.swap_remove(idx));
+        }
+        self.num_seen += other.num_seen;
+    }
+
+    fn compute(&self) -> Option<String> {
+        Some(self.reservoir.join(SAMPLE_SEPARATOR))
     }
 }
 
@@ -234,29 +897,92 @@ where
 /// If there is more than one mode, it returns
 /// the item that reached the maximum value first. So in the case of
 /// ["a", "b", "b", "a"], it will return "b" because "b" was the first
-/// value to appear twice.
+/// value to appear twice. After `merge`, the order values reached the maximum count in is only
+/// partly known, so the ties can be broken differently.
 pub struct Mode<I> {
     histogram: HashMap<I, usize>,
     max_count: usize,
     max_val: I,
+    /// The last value to reach `max_count`
+    last_val: I,
+    tiebreak: Tiebreak,
 }
 
-impl<I> Accumulate<I, I> for Mode<I>
+/// The ways `Mode` can choose between values that appear equally often.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tiebreak {
+    /// The first value to reach the highest count (the default)
+    First,
+    /// The last value to reach the highest count
+    Last,
+    /// The smallest of the most common values
+    Min,
+    /// The largest of the most common values
+    Max,
+}
+
+impl std::str::FromStr for Tiebreak {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "first" => Ok(Tiebreak::First),
+            "last" => Ok(Tiebreak::Last),
+            "min" => Ok(Tiebreak::Min),
+            "max" => Ok(Tiebreak::Max),
+            _ => Err(format!("`{}` is not a valid tiebreak", s)),
+        }
+    }
+}
+
+impl<I> Mode<I>
 where
     I: std::cmp::PartialOrd,
     I: std::cmp::Eq,
     I: std::hash::Hash,
     I: std::clone::Clone,
 {
-    fn new(item: I) -> Mode<I> {
+    /// Creates a new `Mode` that breaks ties between the most common values with `tiebreak`.
+    pub fn with_tiebreak(item: I, tiebreak: Tiebreak) -> Mode<I> {
         let mut histogram = HashMap::new();
         let max_val = item.clone();
+        let last_val = item.clone();
         histogram.insert(item, 1);
         Mode {
             histogram,
             max_count: 1,
             max_val,
+            last_val,
+            tiebreak,
+        }
+    }
+
+    /// Finds the smallest (or, if `largest` is true, the largest) value at `max_count`.
+    fn extreme_mode(&self, largest: bool) -> Option<I> {
+        let mut modes = self
+            .histogram
+            .iter()
+            .filter(|(_, count)| **count == self.max_count)
+            .map(|(val, _)| val);
+        let mut extreme = modes.next()?;
+        for val in modes {
+            if (largest && val > extreme) || (!largest && val < extreme) {
+                extreme = val;
+            }
         }
+        Some(extreme.clone())
+    }
+}
+
+impl<I> Accumulate<I, I> for Mode<I>
+where
+    I: std::cmp::PartialOrd,
+    I: std::cmp::Eq,
+    I: std::hash::Hash,
+    I: std::clone::Clone,
+{
+    fn new(item: I) -> Mode<I> {
+        Mode::with_tiebreak(item, Tiebreak::First)
     }
 
     fn update(&mut self, item: I) {
@@ -266,11 +992,97 @@ where
             self.max_count = new_count;
             self.max_val = item.clone();
         }
+        if new_count >= self.max_count {
+            self.last_val = item.clone();
+        }
         *self.histogram.entry(item).or_insert(0) += 1;
     }
 
+    fn merge(&mut self, other: Mode<I>) {
+        for (item, count) in other.histogram {
+            *self.histogram.entry(item).or_insert(0) += count;
+        }
+        self.max_count = self.histogram.values().copied().max().unwrap_or(0);
+        // the order the values reached the highest count in is lost, so the first and last modes
+        // come from each accumulator's own first and last modes when they can
+        if self.histogram[&self.max_val] < self.max_count {
+            self.max_val = if self.histogram[&other.max_val] == self.max_count {
+                other.max_val
+            } else {
+                self.extreme_mode(false).unwrap()
+            };
+        }
+        if self.histogram[&other.last_val] == self.max_count {
+            self.last_val = other.last_val;
+        } else if self.histogram[&self.last_val] < self.max_count {
+            self.last_val = self.extreme_mode(true).unwrap();
+        }
+    }
+
     fn compute(&self) -> Option<I> {
-        Some(self.max_val.clone())
+        match self.tiebreak {
+            Tiebreak::First => Some(self.max_val.clone()),
+            Tiebreak::Last => Some(self.last_val.clone()),
+            Tiebreak::Min => self.extreme_mode(false),
+            Tiebreak::Max => self.extreme_mode(true),
+        }
+    }
+}
+
+/// The percentile rank of a target value: the fraction of the values that are less than or
+/// equal to it (the empirical CDF at that value), like `0.75` for a value that's larger than or equal to
+/// three quarters of the values.
+///
+/// Like `Median`, this stores the distribution of the values in a `BTreeMap`, so `rank_of` can
+/// find the rank of any value. `PercentileRank::new` doesn't have a target, so its `compute` returns `None`.
+pub struct PercentileRank {
+    values: BTreeMap<DecimalWrapper, usize>,
+    num: usize,
+    target: Option<DecimalWrapper>,
+}
+
+impl PercentileRank {
+    /// Creates a new `PercentileRank` that computes the rank of `target`.
+    pub fn with_target(item: DecimalWrapper, target: DecimalWrapper) -> PercentileRank {
+        let mut rank = PercentileRank::new(item);
+        rank.target = Some(target);
+        rank
+    }
+
+    /// Returns the fraction of the values that are less than or equal to `target`
+    pub fn rank_of(&self, target: DecimalWrapper) -> DecimalWrapper {
+        let at_most: usize = self.values.range(..=target).map(|(_, count)| count).sum();
+        DecimalWrapper {
+            item: Decimal::from(at_most) / Decimal::from(self.num),
+        }
+    }
+}
+
+impl Accumulate<DecimalWrapper, DecimalWrapper> for PercentileRank {
+    fn new(item: DecimalWrapper) -> PercentileRank {
+        let mut values = BTreeMap::new();
+        values.insert(item, 1);
+        PercentileRank {
+            values,
+            num: 1,
+            target: None,
+        }
+    }
+
+    fn update(&mut self, item: DecimalWrapper) {
+        *self.values.entry(item).or_insert(0) += 1;
+        self.num += 1;
+    }
+
+    fn merge(&mut self, other: PercentileRank) {
+        for (item, count) in other.values {
+            *self.values.entry(item).or_insert(0) += count;
+        }
+        self.num += other.num;
+    }
+
+    fn compute(&self) -> Option<DecimalWrapper> {
+        Some(self.rank_of(self.target?))
     }
 }
 
@@ -305,14 +1117,51 @@ where
         }
     }
 
+    fn merge(&mut self, other: Range<I, O>) {
+        self.update(other.min_val);
+        self.update(other.max_val);
+    }
+
     fn compute(&self) -> Option<O> {
         Some(self.max_val - self.min_val)
     }
 }
 
+/// The root mean square (or quadratic mean) of a stream of values.
+///
+/// Like `SumSquares`, this uses floating point arithmetic, so extremely large values
+/// (with magnitudes above roughly `1e154`) will overflow while being squared and produce `inf`.
+pub struct Rms {
+    sum_squares: f64,
+    num_records: f64,
+}
+
+impl Accumulate<f64, f64> for Rms {
+    fn new(item: f64) -> Rms {
+        Rms {
+            sum_squares: item * item,
+            num_records: 1.,
+        }
+    }
+
+    fn update(&mut self, item: f64) {
+        self.sum_squares += item * item;
+        self.num_records += 1.;
+    }
+
+    fn merge(&mut self, other: Rms) {
+        self.sum_squares += other.sum_squares;
+        self.num_records += other.num_records;
+    }
+
+    fn compute(&self) -> Option<f64> {
+        Some((self.sum_squares / self.num_records).sqrt())
+    }
+}
+
 /// Computes the *sample* variance in a single pass, using
 /// [Welford's algorithm](https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Welford's_online_algorithm).
-
+///
 /// The attributes in this method refer to the same ones described in
 /// *Accuracy and Stability of Numerical Algorithms* by Higham (2nd Edition, page 11).
 pub struct StdDev {
@@ -340,6 +1189,15 @@ impl Accumulate<f64, f64> for StdDev {
         self.m += (item - self.m) / self.num_records;
     }
 
+    fn merge(&mut self, other: StdDev) {
+        // combines the two with the pairwise formula from Chan, Golub and LeVeque
+        let num_records = self.num_records + other.num_records;
+        let delta = other.m - self.m;
+        self.q += other.q + delta.powi(2) * self.num_records * other.num_records / num_records;
+        self.m += delta * other.num_records / num_records;
+        self.num_records = num_records;
+    }
+
     fn compute(&self) -> Option<f64> {
         if self.num_records <= 1. {
             return None;
@@ -365,11 +1223,241 @@ where
         self.0 += item;
     }
 
+    fn merge(&mut self, other: Sum<I>) {
+        self.0 += other.0;
+    }
+
     fn compute(&self) -> Option<I> {
         Some(self.0)
     }
 }
 
+/// The sum of the squares of a stream of values.
+///
+/// This uses `f64` rather than `DecimalWrapper` because squaring values quickly exceeds the
+/// range of `Decimal`. The tradeoff is that values with magnitudes above roughly `1e154`
+/// overflow when they're squared, in which case the result is `inf`.
+pub struct SumSquares(f64);
+
+impl Accumulate<f64, f64> for SumSquares {
+    fn new(item: f64) -> SumSquares {
+        SumSquares(item * item)
+    }
+
+    fn update(&mut self, item: f64) {
+        self.0 += item * item;
+    }
+
+    fn merge(&mut self, other: SumSquares) {
+        self.0 += other.0;
+    }
+
+    fn compute(&self) -> Option<f64> {
+        Some(self.0)
+    }
+}
+
+/// The functions `AnyAccumulator` can compute, which are the numeric functions that don't need
+/// any configuration, along with `count`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Count,
+    Max,
+    Mean,
+    Median,
+    Min,
+    Range,
+    Rms,
+    StdDev,
+    Sum,
+    SumSquares,
+}
+
+impl Function {
+    /// The names of the functions, which are the same as the names of the functions on the command line
+    pub const NAMES: [&'static str; 10] = [
+        "count", "max", "mean", "median", "min", "range", "rms", "stddev", "sum", "sumsq",
+    ];
+}
+
+impl std::str::FromStr for Function {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(Function::Count),
+            "max" => Ok(Function::Max),
+            "mean" => Ok(Function::Mean),
+            "median" => Ok(Function::Median),
+            "min" => Ok(Function::Min),
+            "range" => Ok(Function::Range),
+            "rms" => Ok(Function::Rms),
+            "stddev" => Ok(Function::StdDev),
+            "sum" => Ok(Function::Sum),
+            "sumsq" => Ok(Function::SumSquares),
+            _ => Err(format!(
+                "`{}` isn't one of the functions that can be combined ({})",
+                s,
+                Function::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Function::Count => "count",
+            Function::Max => "max",
+            Function::Mean => "mean",
+            Function::Median => "median",
+            Function::Min => "min",
+            Function::Range => "range",
+            Function::Rms => "rms",
+            Function::StdDev => "stddev",
+            Function::Sum => "sum",
+            Function::SumSquares => "sumsq",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The result of an `AnyAccumulator`: a count, an exact decimal, or (for the functions that
+/// use `f64`, like `StdDev`) a floating point number.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnyOutput {
+    Count(usize),
+    Decimal(Decimal),
+    Float(f64),
+}
+
+impl std::fmt::Display for AnyOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnyOutput::Count(count) => write!(f, "{}", count),
+            AnyOutput::Decimal(value) => write!(f, "{}", value),
+            AnyOutput::Float(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Any of the accumulators for a `Function`, chosen when the program runs rather than when it's compiled,
+/// so a single pivot table can hold the results of several functions.
+///
+/// The values are all `DecimalWrapper`s, which the functions that use `f64` convert.
+/// `AnyAccumulator::new` doesn't have a function, so it counts the values, like `Count`.
+pub enum AnyAccumulator {
+    Count(Count<DecimalWrapper>),
+    Max(Maximum<DecimalWrapper>),
+    Mean(Mean),
+    Median(Median),
+    Min(Minimum<DecimalWrapper>),
+    Range(Range<DecimalWrapper, Decimal>),
+    Rms(Rms),
+    StdDev(StdDev),
+    Sum(Sum<DecimalWrapper>),
+    SumSquares(SumSquares),
+}
+
+fn to_float(item: DecimalWrapper) -> f64 {
+    item.item.to_f64().unwrap_or(f64::NAN)
+}
+
+impl AnyAccumulator {
+    /// Creates an accumulator that computes `function`, for aggregating several functions at once
+    /// with `Aggregator::set_functions`.
+    pub fn with_function(item: DecimalWrapper, function: Function) -> AnyAccumulator {
+        match function {
+            Function::Count => AnyAccumulator::Count(Count::new(item)),
+            Function::Max => AnyAccumulator::Max(Maximum::new(item)),
+            Function::Mean => AnyAccumulator::Mean(Mean::new(item)),
+            Function::Median => AnyAccumulator::Median(Median::new(item)),
+            Function::Min => AnyAccumulator::Min(Minimum::new(item)),
+            Function::Range => AnyAccumulator::Range(Range::new(item)),
+            Function::Rms => AnyAccumulator::Rms(Rms::new(to_float(item))),
+            Function::StdDev => AnyAccumulator::StdDev(StdDev::new(to_float(item))),
+            Function::Sum => AnyAccumulator::Sum(Sum::new(item)),
+            Function::SumSquares => AnyAccumulator::SumSquares(SumSquares::new(to_float(item))),
+        }
+    }
+
+    /// The function this accumulator computes
+    fn function(&self) -> Function {
+        match self {
+            AnyAccumulator::Count(_) => Function::Count,
+            AnyAccumulator::Max(_) => Function::Max,
+            AnyAccumulator::Mean(_) => Function::Mean,
+            AnyAccumulator::Median(_) => Function::Median,
+            AnyAccumulator::Min(_) => Function::Min,
+            AnyAccumulator::Range(_) => Function::Range,
+            AnyAccumulator::Rms(_) => Function::Rms,
+            AnyAccumulator::StdDev(_) => Function::StdDev,
+            AnyAccumulator::Sum(_) => Function::Sum,
+            AnyAccumulator::SumSquares(_) => Function::SumSquares,
+        }
+    }
+}
+
+impl Accumulate<DecimalWrapper, AnyOutput> for AnyAccumulator {
+    fn new(item: DecimalWrapper) -> AnyAccumulator {
+        AnyAccumulator::with_function(item, Function::Count)
+    }
+
+    fn update(&mut self, item: DecimalWrapper) {
+        match self {
+            AnyAccumulator::Count(acc) => acc.update(item),
+            AnyAccumulator::Max(acc) => acc.update(item),
+            AnyAccumulator::Mean(acc) => acc.update(item),
+            AnyAccumulator::Median(acc) => acc.update(item),
+            AnyAccumulator::Min(acc) => acc.update(item),
+            AnyAccumulator::Range(acc) => acc.update(item),
+            AnyAccumulator::Rms(acc) => acc.update(to_float(item)),
+            AnyAccumulator::StdDev(acc) => acc.update(to_float(item)),
+            AnyAccumulator::Sum(acc) => acc.update(item),
+            AnyAccumulator::SumSquares(acc) => acc.update(to_float(item)),
+        }
+    }
+
+    /// Merges accumulators for the same function. (`Aggregator::set_functions` gives each function
+    /// its own cells, so it never merges different functions, and this panics if it's asked to.)
+    fn merge(&mut self, other: AnyAccumulator) {
+        match (self, other) {
+            (AnyAccumulator::Count(acc), AnyAccumulator::Count(other)) => acc.merge(other),
+            (AnyAccumulator::Max(acc), AnyAccumulator::Max(other)) => acc.merge(other),
+            (AnyAccumulator::Mean(acc), AnyAccumulator::Mean(other)) => acc.merge(other),
+            (AnyAccumulator::Median(acc), AnyAccumulator::Median(other)) => acc.merge(other),
+            (AnyAccumulator::Min(acc), AnyAccumulator::Min(other)) => acc.merge(other),
+            (AnyAccumulator::Range(acc), AnyAccumulator::Range(other)) => acc.merge(other),
+            (AnyAccumulator::Rms(acc), AnyAccumulator::Rms(other)) => acc.merge(other),
+            (AnyAccumulator::StdDev(acc), AnyAccumulator::StdDev(other)) => acc.merge(other),
+            (AnyAccumulator::Sum(acc), AnyAccumulator::Sum(other)) => acc.merge(other),
+            (AnyAccumulator::SumSquares(acc), AnyAccumulator::SumSquares(other)) => {
+                acc.merge(other)
+            }
+            (acc, other) => unreachable!(
+                "Can't merge the accumulators of two different functions ({} and {})",
+                acc.function(),
+                other.function()
+            ),
+        }
+    }
+
+    fn compute(&self) -> Option<AnyOutput> {
+        match self {
+            AnyAccumulator::Count(acc) => acc.compute().map(AnyOutput::Count),
+            AnyAccumulator::Max(acc) => acc.compute().map(|v| AnyOutput::Decimal(v.item)),
+            AnyAccumulator::Mean(acc) => acc.compute().map(|v| AnyOutput::Decimal(v.item)),
+            AnyAccumulator::Median(acc) => acc.compute().map(|v| AnyOutput::Decimal(v.item)),
+            AnyAccumulator::Min(acc) => acc.compute().map(|v| AnyOutput::Decimal(v.item)),
+            AnyAccumulator::Range(acc) => acc.compute().map(AnyOutput::Decimal),
+            AnyAccumulator::Rms(acc) => acc.compute().map(AnyOutput::Float),
+            AnyAccumulator::StdDev(acc) => acc.compute().map(AnyOutput::Float),
+            AnyAccumulator::Sum(acc) => acc.compute().map(|v| AnyOutput::Decimal(v.item)),
+            AnyAccumulator::SumSquares(acc) => acc.compute().map(AnyOutput::Float),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -408,7 +1496,7 @@ mod tests {
     fn test_max_dates() {
         // there's probably a better way of handling this, but this uses the same
         // code as parsing does, so shouldn't affect either
-        let _ex = parsing::set_date_format("%Y-%m-%d %H:%M:%S".to_string());
+        parsing::set_date_format("%Y-%m-%d %H:%M:%S".to_string());
         let date_updates = vec![
             "2019-02-03 12:23:10",
             "2020-01-03 13:45:02",
@@ -453,7 +1541,7 @@ mod tests {
     fn test_min_dates() {
         // there's probably a better way of handling this, but this uses the same
         // code as parsing does, so shouldn't affect either
-        let _ex = parsing::set_date_format("%Y-%m-%d %H:%M:%S".to_string());
+        parsing::set_date_format("%Y-%m-%d %H:%M:%S".to_string());
         let date_updates = vec![
             "2019-02-03 12:23:10",
             "2020-01-03 13:45:02",
@@ -500,11 +1588,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_minmax_separator() {
+        let mut minmax = MinMax::with_separator(3, "..");
+        minmax.update(-1);
+        minmax.update(10);
+        assert_eq!(minmax.compute(), Some("-1..10".to_string()));
+    }
+
+    #[test]
+    fn test_sample() {
+        let items: Vec<String> = (0..100).map(|i| i.to_string()).collect();
+        let sample_items = |k, seed| {
+            let mut sample = Sample::with_config(items[0].clone(), k, seed);
+            for item in &items[1..] {
+                sample.update(item.clone());
+            }
+            sample.compute().unwrap()
+        };
+        // small groups keep every item
+        let mut small = Sample::with_config("a".to_string(), 3, None);
+        small.update("b".to_string());
+        assert_eq!(small.compute(), Some("a; b".to_string()));
+        let sampled = sample_items(5, Some(42));
+        assert_eq!(sampled.split(SAMPLE_SEPARATOR).count(), 5);
+        assert!(sampled
+            .split(SAMPLE_SEPARATOR)
+            .all(|item| items.contains(&item.to_string())));
+        assert_eq!(sampled, sample_items(5, Some(42)));
+    }
+
     #[test]
     fn test_minmax_dates() {
         // there's probably a better way of handling this, but this uses the same
         // code as parsing does, so shouldn't affect either
-        let _ex = parsing::set_date_format("%Y-%m-%d %H:%M:%S".to_string());
+        parsing::set_date_format("%Y-%m-%d %H:%M:%S".to_string());
         let date_updates = vec![
             "2019-02-03 12:23:10",
             "2020-01-03 13:45:02",
@@ -540,7 +1658,7 @@ mod tests {
     fn test_range_dates() {
         // there's probably a better way of handling this, but this uses the same
         // code as parsing does, so shouldn't affect either
-        let _ex = parsing::set_date_format("%Y-%m-%d %H:%M:%S".to_string());
+        parsing::set_date_format("%Y-%m-%d %H:%M:%S".to_string());
         let date_updates = vec![
             "2019-02-03 00:00:00",
             "2020-01-03 12:00:00",
@@ -555,6 +1673,96 @@ mod tests {
         assert_eq!(date_vals.compute().unwrap(), 731.25);
     }
 
+    #[test]
+    fn test_count_if() {
+        let pattern = Regex::new("^err").unwrap();
+        let mut count_if = CountIf::with_pattern("ok".to_string(), pattern);
+        assert_eq!(count_if.compute(), Some(0));
+        for item in &["error", "an error", "err"] {
+            count_if.update(item.to_string());
+        }
+        assert_eq!(count_if.compute(), Some(2));
+    }
+
+    #[test]
+    fn test_cardinality() {
+        let mut cardinality = Cardinality::new("a");
+        assert_eq!(cardinality.compute(), Some("1 (1)".to_string()));
+        cardinality.update("b");
+        cardinality.update("a");
+        assert_eq!(cardinality.compute(), Some("2 (0.6667)".to_string()));
+        cardinality.update("a");
+        assert_eq!(cardinality.compute(), Some("2 (0.5)".to_string()));
+    }
+
+    #[test]
+    fn test_rate() {
+        let mut rate = Rate::new(BooleanWrapper(true));
+        assert_eq!(rate.compute(), Some("1".to_string()));
+        rate.update(BooleanWrapper(false));
+        rate.update(BooleanWrapper(false));
+        assert_eq!(rate.compute(), Some("0.3333".to_string()));
+        let mut percent = Rate::with_percent(BooleanWrapper(false), true);
+        for value in &[true, true, false, true, false, true, true] {
+            percent.update(BooleanWrapper(*value));
+        }
+        assert_eq!(percent.compute(), Some("62.5%".to_string()));
+    }
+
+    #[test]
+    fn test_length_stats() {
+        let mut lengths = LengthStats::new("café".to_string());
+        assert_eq!(lengths.compute(), Some("4 - 4 (4)".to_string()));
+        lengths.update("日本".to_string());
+        lengths.update("".to_string());
+        lengths.update("naïve".to_string());
+        assert_eq!(lengths.compute(), Some("0 - 5 (2.75)".to_string()));
+    }
+
+    #[test]
+    fn test_max_by_and_min_by() {
+        let picked = |value: f64, pick: &str| Picked {
+            value,
+            pick: pick.to_string(),
+        };
+        let mut max_by = MaxBy::new(picked(5., "a"));
+        let mut min_by = MinBy::new(picked(5., "a"));
+        for (value, pick) in &[(9., "b"), (9., "c"), (2., "d"), (2., "e")] {
+            max_by.update(picked(*value, pick));
+            min_by.update(picked(*value, pick));
+        }
+        // ties go to the first record
+        assert_eq!(max_by.compute(), Some("b".to_string()));
+        assert_eq!(min_by.compute(), Some("d".to_string()));
+        let parsed: Picked<f64> = format!("1.5{}x", PICK_SEPARATOR).parse().unwrap();
+        assert_eq!((parsed.value, parsed.pick.as_str()), (1.5, "x"));
+    }
+
+    #[test]
+    fn test_first_and_last() {
+        let mut first = First::new("a".to_string());
+        let mut last = Last::new("a".to_string());
+        for val in &["b", "c"] {
+            first.update(val.to_string());
+            last.update(val.to_string());
+        }
+        assert_eq!(first.compute(), Some("a".to_string()));
+        assert_eq!(last.compute(), Some("c".to_string()));
+        let picked = |value: f64, pick: &str| Picked {
+            value,
+            pick: pick.to_string(),
+        };
+        let mut first_by = First::new(picked(5., "a"));
+        let mut last_by = Last::new(picked(5., "a"));
+        for (value, pick) in &[(9., "b"), (9., "c"), (2., "d"), (2., "e")] {
+            first_by.update(picked(*value, pick));
+            last_by.update(picked(*value, pick));
+        }
+        // ties go to the first record for first and to the last record for last
+        assert_eq!(first_by.compute(), Some("d".to_string()));
+        assert_eq!(last_by.compute(), Some("c".to_string()));
+    }
+
     #[test]
     fn test_median() {
         let dec1: DecimalWrapper = "2".parse().unwrap();
@@ -571,7 +1779,7 @@ mod tests {
         assert_eq!(dec_vals.compute().unwrap().to_string(), "2.5".to_string());
         let mult_middle_vals: DecimalWrapper = "3".parse().unwrap();
         let mut mult_median = Median::new(mult_middle_vals);
-        for val in vec!["5", "6", "1", "4", "3"] {
+        for val in ["5", "6", "1", "4", "3"] {
             mult_median.update(val.parse().unwrap());
         }
         assert_eq!(
@@ -580,6 +1788,116 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_percentile_rank() {
+        let dec = |s: &str| s.parse::<DecimalWrapper>().unwrap();
+        // 1 through 10, with 4 twice
+        let mut rank = PercentileRank::with_target(dec("1"), dec("4"));
+        for val in &["2", "3", "4", "4", "5", "6", "7", "8", "9", "10"] {
+            rank.update(dec(val));
+        }
+        assert_eq!(
+            rank.compute().unwrap().to_string(),
+            "0.4545454545454545454545454545"
+        );
+        assert_eq!(rank.rank_of(dec("0")).to_string(), "0");
+        assert_eq!(rank.rank_of(dec("4.5")), rank.rank_of(dec("4")));
+        assert_eq!(
+            rank.rank_of(dec("3.99")).to_string(),
+            "0.2727272727272727272727272727"
+        );
+        assert_eq!(rank.rank_of(dec("10")).to_string(), "1");
+        assert_eq!(rank.rank_of(dec("1e3")).to_string(), "1");
+        assert!(PercentileRank::new(dec("1")).compute().is_none());
+    }
+
+    #[test]
+    fn test_approx_median_skips_nan() {
+        let mut first_nan = ApproxMedian::new(f64::NAN);
+        assert_eq!(first_nan.compute(), None);
+        first_nan.update(1.);
+        let mut median = ApproxMedian::new(1.);
+        for val in [f64::NAN, 3., f64::INFINITY, 2., 5., f64::NAN, 4., 6., 7.] {
+            median.update(val);
+            first_nan.update(val);
+        }
+        assert_eq!(median.compute(), first_nan.compute());
+        assert!(median.compute().unwrap().is_finite());
+    }
+
+    #[test]
+    fn test_approx_median() {
+        // exact for five or fewer values
+        let mut small = ApproxMedian::new(2.);
+        assert_eq!(small.compute(), Some(2.));
+        for val in [3., 5., 1.] {
+            small.update(val);
+        }
+        assert_eq!(small.compute(), Some(2.5));
+        small.update(10.);
+        assert_eq!(small.compute(), Some(3.));
+        // a shuffled (uniform) permutation of 0..10007, compared to the exact median
+        let vals: Vec<u32> = (0..10007).map(|i| (i * 7919) % 10007).collect();
+        let mut approx = ApproxMedian::new(vals[0] as f64);
+        let mut exact = Median::new(vals[0].to_string().parse().unwrap());
+        for val in &vals[1..] {
+            approx.update(*val as f64);
+            exact.update(val.to_string().parse().unwrap());
+        }
+        let exact: f64 = exact.compute().unwrap().to_string().parse().unwrap();
+        assert_eq!(exact, 5003.);
+        // within 0.5% of the range of the data
+        assert!((approx.compute().unwrap() - exact).abs() < 50.);
+    }
+
+    #[test]
+    fn test_merge() {
+        let vals = [4., 7., 13., 16., -2., 9.5, 0.25, 8.];
+        let accumulate = |vals: &[f64]| {
+            let mut stddev = StdDev::new(vals[0]);
+            for val in &vals[1..] {
+                stddev.update(*val);
+            }
+            stddev
+        };
+        let mut merged = accumulate(&vals[..3]);
+        merged.merge(accumulate(&vals[3..]));
+        let expected = accumulate(&vals).compute().unwrap();
+        assert!((merged.compute().unwrap() - expected).abs() < 1e-12);
+        // the blended markers stay close to the median
+        let vals: Vec<u32> = (0..10007).map(|i| (i * 7919) % 10007).collect();
+        let mut approx = ApproxMedian::new(vals[0] as f64);
+        let mut second = ApproxMedian::new(vals[5000] as f64);
+        for (i, val) in vals.iter().enumerate().skip(1).filter(|(i, _)| *i != 5000) {
+            match i < 5000 {
+                true => approx.update(*val as f64),
+                false => second.update(*val as f64),
+            }
+        }
+        approx.merge(second);
+        assert!((approx.compute().unwrap() - 5003.).abs() < 50.);
+        // the first mode of the first accumulator is still the first mode
+        let mut mode = Mode::new("a");
+        mode.update("b");
+        let mut other = Mode::new("b");
+        other.update("a");
+        other.update("c");
+        mode.merge(other);
+        assert_eq!(mode.compute(), Some("a"));
+        // samples still only hold items from either accumulator
+        let mut sample = Sample::with_config("a".to_string(), 2, Some(1));
+        sample.update("b".to_string());
+        sample.update("c".to_string());
+        let mut other = Sample::with_config("d".to_string(), 2, Some(2));
+        other.update("e".to_string());
+        sample.merge(other);
+        let sampled = sample.compute().unwrap();
+        assert_eq!(sampled.split(SAMPLE_SEPARATOR).count(), 2);
+        assert!(sampled
+            .split(SAMPLE_SEPARATOR)
+            .all(|item| ["a", "b", "c", "d", "e"].contains(&item)));
+    }
+
     #[test]
     fn test_range_decimals() {
         let updates = vec!["1.2", "2E3", "10000"];
@@ -609,6 +1927,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_mode_tiebreaks() {
+        // c and a tie with a count of 2, but c reaches it first
+        let values = ["c", "b", "c", "a", "a"];
+        let expected = vec![
+            (Tiebreak::First, "c"),
+            (Tiebreak::Last, "a"),
+            (Tiebreak::Min, "a"),
+            (Tiebreak::Max, "c"),
+        ];
+        for (tiebreak, mode) in expected {
+            let mut acc = Mode::with_tiebreak(values[0].to_string(), tiebreak);
+            for val in &values[1..] {
+                acc.update(val.to_string());
+            }
+            assert_eq!(acc.compute(), Some(mode.to_string()));
+        }
+        let mut numeric =
+            Mode::with_tiebreak("10".parse::<DecimalWrapper>().unwrap(), Tiebreak::Min);
+        for val in &["9", "10", "9.0"] {
+            numeric.update(val.parse().unwrap());
+        }
+        assert_eq!(numeric.compute().unwrap().to_string(), "9");
+    }
+
     #[test]
     fn test_sum() {
         let dec_num: DecimalWrapper = "10".parse().unwrap();
@@ -624,6 +1967,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sum_squares() {
+        let mut sum_squares = SumSquares::new(3.);
+        assert_eq!(sum_squares.compute(), Some(9.));
+        for val in [-4., 0.5] {
+            sum_squares.update(val);
+        }
+        assert_eq!(sum_squares.compute(), Some(25.25));
+        sum_squares.update(1e200);
+        assert_eq!(sum_squares.compute(), Some(f64::INFINITY));
+    }
+
+    #[test]
+    fn test_rms() {
+        let mut rms = Rms::new(-2.);
+        assert_eq!(rms.compute(), Some(2.));
+        for val in [2., 2., -2.] {
+            rms.update(val);
+        }
+        assert_eq!(rms.compute(), Some(2.));
+        rms.update(6.);
+        // sqrt((4 * 4 + 36) / 5)
+        assert_eq!(rms.compute(), Some((52_f64 / 5.).sqrt()));
+    }
+
+    #[test]
+    fn test_weighted_count() {
+        let weights = ["2", "0.5", "3"]
+            .iter()
+            .map(|w| w.parse::<DecimalWrapper>().unwrap());
+        let mut count = WeightedCount::new("1".parse().unwrap());
+        for weight in weights {
+            count.update(weight);
+        }
+        assert_eq!(count.compute().unwrap().to_string(), "6.5");
+    }
+
+    #[test]
+    fn test_any_accumulator() {
+        let wrap = |v: i64| DecimalWrapper {
+            item: Decimal::from(v),
+        };
+        let mut sum = AnyAccumulator::with_function(wrap(1), Function::Sum);
+        let mut count = AnyAccumulator::with_function(wrap(1), Function::Count);
+        let mut mean = AnyAccumulator::with_function(wrap(1), Function::Mean);
+        for val in [2, 6] {
+            sum.update(wrap(val));
+            count.update(wrap(val));
+            mean.update(wrap(val));
+        }
+        assert_eq!(sum.compute().unwrap().to_string(), "9");
+        assert_eq!(count.compute().unwrap().to_string(), "3");
+        assert_eq!(mean.compute().unwrap().to_string(), "3");
+        assert!("mode".parse::<Function>().is_err());
+        assert_eq!("sumsq".parse::<Function>(), Ok(Function::SumSquares));
+    }
+
+    #[test]
+    #[should_panic(expected = "two different functions (sum and mean)")]
+    fn test_any_accumulator_merges_one_function() {
+        let wrap = |v: i64| DecimalWrapper {
+            item: Decimal::from(v),
+        };
+        let mut sum = AnyAccumulator::with_function(wrap(1), Function::Sum);
+        sum.merge(AnyAccumulator::with_function(wrap(2), Function::Mean));
+    }
+
     proptest! {
         #![proptest_config(Config::with_cases(100))]
         #[test]
@@ -636,5 +2046,21 @@ mod tests {
             }
             assert_eq!(count_obj.compute().unwrap(), total_count);
         }
+
+        #[test]
+        fn test_median_matches_sorted(mut ints in prop::collection::vec(-20_i64 .. 20, 1 .. 60)) {
+            let mut median = Median::new(DecimalWrapper { item: Decimal::from(ints[0]) });
+            for &int in &ints[1..] {
+                median.update(DecimalWrapper { item: Decimal::from(int) });
+            }
+            ints.sort_unstable();
+            let mid = ints.len() / 2;
+            let expected = if ints.len() % 2 == 0 {
+                (Decimal::from(ints[mid - 1]) + Decimal::from(ints[mid])) / Decimal::from(2)
+            } else {
+                Decimal::from(ints[mid])
+            };
+            assert_eq!(median.compute().unwrap().item, expected);
+        }
     }
 }
@@ -3,26 +3,51 @@
 //! It has three main methods: `new`, which initializes the data; `aggregate`, which takes
 //! a csv file and creates an `IndexMap` of `Accumulator`s; and `write_results` which
 //! outputs the aggregated values to standard output.
-use crate::aggfunc::Accumulate;
+use crate::aggfunc::{Accumulate, AnyOutput, Function, Picked, PICK_SEPARATOR};
 use crate::errors::{CsvCliError, CsvCliResult};
-use crate::parsing::INPUT_DATE_FORMAT;
+use crate::expr::{EvalError, Expr};
+use crate::parsing::{
+    describe_date_formats, from_decimal_comma, loses_precision, CustomDateObject, DateFormat,
+    DecimalWrapper, TimeBucket, INPUT_DATE_FORMAT,
+};
+use indexmap::map::IndexMap;
 use indexmap::set::IndexSet;
+use indicatif::ProgressBar;
 use lazy_static::lazy_static;
+use regex::Regex;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use std::borrow::Cow;
 use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::io;
 use std::marker::PhantomData;
 
-const FIELD_SEPARATOR: &str = "_<sep>_";
+/// Joins the values of multiple row or column fields in the labels of the pivot table. This is the
+/// ASCII unit separator, a control character that doesn't show up in real data, so a value
+/// that contains the displayed separator can't be mistaken for two values.
+const FIELD_SEPARATOR: &str = "\u{1f}";
+/// How the labels display `FIELD_SEPARATOR`, unless you choose another separator with `set_key_separator`
+const DEFAULT_KEY_SEPARATOR: &str = "_<sep>_";
+/// How many records to read between updates of the progress bar
+const PROGRESS_INTERVAL: usize = 1000;
+/// The number of unparseable values `failure_summary` describes, with `set_skip_errors`
+/// (and the number of records `ragged_summary` describes, with `set_lenient`)
+const MAX_FAILURE_EXAMPLES: usize = 5;
 lazy_static! {
-    static ref EMPTY_VALUES: HashSet<&'static str> = vec!["", "null", "nan", "none", "na", "n/a"]
+    static ref EMPTY_VALUES: HashSet<&'static str> = ["", "null", "nan", "none", "na", "n/a"]
         .iter()
         .cloned()
         .collect();
 }
 
+/// Whether a value counts as empty (like `NULL` or `N/A`) for skipping with `-e`
+pub(crate) fn is_null_value(value: &str) -> bool {
+    EMPTY_VALUES.contains(value.to_ascii_lowercase().as_str())
+}
+
 /// How the rows or columns are going to be sorted
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OutputOrder {
     /// Results appear in index order
     IndexOrder,
@@ -32,10 +57,30 @@ pub enum OutputOrder {
     Descending,
 }
 
+/// How `Aggregator::set_precision` formats numeric results
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Notation {
+    /// A fixed number of digits after the decimal point, like `277.33`
+    Fixed,
+    /// A number of significant digits (like `%g` in C), switching to scientific notation
+    /// for very large or very small numbers, like `277.3` or `1.234e-7`
+    General,
+}
+
+/// Which records `Aggregator::set_skip_empty_keys` skips, based on their row and column fields
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum EmptyKeys {
+    /// Records where any of the row and column fields are empty
+    Any,
+    /// Records where all of the row and column fields are empty
+    All,
+}
+
 /// The general type of data being used. I've used this to implement better error handling.
 /// See [the GitHub](https://github.com/maxblee/clipivot#functions) page for more details on the
 /// meaning of these functions.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[non_exhaustive]
 pub enum ParsingStrategy {
     /// For accumulators that hold and manipulate text (string) data.
     Text,
@@ -43,13 +88,85 @@ pub enum ParsingStrategy {
     Numeric,
     /// For accumulators that manipulate dates
     Date,
-    // design from https://docs.rs/csv/1.1.3/src/csv/error.rs.html#61-108
-    #[doc(hidden)]
-    __Nonexhaustive,
+    /// For accumulators that count true and false values
+    Boolean,
 }
 
-/// The object that computes the aggregations and writes to standard output.
+impl ParsingStrategy {
+    /// The name of the strategy, as `--check` and `--list-functions` display it
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParsingStrategy::Text => "text",
+            ParsingStrategy::Numeric => "numeric",
+            ParsingStrategy::Date => "date",
+            ParsingStrategy::Boolean => "boolean",
+        }
+    }
+}
+
+/// Half-open numeric ranges (or buckets) for grouping values into a histogram.
+///
+/// The edges `0,10,100` make the buckets `0..10` and `10..100`, plus an underflow bucket
+/// (`..0`) for values below the first edge and an overflow bucket (`100..`) for values at
+/// or above the last edge.
 #[derive(Debug, PartialEq)]
+pub struct Bins {
+    edges: Vec<Decimal>,
+    labels: Vec<String>,
+}
+
+impl std::str::FromStr for Bins {
+    type Err = CsvCliError;
+
+    /// Parses a comma-separated list of strictly increasing bin edges
+    fn from_str(s: &str) -> CsvCliResult<Bins> {
+        let edges = s
+            .split(',')
+            .map(|edge| {
+                edge.trim()
+                    .parse::<DecimalWrapper>()
+                    .map(|d| d.item)
+                    .map_err(|_| {
+                        CsvCliError::InvalidConfiguration(format!(
+                            "Could not parse the bin edge `{}` as a number",
+                            edge
+                        ))
+                    })
+            })
+            .collect::<CsvCliResult<Vec<Decimal>>>()?;
+        if edges.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The bin edges need to be in strictly increasing order".to_string(),
+            ));
+        }
+        let mut labels = vec![format!("..{}", edges[0])];
+        for pair in edges.windows(2) {
+            labels.push(format!("{}..{}", pair[0], pair[1]));
+        }
+        labels.push(format!("{}..", edges[edges.len() - 1]));
+        Ok(Bins { edges, labels })
+    }
+}
+
+impl Bins {
+    /// The label of the bucket a value falls into
+    fn label(&self, value: Decimal) -> &str {
+        let position = self.edges.iter().take_while(|&&edge| edge <= value).count();
+        &self.labels[position]
+    }
+
+    /// Splits a column label into the part before the bucket and the position of the bucket,
+    /// for sorting buckets numerically rather than alphabetically
+    fn sort_key<'a>(&self, label: &'a str) -> (&'a str, usize) {
+        let (prefix, bucket) = label.rsplit_once(FIELD_SEPARATOR).unwrap_or(("", label));
+        let position = self.labels.iter().position(|l| l == bucket).unwrap_or(0);
+        (prefix, position)
+    }
+}
+
+type Parser<I> = Box<dyn Fn(&str) -> Result<I, <I as std::str::FromStr>::Err>>;
+
+/// The object that computes the aggregations and writes to standard output.
 pub struct Aggregator<T, I, O>
 where
     T: Accumulate<I, O>,
@@ -57,6 +174,24 @@ where
     O: std::fmt::Display,
 {
     aggregations: HashMap<(String, String), T>,
+    /// The number of records aggregated into each cell, for annotating cells with `--with-count`
+    /// and for the `count` row and column. This stays empty when none of those are used, so the
+    /// labels of each cell aren't stored twice.
+    counts: HashMap<(String, String), usize>,
+    /// Whether to add a column with the number of records in each row
+    row_count: bool,
+    /// Whether to add a row with the number of records in each column
+    col_count: bool,
+    /// Creates an accumulator from the first value in a cell, for accumulators that need more
+    /// configuration than `Accumulate::new` allows (like the separator for `MinMax`).
+    initializer: Option<Box<dyn Fn(I) -> T>>,
+    /// Parses values in place of `FromStr`, for parsing dates with `set_date_format`
+    parser: Option<Parser<I>>,
+    /// The date format used in place of the global one from `parsing::set_date_format`
+    date_format: Option<DateFormat>,
+    /// The row and column labels, in the order they were first seen. These only get filled in
+    /// by `add_record` (never from iterating over `aggregations`), so the output
+    /// is the same every time the same records are read in the same order.
     indexes: IndexSet<String>,
     columns: IndexSet<String>,
     index_cols: Vec<usize>,
@@ -66,10 +201,181 @@ where
     row_order: OutputOrder,
     column_order: OutputOrder,
     parsing_strategy: ParsingStrategy,
+    /// Every record read from the input, including the ones that were skipped
+    records_read: usize,
+    records_processed: usize,
+    records_skipped: usize,
+    /// Which records with empty row or column fields are skipped, and how many were
+    skip_empty_keys: Option<EmptyKeys>,
+    keys_skipped: usize,
+    skip_errors: bool,
+    parse_failures: usize,
+    failure_examples: Vec<String>,
+    /// Whether records with the wrong number of fields are skipped, and the ones that were
+    lenient: bool,
+    ragged_records: usize,
+    ragged_examples: Vec<String>,
+    cumulative: bool,
+    /// Whether the columns are sorted by their totals, largest first, instead of by `column_order`
+    sort_cols_by_total: bool,
+    /// Whether to write each cell as a percentage of its row, and whether to keep the raw value
+    row_percent: Option<bool>,
+    empty_as_zero: bool,
+    bins: Option<Bins>,
+    row_bucket: Option<TimeBucket>,
+    corner_label: String,
+    /// Names the aggregation, like `sum(amount)`, for `header_comment` and the second header row
+    value_label: String,
+    multi_header: bool,
+    /// The field whose value `MaxBy` and `MinBy` (or `First` and `Last` with `--order-by`) display,
+    /// joined to each value with `PICK_SEPARATOR`
+    pick_col: Option<usize>,
+    /// The index of a field parsed in place of the values column, with `set_weight`
+    weight_col: Option<usize>,
+    /// The number of digits and the notation used to display numeric results
+    precision: Option<(usize, Notation)>,
+    /// The separators between groups of thousands and before the decimals in numeric results
+    digit_grouping: Option<(String, String)>,
+    /// The index and name of a field whose first value in each row is displayed next to the row label
+    carry: Option<(usize, String)>,
+    /// The names and values of columns with the same value in every row, from `add_constant`
+    constants: Vec<(String, String)>,
+    /// The index of a field with multiple values in each cell, and the separator between them
+    explode: Option<(usize, String)>,
+    /// An expression whose result is aggregated in place of the values column
+    expr: Option<Expr>,
+    /// The fields whose first non-empty value is aggregated in place of the values column
+    coalesce: Vec<usize>,
+    /// Whether values have a decimal comma, like `1.234,56`
+    decimal_comma: bool,
+    /// The functions computed for every value with `set_functions`, each in its own column
+    functions: Vec<Function>,
+    /// Creates an accumulator for one of the `functions` from the first value in a cell
+    function_initializer: Option<Box<dyn Fn(I, Function) -> T>>,
+    /// Whether each cell can only have one value, from `set_unique_cells`
+    unique_cells: bool,
+    /// The indexes and names of the fields whose values go into a column named after the field,
+    /// from `set_melt`
+    melt: Vec<(usize, String)>,
+    /// The row and column labels of the cell whose records are kept with `set_explain`
+    explain: Option<(String, String)>,
+    explained: Vec<csv::StringRecord>,
+    /// The header row, to skip records that repeat it
+    repeated_header: Option<csv::StringRecord>,
+    /// Whether the output starts with a header row
+    output_header: bool,
+    /// Whether to look for values with more digits than a `Decimal` can hold, and the line number
+    /// and value of the first one
+    check_precision: bool,
+    precision_loss: Option<(usize, String)>,
+    /// Whether labels made of digits are padded with zeros when they're sorted,
+    /// and whether the padding is kept in the output
+    zero_pad: bool,
+    keep_padding: bool,
+    /// The widths the values of each row and column field are padded to with `set_zero_pad`
+    row_widths: Vec<usize>,
+    col_widths: Vec<usize>,
+    /// Regular expressions and their replacements for the values of the row and column fields
+    row_replace: Option<(Regex, String)>,
+    col_replace: Option<(Regex, String)>,
+    carried_values: HashMap<String, String>,
+    /// Rows where the carried field had more than one value
+    carry_conflicts: IndexSet<String>,
+    /// Joins the values of multiple row or column fields in the output. The labels are
+    /// always stored with `FIELD_SEPARATOR`, so this only changes how they're displayed.
+    key_separator: String,
+    na_rep: String,
+    na_numbers: Vec<Decimal>,
+    count_format: Option<String>,
+    sample_size: Option<usize>,
+    /// The maximum number of records `aggregate` reads
+    input_limit: Option<usize>,
+    progress: Option<ProgressBar>,
     input_type: PhantomData<I>,
     output_type: PhantomData<O>,
 }
 
+/// Leaves out the closures that create accumulators and parse values, which can't be printed.
+impl<T, I, O> std::fmt::Debug for Aggregator<T, I, O>
+where
+    T: Accumulate<I, O> + std::fmt::Debug,
+    I: std::str::FromStr,
+    O: std::fmt::Display,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Aggregator")
+            .field("aggregations", &self.aggregations)
+            .field("counts", &self.counts)
+            .field("row_count", &self.row_count)
+            .field("col_count", &self.col_count)
+            .field("date_format", &self.date_format)
+            .field("indexes", &self.indexes)
+            .field("columns", &self.columns)
+            .field("index_cols", &self.index_cols)
+            .field("column_cols", &self.column_cols)
+            .field("values_col", &self.values_col)
+            .field("skip_null", &self.skip_null)
+            .field("row_order", &self.row_order)
+            .field("column_order", &self.column_order)
+            .field("parsing_strategy", &self.parsing_strategy)
+            .field("records_read", &self.records_read)
+            .field("records_processed", &self.records_processed)
+            .field("records_skipped", &self.records_skipped)
+            .field("skip_empty_keys", &self.skip_empty_keys)
+            .field("keys_skipped", &self.keys_skipped)
+            .field("skip_errors", &self.skip_errors)
+            .field("parse_failures", &self.parse_failures)
+            .field("failure_examples", &self.failure_examples)
+            .field("lenient", &self.lenient)
+            .field("ragged_records", &self.ragged_records)
+            .field("ragged_examples", &self.ragged_examples)
+            .field("cumulative", &self.cumulative)
+            .field("sort_cols_by_total", &self.sort_cols_by_total)
+            .field("row_percent", &self.row_percent)
+            .field("empty_as_zero", &self.empty_as_zero)
+            .field("bins", &self.bins)
+            .field("row_bucket", &self.row_bucket)
+            .field("corner_label", &self.corner_label)
+            .field("value_label", &self.value_label)
+            .field("multi_header", &self.multi_header)
+            .field("pick_col", &self.pick_col)
+            .field("weight_col", &self.weight_col)
+            .field("precision", &self.precision)
+            .field("digit_grouping", &self.digit_grouping)
+            .field("carry", &self.carry)
+            .field("constants", &self.constants)
+            .field("explode", &self.explode)
+            .field("expr", &self.expr)
+            .field("coalesce", &self.coalesce)
+            .field("decimal_comma", &self.decimal_comma)
+            .field("functions", &self.functions)
+            .field("unique_cells", &self.unique_cells)
+            .field("melt", &self.melt)
+            .field("explain", &self.explain)
+            .field("explained", &self.explained)
+            .field("repeated_header", &self.repeated_header)
+            .field("output_header", &self.output_header)
+            .field("check_precision", &self.check_precision)
+            .field("precision_loss", &self.precision_loss)
+            .field("zero_pad", &self.zero_pad)
+            .field("keep_padding", &self.keep_padding)
+            .field("row_widths", &self.row_widths)
+            .field("col_widths", &self.col_widths)
+            .field("row_replace", &self.row_replace)
+            .field("col_replace", &self.col_replace)
+            .field("carried_values", &self.carried_values)
+            .field("carry_conflicts", &self.carry_conflicts)
+            .field("key_separator", &self.key_separator)
+            .field("na_rep", &self.na_rep)
+            .field("na_numbers", &self.na_numbers)
+            .field("count_format", &self.count_format)
+            .field("sample_size", &self.sample_size)
+            .field("input_limit", &self.input_limit)
+            .field("progress", &self.progress)
+            .finish_non_exhaustive()
+    }
+}
+
 impl<T, I, O> Aggregator<T, I, O>
 where
     T: Accumulate<I, O>,
@@ -90,6 +396,12 @@ where
         let columns = IndexSet::new();
         Aggregator {
             aggregations,
+            counts: HashMap::new(),
+            row_count: false,
+            col_count: false,
+            initializer: None,
+            parser: None,
+            date_format: None,
             indexes,
             columns,
             index_cols,
@@ -99,162 +411,2068 @@ where
             row_order,
             column_order,
             parsing_strategy,
+            records_read: 0,
+            records_processed: 0,
+            records_skipped: 0,
+            skip_empty_keys: None,
+            keys_skipped: 0,
+            skip_errors: false,
+            parse_failures: 0,
+            failure_examples: Vec::new(),
+            lenient: false,
+            ragged_records: 0,
+            ragged_examples: Vec::new(),
+            cumulative: false,
+            sort_cols_by_total: false,
+            row_percent: None,
+            empty_as_zero: false,
+            bins: None,
+            row_bucket: None,
+            corner_label: String::new(),
+            value_label: String::new(),
+            multi_header: false,
+            pick_col: None,
+            weight_col: None,
+            precision: None,
+            digit_grouping: None,
+            carry: None,
+            constants: Vec::new(),
+            explode: None,
+            expr: None,
+            coalesce: Vec::new(),
+            decimal_comma: false,
+            functions: Vec::new(),
+            function_initializer: None,
+            unique_cells: false,
+            melt: Vec::new(),
+            explain: None,
+            explained: Vec::new(),
+            repeated_header: None,
+            output_header: true,
+            check_precision: false,
+            precision_loss: None,
+            zero_pad: false,
+            keep_padding: false,
+            row_widths: Vec::new(),
+            col_widths: Vec::new(),
+            row_replace: None,
+            col_replace: None,
+            carried_values: HashMap::new(),
+            carry_conflicts: IndexSet::new(),
+            key_separator: DEFAULT_KEY_SEPARATOR.to_string(),
+            na_rep: String::new(),
+            na_numbers: Vec::new(),
+            count_format: None,
+            sample_size: None,
+            input_limit: None,
+            progress: None,
             input_type: PhantomData,
             output_type: PhantomData,
         }
     }
 
+    /// Overrides how new accumulators get created, for accumulators that need more
+    /// configuration than `Accumulate::new` allows.
+    pub fn set_initializer<F>(&mut self, initializer: F)
+    where
+        F: Fn(I) -> T + 'static,
+    {
+        self.initializer = Some(Box::new(initializer));
+    }
+
+    /// Parses the dates in the row field with `set_row_bucket` using `format`, instead of the global
+    /// format from `parsing::set_date_format`. Aggregators of dates can use `set_date_format`
+    /// to parse the values with it as well.
+    pub fn set_row_date_format(&mut self, format: DateFormat) {
+        self.date_format = Some(format);
+    }
+
+    /// Writes running totals down each column, instead of the value of each cell. This only
+    /// makes sense for additive accumulators (like `Count` and `Sum`) with numeric output.
+    pub fn set_cumulative(&mut self, cumulative: bool) {
+        self.cumulative = cumulative;
+    }
+
+    /// Sorts the columns by the total of their cells, from largest to smallest, with ties staying in
+    /// the order they were first seen. Like `set_cumulative`, this only makes sense for additive
+    /// accumulators with numeric output.
+    pub fn set_sort_cols_by_total(&mut self) {
+        self.sort_cols_by_total = true;
+    }
+
+    /// Writes each cell as a percentage of the total of its row (like `45.6%`), or with
+    /// `keep_raw`, as the value followed by the percentage (like `123 (45.6%)`). Like
+    /// `set_cumulative`, this only makes sense for additive accumulators.
+    pub fn set_row_percent(&mut self, keep_raw: bool) {
+        self.row_percent = Some(keep_raw);
+    }
+
+    /// Skips values that can't be parsed, instead of returning an error. The skipped values
+    /// are described by `failure_summary`.
+    pub fn set_skip_errors(&mut self, skip_errors: bool) {
+        self.skip_errors = skip_errors;
+    }
+
+    /// Skips records whose row or column fields are empty (or only whitespace), instead of
+    /// grouping them under an empty label. Without any column fields, the records all go in the
+    /// `total` column, so only the row fields are checked (and the same goes for the column
+    /// fields without any row fields). Without either, no records are skipped.
+    pub fn set_skip_empty_keys(&mut self, mode: EmptyKeys) {
+        self.skip_empty_keys = Some(mode);
+    }
+
+    /// Skips records with a different number of fields than the first row, instead of
+    /// returning an error. The skipped records are described by `ragged_summary`.
+    pub fn set_lenient(&mut self) {
+        self.lenient = true;
+    }
+
+    /// Writes `0` in cells that didn't get any values, instead of leaving them empty. Rows and
+    /// columns whose records were all skipped as empty still show up in the output (filled with
+    /// zeroes). Like `set_cumulative`, this only makes sense for additive accumulators.
+    pub fn set_empty_as_zero(&mut self, empty_as_zero: bool) {
+        self.empty_as_zero = empty_as_zero;
+    }
+
+    /// Splits the values into numeric buckets, adding each value's bucket to the labels of
+    /// the columns. (So `Count` with bins creates a histogram.)
+    pub fn set_bins(&mut self, bins: Bins) {
+        self.bins = Some(bins);
+    }
+
+    /// Parses the first row field as a date and groups the rows into periods of time
+    /// (like months), rather than using the raw values.
+    pub fn set_row_bucket(&mut self, bucket: TimeBucket) {
+        self.row_bucket = Some(bucket);
+    }
+
+    /// Labels the top-left corner of the pivot table with the names of the row fields
+    /// (which is otherwise left blank).
+    pub fn label_corner(&mut self, headers: &[&str]) {
+        self.corner_label = self
+            .index_cols
+            .iter()
+            .map(|&i| headers[i])
+            .collect::<Vec<&str>>()
+            .join(FIELD_SEPARATOR);
+    }
+
+    /// Rounds numeric results to `digits` digits in the given notation. Results that aren't
+    /// numbers (like dates or the output of `minmax`) are displayed as they are.
+    pub fn set_precision(&mut self, digits: usize, notation: Notation) {
+        self.precision = Some((digits, notation));
+    }
+
+    /// Separates the thousands in numeric results with `group_separator` (like `1,234,567`) and uses
+    /// `decimal_separator` before the decimals. This happens after rounding with `set_precision`.
+    pub fn set_digit_grouping(&mut self, group_separator: &str, decimal_separator: &str) {
+        self.digit_grouping = Some((group_separator.to_string(), decimal_separator.to_string()));
+    }
+
+    /// Adds a `count` column with the number of records in each row.
+    /// Like all of the counts, this only includes the records that made it into the aggregations,
+    /// so it leaves out values skipped as empty or that failed to parse (with `set_skip_errors`).
+    pub fn set_row_count(&mut self, row_count: bool) {
+        self.row_count = row_count;
+    }
+
+    /// Adds a `count` row at the bottom with the number of records in each column.
+    pub fn set_col_count(&mut self, col_count: bool) {
+        self.col_count = col_count;
+    }
+
+    /// Passes the value of another field along with each value, for `MaxBy`, `MinBy`, `First` and `Last`
+    /// (whose input is a `Picked` value).
+    pub fn set_pick(&mut self, col: usize) {
+        self.pick_col = Some(col);
+    }
+
+    /// Parses the value of another field in each record instead of its value, for `WeightedCount`.
+    /// Records are still skipped based on their values (with `-e`), but the accumulator only sees
+    /// their weights.
+    pub fn set_weight(&mut self, col: usize) {
+        self.weight_col = Some(col);
+    }
+
+    /// Names the aggregation (like `sum(amount)`) for `header_comment` and `set_multi_header`.
+    pub fn set_value_label(&mut self, label: &str) {
+        self.value_label = label.to_string();
+    }
+
+    /// Writes a second header row with the name of the aggregation (from `set_value_label`)
+    /// above each column.
+    pub fn set_multi_header(&mut self, multi_header: bool) {
+        self.multi_header = multi_header;
+    }
+
+    /// Describes where the output columns come from in a `#`-commented line (without a line ending),
+    /// like `# values: sum(amount); rows: state; columns: year`.
+    pub fn header_comment(&self, headers: &[&str]) -> String {
+        let join = |cols: &[usize]| {
+            cols.iter()
+                .map(|&i| headers[i])
+                .collect::<Vec<&str>>()
+                .join(", ")
+        };
+        let mut columns = join(&self.column_cols);
+        if self.bins.is_some() {
+            if !columns.is_empty() {
+                columns.push_str(", ");
+            }
+            let values = match &self.expr {
+                Some(expr) => expr.to_string(),
+                None if !self.coalesce.is_empty() => format!("coalesce({})", join(&self.coalesce)),
+                None => headers[self.values_col].to_string(),
+            };
+            columns.push_str(&format!("{} ranges", values));
+        }
+        format!(
+            "# values: {}; rows: {}; columns: {}",
+            self.value_label,
+            join(&self.index_cols),
+            columns
+        )
+    }
+
+    /// Displays the first value of another field in each row in a column next to the row labels,
+    /// for descriptive fields that don't change within a row (like the name of a state in rows of
+    /// state codes). The rows where the field does change are returned by `carry_conflicts`.
+    pub fn set_carry(&mut self, col: usize, name: &str) {
+        self.carry = Some((col, name.to_string()));
+    }
+
+    /// Adds a column named `name` with `value` in every row (including the `count` row), for tagging
+    /// the output with something like the name of its source. The columns go after the row labels
+    /// (and the column from `set_carry`), in the order they were added.
+    pub fn add_constant(&mut self, name: &str, value: &str) {
+        self.constants.push((name.to_string(), value.to_string()));
+    }
+
+    /// Splits the values of a field on `separator`, treating each record as one record for each
+    /// piece (with the same values in the other fields). The pieces are trimmed, and empty pieces
+    /// are ignored, so `a; b;` becomes `a` and `b`.
+    pub fn set_explode(&mut self, col: usize, separator: &str) {
+        self.explode = Some((col, separator.to_string()));
+    }
+
+    /// Aggregates the result of an arithmetic expression on each record instead of the
+    /// values column. Records where one of the fields in the expression is empty are
+    /// treated like records with an empty value.
+    pub fn set_expr(&mut self, expr: Expr) {
+        self.expr = Some(expr);
+    }
+
+    /// Aggregates the first non-empty value among several fields instead of the values column,
+    /// checking them in the order they're listed. Blank values are empty, as are the values `-e`
+    /// skips (like `NA`) if `skip_null` is set. Records where all of them are empty
+    /// are treated like records with an empty value.
+    pub fn set_coalesce(&mut self, cols: Vec<usize>) {
+        self.coalesce = cols;
+    }
+
+    /// Parses values with a decimal comma and periods between groups of thousands, like `1.234,56`,
+    /// instead of a decimal point. This rewrites each value before it's parsed (with
+    /// `parsing::from_decimal_comma`), so it works for every numeric input type. It doesn't change
+    /// an expression from `set_expr`, which has its own `Expr::set_decimal_comma`.
+    pub fn set_decimal_comma(&mut self) {
+        self.decimal_comma = true;
+    }
+
+    /// Computes several functions in a single pass, with a column for each function (in each column
+    /// of the pivot table) labeled with its name, the way `set_bins` adds a column for each bin.
+    /// Each accumulator is created with `initializer`, from the first value in its cell and its function,
+    /// so this needs an accumulator that can compute any of them, like `AnyAccumulator::with_function`.
+    ///
+    /// Within each column, the functions stay in the order they're listed here, however the columns are sorted.
+    pub fn set_functions<F>(&mut self, functions: Vec<Function>, initializer: F)
+    where
+        F: Fn(I, Function) -> T + 'static,
+    {
+        self.functions = functions;
+        self.function_initializer = Some(Box::new(initializer));
+    }
+
+    /// Returns an error instead of aggregating when a second value goes into the same cell,
+    /// for reshaping data that should already have one value in each cell.
+    pub fn set_unique_cells(&mut self) {
+        self.unique_cells = true;
+    }
+
+    /// Aggregates the values of several fields instead of the values column, each in a column
+    /// named after its field (and ignoring the column fields), for reshaping wide data into
+    /// long data with `write_long`.
+    pub fn set_melt(&mut self, fields: Vec<(usize, String)>) {
+        self.melt = fields;
+    }
+
+    /// Keeps the records that go into the cell with the given row and column labels (as they're
+    /// displayed in the output), so `explained_records` can show why the cell has the value it has.
+    /// Only that cell's records are kept, so this needs as much memory as the records in the cell.
+    pub fn set_explain(&mut self, row: &str, column: &str) {
+        self.explain = Some((row.to_string(), column.to_string()));
+    }
+
+    /// Returns the records that went into the cell from `set_explain`, in the order they were read.
+    pub fn explained_records(&self) -> &[csv::StringRecord] {
+        &self.explained
+    }
+
+    /// Skips records that are identical to the header row, which show up in the middle of files
+    /// made by concatenating CSV files with `cat`. The skipped rows still count toward the
+    /// record numbers in error messages.
+    pub fn set_skip_repeated_header(&mut self, header: csv::StringRecord) {
+        self.repeated_header = Some(header);
+    }
+
+    /// Leaves the header row (and the row of value labels from `set_multi_header`) out of the output,
+    /// for appending the pivot table to a file that already has a header.
+    pub fn set_no_output_header(&mut self) {
+        self.output_header = false;
+    }
+
+    /// Looks for values that change when they're parsed as `Decimal`s, for functions
+    /// that parse values as `DecimalWrapper`s. The first one is returned by `precision_loss`.
+    pub fn set_precision_check(&mut self) {
+        self.check_precision = true;
+    }
+
+    /// Returns the line number and value of the first value that changed when it was
+    /// parsed, with `set_precision_check`.
+    pub fn precision_loss(&self) -> Option<(usize, &str)> {
+        self.precision_loss
+            .as_ref()
+            .map(|(line_num, value)| (*line_num, value.as_str()))
+    }
+
+    /// Sorts labels made of digits as though they were padded with zeros to the same width, so
+    /// `9` comes before `10`. The padding is only displayed with `keep_padding`. This only pads
+    /// whole numbers without signs; each field of a multi-field label is padded separately.
+    pub fn set_zero_pad(&mut self, keep_padding: bool) {
+        self.zero_pad = true;
+        self.keep_padding = keep_padding;
+    }
+
+    /// Replaces the matches of `pattern` in the values of the row fields with `replacement`
+    /// before grouping them, so `Acme Inc (123)` and `Acme Inc (456)` can both become `Acme Inc`.
+    /// The replacement can refer to groups in the pattern, like `$1`.
+    pub fn set_row_replace(&mut self, pattern: Regex, replacement: &str) {
+        self.row_replace = Some((pattern, replacement.to_string()));
+    }
+
+    /// Like `set_row_replace`, but for the values of the column fields.
+    pub fn set_col_replace(&mut self, pattern: Regex, replacement: &str) {
+        self.col_replace = Some((pattern, replacement.to_string()));
+    }
+
+    /// Returns the labels of the rows where the field from `set_carry` had more than one value.
+    pub fn carry_conflicts(&self) -> Vec<String> {
+        self.carry_conflicts
+            .iter()
+            .map(|row| display_label(row, &self.key_separator))
+            .collect()
+    }
+
+    /// Sets the separator between the values of multiple row or column fields in the output
+    /// (e.g. ` / ` to display `Columbus / OH`). Defaults to `_<sep>_`.
+    pub fn set_key_separator(&mut self, separator: &str) {
+        self.key_separator = separator.to_string();
+    }
+
+    /// Sets the text written in cells without a value (either because no records matched
+    /// the cell or because the accumulator couldn't compute a value). Defaults to an empty string.
+    pub fn set_na_rep(&mut self, na_rep: &str) {
+        self.na_rep = na_rep.to_string();
+    }
+
+    /// Skips values equal to any of these numbers (like the `-999` that surveys often use
+    /// for missing values), just like empty values. This only applies when parsing values as numbers.
+    pub fn set_na_numbers(&mut self, na_numbers: Vec<Decimal>) {
+        self.na_numbers = na_numbers;
+    }
+
+    /// Annotates each cell that has a value with the number of records in the cell.
+    /// `{value}` and `{count}` in the format are replaced with the computed value and the
+    /// number of records, so the default `{value} ({count})` writes cells like `1234.5 (12)`.
+    pub fn set_count_format(&mut self, format: &str) {
+        self.count_format = Some(format.to_string());
+    }
+
+    /// Stops aggregating after reading `limit` records, so the results only describe the
+    /// beginning of the input. Useful for quickly previewing a pivot table of a large file.
+    pub fn set_input_limit(&mut self, limit: usize) {
+        self.input_limit = Some(limit);
+    }
+
+    /// Before aggregating, checks the first `sample_size` non-empty values in the values column
+    /// and returns an error if none of them can be parsed. This catches mistakes like
+    /// running `mean` on a text column before reading through the rest of the file.
+    pub fn set_sample_check(&mut self, sample_size: usize) {
+        self.sample_size = Some(sample_size);
+    }
+
+    /// Reports progress while aggregating. If the progress bar has a length, it's treated
+    /// as the number of bytes in the input; otherwise, it counts the records read.
+    pub fn set_progress(&mut self, progress: ProgressBar) {
+        self.progress = Some(progress);
+    }
+
+    /// Describes the resolved (0-indexed) columns and the parsing strategy,
+    /// without aggregating anything. Used by the `--check` flag.
+    pub fn describe_config(&self) -> String {
+        let join = |cols: &[usize]| {
+            cols.iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+        let values = match &self.expr {
+            Some(expr) => expr.to_string(),
+            None if !self.coalesce.is_empty() => format!("coalesce({})", join(&self.coalesce)),
+            None => self.values_col.to_string(),
+        };
+        format!(
+            "rows: {}\ncolumns: {}\nvalues: {}\nparsing strategy: {}\n",
+            join(&self.index_cols),
+            join(&self.column_cols),
+            values,
+            self.parsing_strategy.name()
+        )
+    }
+
+    /// Summarizes the size of the pivot table and how many records went into it.
+    /// Used by the `--stats` flag.
+    pub fn stats(&self) -> String {
+        let mut stats = format!(
+            "distinct rows: {}\ndistinct columns: {}\nrecords processed: {}\nrecords skipped as empty: {}\n",
+            self.indexes.len(),
+            self.columns.len(),
+            self.records_processed,
+            self.records_skipped
+        );
+        if self.skip_empty_keys.is_some() {
+            stats.push_str(&format!(
+                "records skipped for empty rows or columns: {}\n",
+                self.keys_skipped
+            ));
+        }
+        stats
+    }
+
+    /// Describes the values skipped with `set_skip_errors` (the number of them and the first few
+    /// errors), or returns `None` if every value could be parsed.
+    pub fn failure_summary(&self) -> Option<String> {
+        if self.parse_failures == 0 {
+            return None;
+        }
+        let mut summary = format!(
+            "Skipped {} value(s) that could not be parsed:\n",
+            self.parse_failures
+        );
+        for example in &self.failure_examples {
+            summary.push_str(&format!("  {}\n", example));
+        }
+        if self.parse_failures > self.failure_examples.len() {
+            summary.push_str(&format!(
+                "  ...and {} more\n",
+                self.parse_failures - self.failure_examples.len()
+            ));
+        }
+        Some(summary)
+    }
+
+    /// Removes every column from the pivot table except the ones with the given labels.
+    /// Returns the labels that didn't match any column, or an error if no columns are left.
+    pub fn keep_columns(&mut self, labels: &[&str]) -> CsvCliResult<Vec<String>> {
+        let missing = self.missing_columns(labels);
+        let separator = &self.key_separator;
+        self.columns
+            .retain(|col| labels.contains(&display_label(col, separator).as_str()));
+        if self.columns.is_empty() {
+            return Err(CsvCliError::InvalidConfiguration(
+                "None of the columns you chose to keep appear in the pivot table".to_string(),
+            ));
+        }
+        Ok(missing)
+    }
+
+    /// Removes the columns with the given labels from the pivot table.
+    /// Returns the labels that didn't match any column, or an error if no columns are left.
+    pub fn drop_columns(&mut self, labels: &[&str]) -> CsvCliResult<Vec<String>> {
+        let missing = self.missing_columns(labels);
+        let separator = &self.key_separator;
+        self.columns
+            .retain(|col| !labels.contains(&display_label(col, separator).as_str()));
+        if self.columns.is_empty() {
+            return Err(CsvCliError::InvalidConfiguration(
+                "You dropped every column in the pivot table".to_string(),
+            ));
+        }
+        Ok(missing)
+    }
+
+    fn missing_columns(&self, labels: &[&str]) -> Vec<String> {
+        labels
+            .iter()
+            .filter(|label| {
+                !self
+                    .columns
+                    .iter()
+                    .any(|col| display_label(col, &self.key_separator) == **label)
+            })
+            .map(|label| label.to_string())
+            .collect()
+    }
+
     /// Takes a CSV (from anything that impl's Read) and aggregates information
     /// from the struct's settings. Does not write anything.
     pub fn aggregate<R: std::io::Read>(&mut self, rdr: &mut csv::Reader<R>) -> CsvCliResult<()> {
         let mut line_num = 0;
+        if let Some(sample_size) = self.sample_size {
+            let sample = self.read_sample(rdr, sample_size)?;
+            // skipped records keep their place, so the records after them keep their indexes
+            for record in &sample {
+                if let Some(record) = record {
+                    self.add_record(record, line_num)?;
+                }
+                line_num += 1;
+            }
+        }
+        let limit = self.input_limit.unwrap_or(usize::MAX);
         let mut record = csv::StringRecord::new();
-        while rdr.read_record(&mut record)? {
-            self.add_record(&record, line_num)?;
+        while line_num < limit {
+            match rdr.read_record(&mut record) {
+                Ok(true) => self.add_record(&record, line_num)?,
+                Ok(false) => break,
+                Err(err) => self.skip_ragged(err.into())?,
+            }
             line_num += 1;
+            if line_num % PROGRESS_INTERVAL == 0 {
+                self.update_progress(rdr.position().byte(), line_num);
+            }
+        }
+        if let Some(progress) = &self.progress {
+            self.update_progress(rdr.position().byte(), line_num);
+            progress.finish();
+        }
+        self.records_read += line_num;
+        Ok(())
+    }
+
+    /// Reads the records of `rdr` like `aggregate`, but adds each record to the aggregator in `parts`
+    /// for the value of its `split_col` field instead of this one, creating the aggregator with
+    /// `new_part` the first time the value comes up. This aggregator only keeps track of the
+    /// progress and of the records skipped with `set_lenient`.
+    ///
+    /// This reads the input once, but every part is held in memory until it's written.
+    pub fn aggregate_split<R, F>(
+        &mut self,
+        rdr: &mut csv::Reader<R>,
+        split_col: usize,
+        parts: &mut IndexMap<String, Aggregator<T, I, O>>,
+        mut new_part: F,
+    ) -> CsvCliResult<()>
+    where
+        R: std::io::Read,
+        F: FnMut() -> CsvCliResult<Aggregator<T, I, O>>,
+    {
+        let mut line_num = 0;
+        let limit = self.input_limit.unwrap_or(usize::MAX);
+        let mut record = csv::StringRecord::new();
+        while line_num < limit {
+            match rdr.read_record(&mut record) {
+                Ok(true) if self.is_repeated_header(&record) => {}
+                Ok(true) => {
+                    let value = record.get(split_col).unwrap_or("");
+                    let part = match parts.get_mut(value) {
+                        Some(part) => part,
+                        None => parts.entry(value.to_string()).or_insert(new_part()?),
+                    };
+                    part.add_record(&record, line_num)?;
+                }
+                Ok(false) => break,
+                Err(err) => self.skip_ragged(err.into())?,
+            }
+            line_num += 1;
+            if line_num % PROGRESS_INTERVAL == 0 {
+                self.update_progress(rdr.position().byte(), line_num);
+            }
+        }
+        if let Some(progress) = &self.progress {
+            self.update_progress(rdr.position().byte(), line_num);
+            progress.finish();
+        }
+        self.records_read += line_num;
+        Ok(())
+    }
+
+    /// Returns an error if none of the inputs read by `aggregate` or `aggregate_split` had
+    /// any records. This is checked once every input has been read, since one empty file
+    /// among several isn't a problem.
+    pub fn check_has_records(&self, has_header: bool) -> CsvCliResult<()> {
+        match (self.records_read, has_header) {
+            (0, true) => Err(CsvCliError::InvalidConfiguration(
+                "The input has a header row but no records to aggregate".to_string(),
+            )),
+            (0, false) => Err(CsvCliError::InvalidConfiguration(
+                "The input has no records to aggregate".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Combines this aggregator with one that aggregated the records after this one's (like the
+    /// records of the next file), so the results are the same as aggregating every record here.
+    /// Both aggregators should have the same settings; only the aggregated values get combined.
+    ///
+    /// With `set_unique_cells`, this returns an error if both aggregators have a value in the same cell.
+    pub fn merge(&mut self, other: Aggregator<T, I, O>) -> CsvCliResult<()> {
+        for (key, accumulator) in other.aggregations {
+            match self.aggregations.entry(key) {
+                Entry::Occupied(entry) if self.unique_cells => {
+                    let (index, column) = entry.key();
+                    return Err(CsvCliError::InvalidConfiguration(format!(
+                        "The cell in row `{}` and column `{}` has values in more than one file",
+                        index, column
+                    )));
+                }
+                Entry::Occupied(entry) => entry.into_mut().merge(accumulator),
+                Entry::Vacant(entry) => {
+                    entry.insert(accumulator);
+                }
+            }
+        }
+        for (key, count) in other.counts {
+            *self.counts.entry(key).or_insert(0) += count;
+        }
+        self.indexes.extend(other.indexes);
+        self.columns.extend(other.columns);
+        for (index_vals, value) in other.carried_values {
+            match self.carried_values.get(&index_vals) {
+                Some(first) if *first != value => {
+                    self.carry_conflicts.insert(index_vals);
+                }
+                Some(_) => {}
+                None => {
+                    self.carried_values.insert(index_vals, value);
+                }
+            }
+        }
+        self.carry_conflicts.extend(other.carry_conflicts);
+        self.explained.extend(other.explained);
+        self.records_read += other.records_read;
+        self.records_processed += other.records_processed;
+        self.records_skipped += other.records_skipped;
+        self.keys_skipped += other.keys_skipped;
+        self.parse_failures += other.parse_failures;
+        let room = MAX_FAILURE_EXAMPLES.saturating_sub(self.failure_examples.len());
+        self.failure_examples
+            .extend(other.failure_examples.into_iter().take(room));
+        self.ragged_records += other.ragged_records;
+        let room = MAX_FAILURE_EXAMPLES.saturating_sub(self.ragged_examples.len());
+        self.ragged_examples
+            .extend(other.ragged_examples.into_iter().take(room));
+        if self.precision_loss.is_none() {
+            self.precision_loss = other.precision_loss;
         }
         Ok(())
     }
 
+    /// Reads records until it finds `sample_size` non-empty values, returning an error
+    /// if there are non-empty values and none of them can be parsed.
+    fn read_sample<R: std::io::Read>(
+        &mut self,
+        rdr: &mut csv::Reader<R>,
+        sample_size: usize,
+    ) -> CsvCliResult<Vec<Option<csv::StringRecord>>> {
+        let mut sample = Vec::new();
+        let mut non_empty = 0;
+        let mut parsed = 0;
+        let limit = self.input_limit.unwrap_or(usize::MAX);
+        let mut record = csv::StringRecord::new();
+        while non_empty < sample_size && sample.len() < limit {
+            match rdr.read_record(&mut record) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    self.skip_ragged(err.into())?;
+                    sample.push(None);
+                    continue;
+                }
+            }
+            if self.is_repeated_header(&record) {
+                sample.push(Some(record.clone()));
+                continue;
+            }
+            let exploded = self.explode_record(&record);
+            for piece in exploded.as_deref().unwrap_or(std::slice::from_ref(&record)) {
+                match self.record_value(piece, 0) {
+                    Ok(value) if is_null_value(&value) => {}
+                    Ok(value) => {
+                        non_empty += 1;
+                        if self.parse_input(&value, piece).is_ok() {
+                            parsed += 1;
+                        }
+                    }
+                    Err(_) => non_empty += 1,
+                }
+            }
+            sample.push(Some(record.clone()));
+        }
+        if non_empty > 0 && parsed == 0 {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "None of the first {} non-empty values in the values column could be parsed ({}). \
+                 Did you choose the right column and function?",
+                non_empty,
+                self.describe_err()
+            )));
+        }
+        Ok(sample)
+    }
+
+    /// Parses a value from the values column, joining it to the value of the pick field
+    /// (with `set_pick`) in the same record
+    fn parse_input(&self, value: &str, record: &csv::StringRecord) -> Result<I, I::Err> {
+        let weight;
+        let value = match self.weight_col {
+            Some(col) => {
+                weight = self.localized_value(record.get(col).unwrap_or(""));
+                &weight
+            }
+            None => value,
+        };
+        // numbers parse the same way with or without whitespace around them,
+        // whether they're decimals or floating point numbers
+        let value = match self.parsing_strategy {
+            ParsingStrategy::Numeric => value.trim(),
+            _ => value,
+        };
+        let joined;
+        let value = match self.pick_col {
+            Some(col) => {
+                joined = format!(
+                    "{}{}{}",
+                    value,
+                    PICK_SEPARATOR,
+                    record.get(col).unwrap_or("")
+                );
+                &joined
+            }
+            None => value,
+        };
+        match &self.parser {
+            Some(parser) => parser(value),
+            None => value.parse(),
+        }
+    }
+
+    fn update_progress(&self, bytes_read: u64, records_read: usize) {
+        if let Some(progress) = &self.progress {
+            match progress.length() {
+                Some(_) => progress.set_position(bytes_read),
+                None => progress.set_position(records_read as u64),
+            }
+        }
+    }
+
     /// Writes the aggregated information into a list of records
     pub fn to_vec(&self) -> Vec<Vec<String>> {
         let mut rows = vec![];
         rows.push(self.get_pivot_header());
+        let cells = self.cells_by_row();
         for row in &self.indexes {
-            rows.push(self.get_pivot_row(row));
+            rows.push(self.get_pivot_row(row, row_cells(&cells, row)));
+        }
+        if self.col_count {
+            rows.push(self.get_count_row());
         }
         rows
     }
 
     /// Writes the aggregated information to standard output.
+    ///
+    /// If there are neither row nor column fields, the pivot table only has a single cell,
+    /// so this writes it as a single `total,<value>` row without a header (followed by the
+    /// `set_col_count` row, if there is one). With more than one function or with
+    /// `set_multi_header`, the header is still written.
+    ///
+    /// If the output is piped into a program that stops reading early (like `head`), this stops
+    /// writing and returns `Ok`. (Rust ignores `SIGPIPE`, so a closed pipe shows up as a
+    /// `BrokenPipe` error. Other platforms don't have `SIGPIPE`, so this is a no-op there.)
     pub fn write_results<W: io::Write>(&mut self, writer: &mut csv::Writer<W>) -> CsvCliResult<()> {
         self.prepare_write()?;
-        writer.write_record(self.get_pivot_header())?;
+        let result = self
+            .output_rows(|row| Ok(writer.write_record(row)?))
+            .and_then(|_| Ok(writer.flush()?));
+        match result {
+            Err(err) if err.is_broken_pipe() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Writes the pivot table in long format, with a record for each cell that has a value:
+    /// the label of its row, the label of its column (under `names_to`) and its value
+    /// (under `values_to`). Empty cells are left out.
+    pub fn write_long<W: io::Write>(
+        &mut self,
+        writer: &mut csv::Writer<W>,
+        names_to: &str,
+        values_to: &str,
+    ) -> CsvCliResult<()> {
+        self.prepare_write()?;
+        let cells = self.cells_by_row();
+        let columns: Vec<&String> = self.columns.iter().collect();
+        let mut write_rows = || -> CsvCliResult<()> {
+            if self.output_header {
+                writer.write_record([
+                    display_label(&self.corner_label, &self.key_separator).as_str(),
+                    names_to,
+                    values_to,
+                ])?;
+            }
+            for row in &self.indexes {
+                let mut row_cells = row_cells(&cells, row).to_vec();
+                row_cells.sort_by_key(|&(idx, _)| idx);
+                for (idx, acc) in row_cells {
+                    if let Some(value) = acc.compute() {
+                        writer.write_record([
+                            display_label(row, &self.key_separator),
+                            display_label(columns[idx], &self.key_separator),
+                            self.format_result(&value.to_string()),
+                        ])?;
+                    }
+                }
+            }
+            Ok(writer.flush()?)
+        };
+        match write_rows() {
+            Err(err) if err.is_broken_pipe() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Writes the size of the pivot table instead of the table itself, as a CSV with the number
+    /// of distinct rows, the number of distinct columns, and the number of cells with values.
+    /// Unlike `write_results`, this works with empty pivot tables.
+    pub fn write_shape<W: io::Write>(&self, writer: &mut csv::Writer<W>) -> CsvCliResult<()> {
+        let cells = self
+            .aggregations
+            .keys()
+            .filter(|(row, col)| self.indexes.contains(row) && self.columns.contains(col))
+            .count();
+        let result = writer
+            .write_record(["rows", "columns", "cells"])
+            .and_then(|_| {
+                writer.write_record([
+                    self.indexes.len().to_string(),
+                    self.columns.len().to_string(),
+                    cells.to_string(),
+                ])
+            })
+            .and_then(|_| Ok(writer.flush()?));
+        match result.map_err(CsvCliError::from) {
+            Err(err) if err.is_broken_pipe() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Writes the aggregated information as a table for reading in a terminal, with the columns
+    /// lined up with spaces. Numeric columns are right-aligned, and other columns are left-aligned.
+    ///
+    /// Cells longer than `max_width` characters are cut off with an ellipsis. Unlike `write_results`,
+    /// this holds the entire table in memory to find the width of each column.
+    pub fn write_pretty<W: io::Write>(
+        &mut self,
+        writer: &mut W,
+        max_width: Option<usize>,
+    ) -> CsvCliResult<()> {
+        self.prepare_write()?;
+        let mut rows = Vec::new();
+        self.output_rows(|row| {
+            rows.push(row);
+            Ok(())
+        })?;
+        let header_rows = match self.index_cols.is_empty() && self.column_cols.is_empty() {
+            true if self.bins.is_none() => 0,
+            _ if !self.output_header => 0,
+            _ if self.multi_header => 2,
+            _ => 1,
+        };
+        let table = pretty_table(&rows, header_rows, max_width, &self.na_rep);
+        let result = writer
+            .write_all(table.as_bytes())
+            .and_then(|_| writer.flush());
+        match result.map_err(CsvCliError::from) {
+            Err(err) if err.is_broken_pipe() => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Passes each row of the output to `write_row`, in order.
+    fn output_rows<F>(&self, mut write_row: F) -> CsvCliResult<()>
+    where
+        F: FnMut(Vec<String>) -> CsvCliResult<()>,
+    {
+        let cells = self.cells_by_row();
+        // a single cell reads like a scalar, so it's written without a header row; with more
+        // than one function, the header says which value is which
+        if self.index_cols.is_empty()
+            && self.column_cols.is_empty()
+            && self.bins.is_none()
+            && self.columns.len() == 1
+            && !self.multi_header
+        {
+            write_row(self.get_pivot_row("total", row_cells(&cells, "total")))?;
+            if self.col_count {
+                write_row(self.get_count_row())?;
+            }
+            return Ok(());
+        }
+        if self.output_header {
+            write_row(self.get_pivot_header())?;
+            if self.multi_header {
+                write_row(self.get_value_header())?;
+            }
+        }
+        let mut running_totals = vec![None; self.columns.len()];
         for row in &self.indexes {
-            writer.write_record(self.get_pivot_row(row))?;
+            let mut record = self.get_pivot_row(row, row_cells(&cells, row));
+            if self.cumulative {
+                let label_width = self.label_width();
+                let cells = &mut record[label_width..label_width + self.columns.len()];
+                accumulate_row(cells, &mut running_totals);
+                // the running totals add up the unrounded, ungrouped values
+                for cell in cells.iter_mut() {
+                    *cell = self.format_result(cell);
+                }
+            }
+            if let Some(keep_raw) = self.row_percent {
+                let label_width = self.label_width();
+                let cells = &mut record[label_width..label_width + self.columns.len()];
+                self.percent_row(cells, keep_raw);
+            }
+            write_row(record)?;
+        }
+        if self.col_count {
+            write_row(self.get_count_row())?;
         }
-        writer.flush()?;
         Ok(())
     }
 
-    /// This prepares a pivot table for output (sorting it
-    /// and verifying that there's more than 1 row)
-    fn prepare_write(&mut self) -> CsvCliResult<()> {
-        if self.columns.is_empty() {
-            return Err(CsvCliError::InvalidConfiguration(
-                "Did not parse any lines before finishing".to_string(),
-            ));
+    /// Describes the records skipped with `set_lenient` (the number of them and the first few
+    /// errors), or returns `None` if every record had the right number of fields.
+    pub fn ragged_summary(&self) -> Option<String> {
+        if self.ragged_records == 0 {
+            return None;
+        }
+        let mut summary = format!(
+            "Skipped {} record(s) with the wrong number of fields:\n",
+            self.ragged_records
+        );
+        for example in &self.ragged_examples {
+            summary.push_str(&format!("  {}\n", example));
+        }
+        if self.ragged_records > self.ragged_examples.len() {
+            summary.push_str(&format!(
+                "  ...and {} more\n",
+                self.ragged_records - self.ragged_examples.len()
+            ));
+        }
+        Some(summary)
+    }
+
+    /// This prepares a pivot table for output (sorting it
+    /// and verifying that there's more than 1 row)
+    fn prepare_write(&mut self) -> CsvCliResult<()> {
+        if self.columns.is_empty() {
+            return Err(CsvCliError::InvalidConfiguration(self.describe_empty()));
+        }
+        self.sort_results();
+        Ok(())
+    }
+
+    /// Explains why nothing went into the pivot table: either there weren't any records,
+    /// or every record was skipped, as empty or because its value couldn't be parsed.
+    fn describe_empty(&self) -> String {
+        let (processed, skipped, failures) = (
+            self.records_processed,
+            self.records_skipped,
+            self.parse_failures,
+        );
+        if processed == 0 && self.keys_skipped > 0 {
+            format!(
+                "All {} record(s) had empty row or column fields, so --null-skip-rows skipped all of them",
+                self.keys_skipped
+            )
+        } else if processed == 0 {
+            "Did not read any records before finishing".to_string()
+        } else if skipped == processed {
+            let (missing, fix) = match (self.skip_null, self.na_numbers.is_empty()) {
+                (_, true) => ("empty values", "remove -e"),
+                (false, false) => ("--na-number values", "remove --na-number"),
+                (true, false) => ("empty or --na-number values", "remove -e and --na-number"),
+            };
+            format!(
+                "All {} record(s) had {} in the values column, so they were all skipped. \
+                 Did you choose the right column? To aggregate them anyway, {}",
+                processed, missing, fix
+            )
+        } else if failures == processed {
+            format!(
+                "None of the {} value(s) could be parsed, so --skip-errors skipped all of them. \
+                 Did you choose the right column and function?",
+                processed
+            )
+        } else {
+            format!(
+                "All {} record(s) were skipped: {} because they were missing values in the values column \
+                 and {} because their values couldn't be parsed",
+                processed, skipped, failures
+            )
+        }
+    }
+
+    /// The number of cells before the values in each row: the row label, the carried field
+    /// and the constant columns
+    fn label_width(&self) -> usize {
+        1 + self.carry.is_some() as usize + self.constants.len()
+    }
+
+    fn get_pivot_header(&self) -> Vec<String> {
+        let mut header = vec![display_label(&self.corner_label, &self.key_separator)];
+        if let Some((_, name)) = &self.carry {
+            header.push(name.to_string());
+        }
+        header.extend(self.constants.iter().map(|(name, _)| name.clone()));
+        for col in &self.columns {
+            let col = match self.keep_padding {
+                true => Cow::Owned(pad_label(col, &self.col_widths)),
+                false => Cow::Borrowed(col.as_str()),
+            };
+            header.push(display_label(&col, &self.key_separator));
+        }
+        if self.row_count {
+            header.push("count".to_string());
+        }
+        header
+    }
+
+    /// The second header row, naming the aggregation above each column
+    fn get_value_header(&self) -> Vec<String> {
+        let mut header = vec![String::new(); self.label_width()];
+        header.extend(self.columns.iter().map(|_| self.value_label.clone()));
+        if self.row_count {
+            header.push(String::new());
+        }
+        header
+    }
+
+    /// Rounds and groups the digits of a result, with `set_precision` and `set_digit_grouping`
+    fn format_result(&self, value: &str) -> String {
+        let value = match self.precision {
+            Some((digits, notation)) => format_number(value, digits, notation),
+            None => value.to_string(),
+        };
+        match &self.digit_grouping {
+            Some((group_separator, decimal_separator)) => {
+                group_digits(&value, group_separator, decimal_separator)
+            }
+            None => value,
+        }
+    }
+
+    /// Replaces each cell in a pivot row (without its labels) with its percentage of the row's
+    /// total, rounded to two decimal places. Cells that aren't numbers (like empty cells) are
+    /// left alone, and a row that adds up to zero doesn't have any percentages.
+    fn percent_row(&self, cells: &mut [String], keep_raw: bool) {
+        let values: Vec<Option<Decimal>> = cells.iter().map(|cell| cell.parse().ok()).collect();
+        let total: Decimal = values.iter().flatten().copied().sum();
+        for (cell, value) in cells.iter_mut().zip(values) {
+            let value = match value {
+                Some(value) => value,
+                None => continue,
+            };
+            let percent = match total == Decimal::new(0, 0) {
+                true => self.na_rep.clone(),
+                false => {
+                    let percent = value * Decimal::from(100) / total;
+                    format!("{}%", percent.round_dp(2).normalize())
+                }
+            };
+            // a row that sums to zero has no percentages, so without --na-rep there's nothing to add
+            *cell = match keep_raw {
+                true if percent.is_empty() => self.format_result(cell),
+                true => format!("{} ({})", self.format_result(cell), percent),
+                false => percent,
+            };
+        }
+    }
+
+    /// The number of records in a cell of the pivot table
+    fn cell_count(&self, row_value: &str, col: &str) -> usize {
+        self.counts
+            .get(&(row_value.to_string(), col.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Groups the cells of the pivot table by row, along with the position of each cell's column.
+    /// This takes one pass over the cells, so writing a row only looks at the cells it has
+    /// rather than looking up every column, which matters when most of the cells are empty.
+    fn cells_by_row(&self) -> HashMap<&str, Vec<(usize, &T)>> {
+        let mut cells: HashMap<&str, Vec<(usize, &T)>> = HashMap::new();
+        for ((row, col), acc) in &self.aggregations {
+            if let Some((idx, _)) = self.columns.get_full(col) {
+                cells.entry(row.as_str()).or_default().push((idx, acc));
+            }
+        }
+        cells
+    }
+
+    /// The `count` row at the bottom, with the number of records in each column
+    fn get_count_row(&self) -> Vec<String> {
+        let mut record = vec!["count".to_string()];
+        if self.carry.is_some() {
+            record.push(String::new());
+        }
+        record.extend(self.constants.iter().map(|(_, value)| value.clone()));
+        let mut col_counts = vec![0; self.columns.len()];
+        for ((row, col), count) in &self.counts {
+            if let (true, Some((idx, _))) = (self.indexes.contains(row), self.columns.get_full(col))
+            {
+                col_counts[idx] += count;
+            }
+        }
+        record.extend(col_counts.iter().map(usize::to_string));
+        if self.row_count {
+            record.push(col_counts.iter().sum::<usize>().to_string());
+        }
+        record
+    }
+
+    /// this gets a single row of the pivot table, given the index value and the row's cells
+    /// (from `cells_by_row`)
+    fn get_pivot_row(&self, row_value: &str, cells: &[(usize, &T)]) -> Vec<String> {
+        let label = match self.keep_padding {
+            true => Cow::Owned(pad_label(row_value, &self.row_widths)),
+            false => Cow::Borrowed(row_value),
+        };
+        let mut record = vec![display_label(&label, &self.key_separator)];
+        if self.carry.is_some() {
+            record.push(
+                self.carried_values
+                    .get(row_value)
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+        }
+        record.extend(self.constants.iter().map(|(_, value)| value.clone()));
+        let mut accumulators: Vec<Option<&T>> = vec![None; self.columns.len()];
+        for &(idx, acc) in cells {
+            accumulators[idx] = Some(acc);
+        }
+        for (col, acc) in self.columns.iter().zip(accumulators) {
+            let cell = acc.and_then(|v| v.compute()).map_or_else(
+                || match self.empty_as_zero {
+                    true => "0".to_string(),
+                    false => self.na_rep.clone(),
+                },
+                |v| {
+                    // running totals and percentages use the unrounded, ungrouped values
+                    let value = match self.cumulative || self.row_percent.is_some() {
+                        true => v.to_string(),
+                        false => self.format_result(&v.to_string()),
+                    };
+                    match &self.count_format {
+                        Some(format) => format
+                            .replace("{value}", &value)
+                            .replace("{count}", &self.cell_count(row_value, col).to_string()),
+                        None => value,
+                    }
+                },
+            );
+            record.push(cell);
+        }
+        if self.row_count {
+            let row_count: usize = cells
+                .iter()
+                .filter_map(|(idx, _)| self.columns.get_index(*idx))
+                .map(|col| self.cell_count(row_value, col))
+                .sum();
+            record.push(row_count.to_string());
+        }
+        record
+    }
+
+    fn add_record(&mut self, record: &csv::StringRecord, line_num: usize) -> CsvCliResult<()> {
+        if self.is_repeated_header(record) {
+            return Ok(());
+        }
+        if !self.melt.is_empty() {
+            return self.add_melted_record(record, line_num);
+        }
+        match self.explode_record(record) {
+            Some(pieces) => {
+                for piece in &pieces {
+                    self.add_single_record(piece, line_num)?;
+                }
+                Ok(())
+            }
+            None => self.add_single_record(record, line_num),
+        }
+    }
+
+    fn is_repeated_header(&self, record: &csv::StringRecord) -> bool {
+        self.repeated_header.as_ref() == Some(record)
+    }
+
+    /// Splits a record into one record for each piece of the field from `set_explode`,
+    /// or a single record with an empty field if there aren't any pieces.
+    /// Returns `None` without `set_explode`, so the record is used as it is.
+    fn explode_record(&self, record: &csv::StringRecord) -> Option<Vec<csv::StringRecord>> {
+        let (col, separator) = self.explode.as_ref()?;
+        let mut pieces: Vec<&str> = record
+            .get(*col)?
+            .split(separator.as_str())
+            .map(str::trim)
+            .filter(|piece| !piece.is_empty())
+            .collect();
+        if pieces.is_empty() {
+            pieces.push("");
+        }
+        let exploded = pieces
+            .into_iter()
+            .map(|piece| {
+                record
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| if i == *col { piece } else { field })
+                    .collect()
+            })
+            .collect();
+        Some(exploded)
+    }
+
+    fn add_single_record(
+        &mut self,
+        record: &csv::StringRecord,
+        line_num: usize,
+    ) -> CsvCliResult<()> {
+        if self.has_empty_keys(record) {
+            self.keys_skipped += 1;
+            return Ok(());
+        }
+        self.records_processed += 1;
+        let value_string = match self.record_value(record, line_num) {
+            Err(err @ CsvCliError::ParsingError { .. }) if self.skip_errors => {
+                self.add_failure(err);
+                return Ok(());
+            }
+            result => result?,
+        };
+        if self.is_missing(&value_string) {
+            self.records_skipped += 1;
+            // the values can't be binned, so only add the labels when there aren't any bins
+            if self.empty_as_zero && self.bins.is_none() {
+                let index_vals = self.get_index_string(record, line_num)?;
+                self.carry_value(&index_vals, record);
+                self.indexes.insert(index_vals);
+                let column_vals =
+                    self.get_column_string(&self.column_cols, &self.col_replace, record);
+                for (label, _) in self.function_labels(&column_vals) {
+                    self.columns.insert(label);
+                }
+            }
+        } else {
+            let index_vals = self.get_index_string(record, line_num)?;
+            match self.add_value(index_vals, record, &value_string, line_num) {
+                Err(err @ CsvCliError::ParsingError { .. }) if self.skip_errors => {
+                    self.add_failure(err);
+                }
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a record should be skipped because of its empty row or column fields,
+    /// with `set_skip_empty_keys`
+    fn has_empty_keys(&self, record: &csv::StringRecord) -> bool {
+        let mode = match self.skip_empty_keys {
+            Some(mode) if !(self.index_cols.is_empty() && self.column_cols.is_empty()) => mode,
+            _ => return false,
+        };
+        let mut keys = self
+            .index_cols
+            .iter()
+            .chain(&self.column_cols)
+            .map(|&col| record.get(col).unwrap_or("").trim().is_empty());
+        match mode {
+            EmptyKeys::Any => keys.any(|empty| empty),
+            EmptyKeys::All => keys.all(|empty| empty),
+        }
+    }
+
+    /// Adds the value of each field from `set_melt` to the column named after the field
+    fn add_melted_record(
+        &mut self,
+        record: &csv::StringRecord,
+        line_num: usize,
+    ) -> CsvCliResult<()> {
+        if self.has_empty_keys(record) {
+            self.keys_skipped += 1;
+            return Ok(());
+        }
+        self.records_processed += 1;
+        let index_vals = self.get_index_string(record, line_num)?;
+        for (col, name) in self.melt.clone() {
+            let value = record.get(col).unwrap_or("");
+            if self.is_missing(value) {
+                continue;
+            }
+            let value = self.localized_value(value).into_owned();
+            match self.update_aggregations(
+                index_vals.clone(),
+                name.clone(),
+                &value,
+                record,
+                line_num,
+                None,
+            ) {
+                Err(err @ CsvCliError::ParsingError { .. }) if self.skip_errors => {
+                    self.add_failure(err);
+                    continue;
+                }
+                result => result?,
+            }
+            self.columns.insert(name);
+        }
+        self.carry_value(&index_vals, record);
+        self.indexes.insert(index_vals);
+        Ok(())
+    }
+
+    /// Counts a record with the wrong number of fields with `set_lenient`, keeping the first
+    /// few errors, or returns any other error from reading a record
+    fn skip_ragged(&mut self, err: CsvCliError) -> CsvCliResult<()> {
+        match err {
+            CsvCliError::UnequalLengths { .. } if self.lenient => {
+                self.ragged_records += 1;
+                if self.ragged_examples.len() < MAX_FAILURE_EXAMPLES {
+                    self.ragged_examples.push(err.to_string());
+                }
+                Ok(())
+            }
+            err => Err(err),
+        }
+    }
+
+    /// Counts a record that couldn't be parsed with --skip-errors, keeping the first few errors
+    fn add_failure(&mut self, err: CsvCliError) {
+        self.parse_failures += 1;
+        if self.failure_examples.len() < MAX_FAILURE_EXAMPLES {
+            self.failure_examples.push(err.to_string());
+        }
+    }
+
+    /// Rewrites a value with a decimal comma so it parses as a number, with `set_decimal_comma`
+    fn localized_value<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        match self.decimal_comma {
+            true => from_decimal_comma(value),
+            false => Cow::Borrowed(value),
+        }
+    }
+
+    /// Returns the value of a record to aggregate: the value in the values column, or the
+    /// result of the expression from `set_expr`. An expression with an empty field has an
+    /// empty value.
+    fn record_value<'a>(
+        &self,
+        record: &'a csv::StringRecord,
+        line_num: usize,
+    ) -> CsvCliResult<Cow<'a, str>> {
+        if !self.coalesce.is_empty() {
+            let value = self
+                .coalesce
+                .iter()
+                .filter_map(|&col| record.get(col))
+                .find(|value| !(value.trim().is_empty() || self.skip_null && is_null_value(value)));
+            return Ok(self.localized_value(value.unwrap_or("")));
+        }
+        let expr = match &self.expr {
+            Some(expr) => expr,
+            None => return Ok(self.localized_value(record.get(self.values_col).unwrap_or(""))),
+        };
+        let (str_to_parse, err) = match expr.eval(record) {
+            Ok(value) => return Ok(Cow::Owned(value.to_string())),
+            Err(EvalError::Empty) => return Ok(Cow::Borrowed("")),
+            Err(EvalError::NotANumber { field, value }) => (
+                value,
+                format!(
+                    "Failed to parse the `{}` field in the --expr expression as numeric",
+                    field
+                ),
+            ),
+            Err(EvalError::DivisionByZero) => (expr.to_string(), "Divided by zero".to_string()),
+            Err(EvalError::Overflow) => (expr.to_string(), "The result was too large".to_string()),
+        };
+        Err(CsvCliError::ParsingError {
+            line_num,
+            str_to_parse,
+            err,
+        })
+    }
+
+    /// Adds a value to the cell in the given row, only adding the row and column
+    /// to the pivot table if the value can be parsed
+    fn add_value(
+        &mut self,
+        index_vals: String,
+        record: &csv::StringRecord,
+        value_string: &str,
+        line_num: usize,
+    ) -> CsvCliResult<()> {
+        let mut column_vals = self.get_column_string(&self.column_cols, &self.col_replace, record);
+        if let Some(bins) = &self.bins {
+            let value = value_string
+                .parse::<DecimalWrapper>()
+                .map_err(|_| CsvCliError::ParsingError {
+                    line_num,
+                    str_to_parse: value_string.to_string(),
+                    err: "Failed to parse as numeric".to_string(),
+                })?
+                .item;
+            let bucket = bins.label(value);
+            column_vals = match self.column_cols.is_empty() {
+                true => bucket.to_string(),
+                false => format!("{}{}{}", column_vals, FIELD_SEPARATOR, bucket),
+            };
+        }
+        let labels = self.function_labels(&column_vals);
+        for (label, function) in &labels {
+            self.update_aggregations(
+                index_vals.clone(),
+                label.clone(),
+                value_string,
+                record,
+                line_num,
+                *function,
+            )?;
+        }
+        if let Some((row, column)) = &self.explain {
+            if display_label(&index_vals, &self.key_separator) == *row
+                && display_label(&column_vals, &self.key_separator) == *column
+            {
+                self.explained.push(record.clone());
+            }
+        }
+        self.carry_value(&index_vals, record);
+        self.indexes.insert(index_vals);
+        for (label, _) in labels {
+            self.columns.insert(label);
+        }
+        Ok(())
+    }
+
+    /// The labels of the cells a value goes into in a column: the column itself, or with
+    /// `set_functions`, a column for each function (with the name of the function after the column's label)
+    fn function_labels(&self, column_vals: &str) -> Vec<(String, Option<Function>)> {
+        if self.functions.is_empty() {
+            return vec![(column_vals.to_string(), None)];
+        }
+        self.functions
+            .iter()
+            .map(|&function| {
+                let label = match self.column_cols.is_empty() && self.bins.is_none() {
+                    true => function.to_string(),
+                    false => format!("{}{}{}", column_vals, FIELD_SEPARATOR, function),
+                };
+                (label, Some(function))
+            })
+            .collect()
+    }
+
+    /// Keeps the first value of the carried field in a row, noting the row if the value changes
+    fn carry_value(&mut self, index_vals: &str, record: &csv::StringRecord) {
+        let carry_col = match &self.carry {
+            Some((col, _)) => *col,
+            None => return,
+        };
+        let value = record.get(carry_col).unwrap_or("");
+        match self.carried_values.get(index_vals) {
+            Some(first) if first != value => {
+                self.carry_conflicts.insert(index_vals.to_string());
+            }
+            Some(_) => {}
+            None => {
+                self.carried_values
+                    .insert(index_vals.to_string(), value.to_string());
+            }
+        }
+    }
+
+    /// Whether a value should be skipped, either because it's empty (with `skip_null`)
+    /// or because it's one of the sentinel numbers
+    fn is_missing(&self, value_string: &str) -> bool {
+        if self.skip_null && is_null_value(value_string) {
+            return true;
+        }
+        if self.na_numbers.is_empty() || self.parsing_strategy != ParsingStrategy::Numeric {
+            return false;
+        }
+        value_string
+            .parse::<DecimalWrapper>()
+            .map(|value| self.na_numbers.contains(&value.item))
+            .unwrap_or(false)
+    }
+
+    /// Gets the label of a row, replacing the first row field with its time bucket
+    /// if there is one
+    fn get_index_string(
+        &self,
+        record: &csv::StringRecord,
+        line_num: usize,
+    ) -> CsvCliResult<String> {
+        let bucket = match (self.row_bucket, self.index_cols.first()) {
+            (Some(bucket), Some(&date_col)) => (bucket, date_col),
+            _ => return Ok(self.get_column_string(&self.index_cols, &self.row_replace, record)),
+        };
+        let date_str = record.get(bucket.1).unwrap();
+        let date = match &self.date_format {
+            Some(format) => CustomDateObject::parse_with_format(date_str, format),
+            None => date_str.parse::<CustomDateObject>(),
+        }
+        .map_err(|_| CsvCliError::ParsingError {
+            line_num,
+            str_to_parse: date_str.to_string(),
+            err: format!(
+                "Could not parse the row field as a date with {}",
+                match &self.date_format {
+                    Some(format) => format.describe(),
+                    None => describe_date_formats(),
+                }
+            ),
+        })?;
+        let mut labels = vec![bucket.0.label(&date)];
+        for column in &self.index_cols[1..] {
+            let value = record.get(*column).unwrap();
+            labels.push(replace_label(&self.row_replace, value).into_owned());
+        }
+        Ok(labels.join(FIELD_SEPARATOR))
+    }
+
+    fn get_column_string(
+        &self,
+        columns: &[usize],
+        replace: &Option<(Regex, String)>,
+        record: &csv::StringRecord,
+    ) -> String {
+        if columns.is_empty() {
+            return "total".to_string();
+        }
+        let mut column_records = Vec::new();
+        for column in columns {
+            let string_val = record.get(*column).unwrap();
+            column_records.push(replace_label(replace, string_val).into_owned());
+        }
+        column_records.join(FIELD_SEPARATOR)
+    }
+
+    fn describe_err(&self) -> String {
+        match self.parsing_strategy {
+            ParsingStrategy::Text => "Failed to parse as text".to_string(),
+            ParsingStrategy::Numeric => "Failed to parse as numeric".to_string(),
+            ParsingStrategy::Boolean => {
+                "Failed to parse as a boolean (like true/false, yes/no or 1/0)".to_string()
+            }
+            ParsingStrategy::Date => {
+                let global_formats;
+                let formats = match &self.date_format {
+                    Some(format) => format.formats(),
+                    None => {
+                        global_formats = INPUT_DATE_FORMAT.lock().unwrap();
+                        &global_formats[..]
+                    }
+                };
+                match formats.len() {
+                    1 => format!(
+                        "Could not parse as a datetime with the `{0}` format, or as a date (without a time) \
+                         with the `{0}` format. Hint: you can set the date format with the -F option",
+                        formats[0]
+                    ),
+                    _ => format!(
+                        "Could not parse as a datetime or as a date (without a time) with any of the `{}` \
+                         formats. Hint: you can set the date formats with the -F option",
+                        formats.join("`, `")
+                    ),
+                }
+            }
+        }
+    }
+
+    fn update_aggregations(
+        &mut self,
+        indexname: String,
+        columnname: String,
+        input_str: &str,
+        record: &csv::StringRecord,
+        line_num: usize,
+        function: Option<Function>,
+    ) -> CsvCliResult<()> {
+        let parsed_val =
+            self.parse_input(input_str, record)
+                .map_err(|_| match self.weight_col {
+                    Some(col) => CsvCliError::ParsingError {
+                        line_num,
+                        str_to_parse: record.get(col).unwrap_or("").to_string(),
+                        err: "Failed to parse the weight as numeric".to_string(),
+                    },
+                    None => CsvCliError::ParsingError {
+                        line_num,
+                        str_to_parse: input_str.to_string(),
+                        err: self.describe_err(),
+                    },
+                })?;
+        if self.check_precision && self.precision_loss.is_none() && loses_precision(input_str) {
+            self.precision_loss = Some((line_num, input_str.to_string()));
+        }
+
+        if self.count_format.is_some() || self.row_count || self.col_count {
+            *self
+                .counts
+                .entry((indexname.clone(), columnname.clone()))
+                .or_insert(0) += 1;
+        }
+        match self.aggregations.entry((indexname, columnname)) {
+            Entry::Occupied(entry) if self.unique_cells => {
+                let (index, column) = entry.key();
+                return Err(CsvCliError::ParsingError {
+                    line_num,
+                    str_to_parse: input_str.to_string(),
+                    err: format!(
+                        "The cell in row `{}` and column `{}` already has a value \
+                         (--on-conflict can keep the first or last value or sum them)",
+                        index, column
+                    ),
+                });
+            }
+            Entry::Occupied(entry) => {
+                entry.into_mut().update(parsed_val);
+            }
+            Entry::Vacant(entry) => {
+                let accumulator = match (function, &self.function_initializer, &self.initializer) {
+                    (Some(function), Some(initializer), _) => initializer(parsed_val, function),
+                    (_, _, Some(initializer)) => initializer(parsed_val),
+                    _ => T::new(parsed_val),
+                };
+                entry.insert(accumulator);
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Puts the functions from `set_functions` back in the order they were listed within each column,
+    /// keeping the columns themselves where sorting put them
+    fn sort_functions(&mut self) {
+        let names: Vec<String> = self.functions.iter().map(Function::to_string).collect();
+        let split = |label: &str| -> (String, usize) {
+            let (column, name) = label.rsplit_once(FIELD_SEPARATOR).unwrap_or(("", label));
+            let position = names.iter().position(|n| n == name).unwrap_or(0);
+            (column.to_string(), position)
+        };
+        let mut column_positions: HashMap<String, usize> = HashMap::new();
+        for label in &self.columns {
+            let next = column_positions.len();
+            column_positions.entry(split(label).0).or_insert(next);
+        }
+        self.columns.sort_by(|a, b| {
+            let (a_column, a_position) = split(a);
+            let (b_column, b_position) = split(b);
+            (column_positions[&a_column], a_position)
+                .cmp(&(column_positions[&b_column], b_position))
+        });
+    }
+
+    /// The total of the (numeric) results in each column, for `set_sort_cols_by_total`
+    fn column_totals(&self) -> HashMap<String, Decimal> {
+        let mut totals: HashMap<String, Decimal> = self
+            .columns
+            .iter()
+            .map(|col| (col.clone(), Decimal::new(0, 0)))
+            .collect();
+        for ((row, col), acc) in &self.aggregations {
+            let value = acc
+                .compute()
+                .and_then(|v| v.to_string().parse::<Decimal>().ok());
+            if let (true, Some(total), Some(value)) =
+                (self.indexes.contains(row), totals.get_mut(col), value)
+            {
+                *total += value;
+            }
+        }
+        totals
+    }
+
+    /// Sorts the rows and columns. The labels are unique, so there aren't any ties, and labels from
+    /// multiple fields sort field by field, since `FIELD_SEPARATOR` comes before any printable character.
+    fn sort_results(&mut self) {
+        if self.zero_pad {
+            self.row_widths = pad_widths(&self.indexes);
+            if self.bins.is_none() {
+                self.col_widths = pad_widths(&self.columns);
+            }
+        }
+        let (row_widths, col_widths) = (&self.row_widths, &self.col_widths);
+        if self.sort_cols_by_total {
+            let totals = self.column_totals();
+            self.columns.sort_by(|a, b| totals[b].cmp(&totals[a]));
+        }
+        match (self.column_order, &self.bins) {
+            (OutputOrder::Ascending, None) if self.zero_pad => self
+                .columns
+                .sort_by(|a, b| pad_label(a, col_widths).cmp(&pad_label(b, col_widths))),
+            (OutputOrder::Descending, None) if self.zero_pad => self
+                .columns
+                .sort_by(|a, b| pad_label(b, col_widths).cmp(&pad_label(a, col_widths))),
+            (OutputOrder::Ascending, None) => self.columns.sort(),
+            (OutputOrder::Descending, None) => self.columns.sort_by(|a, b| b.cmp(a)),
+            // sorts buckets numerically (so `2..10` comes before `10..100`)
+            (OutputOrder::Ascending, Some(bins)) => self
+                .columns
+                .sort_by(|a, b| bins.sort_key(a).cmp(&bins.sort_key(b))),
+            (OutputOrder::Descending, Some(bins)) => self
+                .columns
+                .sort_by(|a, b| bins.sort_key(b).cmp(&bins.sort_key(a))),
+            (OutputOrder::IndexOrder, _) => {}
+        };
+        match self.row_order {
+            OutputOrder::Ascending if self.zero_pad => self
+                .indexes
+                .sort_by(|a, b| pad_label(a, row_widths).cmp(&pad_label(b, row_widths))),
+            OutputOrder::Descending if self.zero_pad => self
+                .indexes
+                .sort_by(|a, b| pad_label(b, row_widths).cmp(&pad_label(a, row_widths))),
+            OutputOrder::Ascending => self.indexes.sort(),
+            OutputOrder::Descending => self.indexes.sort_by(|a, b| b.cmp(a)),
+            OutputOrder::IndexOrder => {}
+        };
+        if !self.functions.is_empty() {
+            self.sort_functions();
+        }
+    }
+}
+
+/// The results of accumulators that are numbers (like counts and sums), which
+/// `Aggregator::to_matrix` returns as `f64`s.
+pub trait NumericOutput {
+    fn as_f64(&self) -> f64;
+}
+
+impl NumericOutput for usize {
+    fn as_f64(&self) -> f64 {
+        *self as f64
+    }
+}
+
+impl NumericOutput for f64 {
+    fn as_f64(&self) -> f64 {
+        *self
+    }
+}
+
+impl NumericOutput for AnyOutput {
+    fn as_f64(&self) -> f64 {
+        match self {
+            AnyOutput::Count(count) => *count as f64,
+            AnyOutput::Decimal(value) => value.to_f64().unwrap_or(f64::NAN),
+            AnyOutput::Float(value) => *value,
+        }
+    }
+}
+
+impl NumericOutput for DecimalWrapper {
+    fn as_f64(&self) -> f64 {
+        self.item.to_f64().unwrap_or(f64::NAN)
+    }
+}
+
+impl<T, O> Aggregator<T, CustomDateObject, O>
+where
+    T: Accumulate<CustomDateObject, O>,
+    O: std::fmt::Display,
+{
+    /// Parses dates with `format` instead of the global format from `parsing::set_date_format`,
+    /// so aggregators with different formats can run at the same time. This also applies
+    /// to the row field with `set_row_bucket`.
+    pub fn set_date_format(&mut self, format: DateFormat) {
+        let parse_format = format.clone();
+        self.parser = Some(Box::new(move |s| {
+            CustomDateObject::parse_with_format(s, &parse_format)
+        }));
+        self.date_format = Some(format);
+    }
+}
+
+impl<T, O> Aggregator<T, Picked<CustomDateObject>, O>
+where
+    T: Accumulate<Picked<CustomDateObject>, O>,
+    O: std::fmt::Display,
+{
+    /// Parses dates with `format` instead of the global format, like `set_date_format`
+    /// for aggregators of dates alone
+    pub fn set_date_format(&mut self, format: DateFormat) {
+        let parse_format = format.clone();
+        self.parser = Some(Box::new(move |s| {
+            Picked::parse_with(s, |value| {
+                CustomDateObject::parse_with_format(value, &parse_format)
+            })
+            .ok_or(())
+        }));
+        self.date_format = Some(format);
+    }
+}
+
+/// The row labels, the column labels, and the cells of a pivot table from `Aggregator::to_matrix`
+pub type Matrix = (Vec<String>, Vec<String>, Vec<Vec<Option<f64>>>);
+
+impl<T, I, O> Aggregator<T, I, O>
+where
+    T: Accumulate<I, O>,
+    I: std::str::FromStr,
+    O: std::fmt::Display + NumericOutput,
+{
+    /// Returns the pivot table as numbers, for passing to numeric libraries without parsing
+    /// the output of `to_vec`: the row labels, the column labels, and a row of cells for each
+    /// row label (with a cell for each column label), where empty cells are `None`.
+    ///
+    /// Like `to_vec`, this keeps the rows and columns in their current order and only includes the
+    /// results themselves, without rounding, running totals or counts. It only works with
+    /// accumulators whose results are numbers.
+    pub fn to_matrix(&self) -> Matrix {
+        let labels = |labels: &IndexSet<String>| {
+            labels
+                .iter()
+                .map(|label| display_label(label, &self.key_separator))
+                .collect()
+        };
+        let cells = self.cells_by_row();
+        let matrix = self
+            .indexes
+            .iter()
+            .map(|row| {
+                let mut values = vec![None; self.columns.len()];
+                for (idx, acc) in row_cells(&cells, row) {
+                    values[*idx] = acc.compute().map(|v| v.as_f64());
+                }
+                values
+            })
+            .collect();
+        (labels(&self.indexes), labels(&self.columns), matrix)
+    }
+}
+
+/// Converts a row or column label into the label displayed in the output, joining the values
+/// of multiple fields with `separator` instead of `FIELD_SEPARATOR`.
+fn display_label(label: &str, separator: &str) -> String {
+    label.replace(FIELD_SEPARATOR, separator)
+}
+
+/// Finds the widest value made entirely of digits in each field of the labels, for `pad_label`.
+fn pad_widths(labels: &IndexSet<String>) -> Vec<usize> {
+    let mut widths = Vec::new();
+    for label in labels {
+        for (i, value) in label.split(FIELD_SEPARATOR).enumerate() {
+            if widths.len() <= i {
+                widths.push(0);
+            }
+            if is_digits(value) {
+                widths[i] = widths[i].max(value.len());
+            }
         }
-        self.sort_results();
-        Ok(())
     }
+    widths
+}
 
-    fn get_pivot_header(&self) -> Vec<String> {
-        let mut header = vec![String::new()];
-        for col in &self.columns {
-            header.push(col.to_string());
+/// Left-pads the values of a label that are made entirely of digits with zeros, to the widths
+/// from `pad_widths`.
+fn pad_label(label: &str, widths: &[usize]) -> String {
+    label
+        .split(FIELD_SEPARATOR)
+        .enumerate()
+        .map(|(i, value)| match widths.get(i) {
+            Some(&width) if is_digits(value) => format!("{:0>width$}", value, width = width),
+            _ => value.to_string(),
+        })
+        .collect::<Vec<String>>()
+        .join(FIELD_SEPARATOR)
+}
+
+fn is_digits(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Rounds a displayed result to `digits` digits, leaving it alone if it isn't a number.
+/// Fixed notation rounds decimals exactly, and general notation works like `%g` in C.
+fn format_number(value: &str, digits: usize, notation: Notation) -> String {
+    match notation {
+        Notation::Fixed => {
+            if let Ok(num) = value.parse::<Decimal>() {
+                // `round_dp` rounds ties to the even digit, like the formatting of `f64`s below
+                format!("{:.*}", digits, num.round_dp(digits as u32))
+            } else if let Ok(num) = value.parse::<f64>() {
+                format!("{:.*}", digits, num)
+            } else {
+                value.to_string()
+            }
+        }
+        Notation::General => {
+            let num = match value.parse::<f64>() {
+                Ok(num) if num.is_finite() => num,
+                _ => return value.to_string(),
+            };
+            // %g treats a precision of 0 as 1
+            let digits = digits.max(1);
+            let scientific = format!("{:.*e}", digits - 1, num);
+            let (mantissa, exponent) = scientific.split_once('e').unwrap();
+            let exponent: i32 = exponent.parse().unwrap();
+            if exponent < -4 || exponent >= digits as i32 {
+                format!("{}e{}", trim_zeros(mantissa), exponent)
+            } else {
+                let decimals = (digits as i32 - 1 - exponent) as usize;
+                trim_zeros(&format!("{:.*}", decimals, num)).to_string()
+            }
         }
-        header
     }
+}
 
-    /// this gets a single row of the pivot table, given the index value
-    fn get_pivot_row(&self, row_value: &str) -> Vec<String> {
-        let mut record = vec![row_value.to_string()];
-        for col in &self.columns {
-            let cell = self
-                .aggregations
-                .get(&(row_value.to_string(), col.to_string()))
-                .map_or(String::new(), |v| {
-                    v.compute()
-                        .map(|v| v.to_string())
-                        .unwrap_or_else(String::new)
-                });
-            record.push(cell);
+/// Separates the thousands in a number with `group_separator` and replaces its decimal point with
+/// `decimal_separator`, leaving it alone if it isn't a number (or is in scientific notation).
+fn group_digits(value: &str, group_separator: &str, decimal_separator: &str) -> String {
+    if value.parse::<f64>().is_err() || value.contains(|c: char| c.is_alphabetic()) {
+        return value.to_string();
+    }
+    let (sign, unsigned) = match value.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", value),
+    };
+    let (integer, decimals) = match unsigned.split_once('.') {
+        Some((integer, decimals)) => (integer, Some(decimals)),
+        None => (unsigned, None),
+    };
+    let mut grouped = String::from(sign);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push_str(group_separator);
         }
-        record
+        grouped.push(digit);
     }
+    if let Some(decimals) = decimals {
+        grouped.push_str(decimal_separator);
+        grouped.push_str(decimals);
+    }
+    grouped
+}
 
-    fn add_record(&mut self, record: &csv::StringRecord, line_num: usize) -> CsvCliResult<()> {
-        let value_string = record.get(self.values_col).unwrap();
-        if !(self.skip_null && EMPTY_VALUES.contains(value_string.to_ascii_lowercase().as_str())) {
-            let index_vals = self.get_column_string(&self.index_cols, record);
-            self.indexes.insert(index_vals.clone());
-            let column_vals = self.get_column_string(&self.column_cols, record);
-            self.columns.insert(column_vals.clone());
-            self.update_aggregations(index_vals, column_vals, value_string, line_num)?;
-        }
-        Ok(())
+/// Applies the regular expression and replacement from `set_row_replace` or `set_col_replace`
+/// to the value of a row or column field.
+fn replace_label<'a>(replace: &Option<(Regex, String)>, value: &'a str) -> Cow<'a, str> {
+    match replace {
+        Some((pattern, replacement)) => pattern.replace_all(value, replacement.as_str()),
+        None => Cow::Borrowed(value),
     }
+}
 
-    fn get_column_string(&self, columns: &[usize], record: &csv::StringRecord) -> String {
-        if columns.is_empty() {
-            return "total".to_string();
-        }
-        let mut column_records = Vec::new();
-        for column in columns {
-            let string_val = record.get(*column).unwrap();
-            column_records.push(string_val.to_string());
-        }
-        column_records.join(FIELD_SEPARATOR)
+/// Removes trailing zeros after the decimal point (and the decimal point, if nothing is left after it).
+fn trim_zeros(num: &str) -> &str {
+    if num.contains('.') {
+        num.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        num
     }
+}
 
-    fn describe_err(&self) -> String {
-        match self.parsing_strategy {
-            ParsingStrategy::Text => "Failed to parse as text".to_string(),
-            ParsingStrategy::Numeric => "Failed to parse as numeric".to_string(),
-            ParsingStrategy::Date => format!(
-                "Could not parse as date with {} format",
-                INPUT_DATE_FORMAT.lock().unwrap()
-            ),
-            _ => "Generic parsing error".to_string(),
+/// Lines up the cells of `rows` in columns separated by two spaces, cutting off cells longer than
+/// `max_width` characters (down to just the ellipsis, if `max_width` is 0). Columns where every cell (other than the first `header_rows` rows,
+/// empty cells and `na_rep`) is a number are right-aligned.
+fn pretty_table(
+    rows: &[Vec<String>],
+    header_rows: usize,
+    max_width: Option<usize>,
+    na_rep: &str,
+) -> String {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| match max_width {
+                    Some(width) if cell.chars().count() > width => {
+                        let mut cut: String = cell.chars().take(width.saturating_sub(1)).collect();
+                        cut.push('…');
+                        cut
+                    }
+                    _ => cell.to_string(),
+                })
+                .collect()
+        })
+        .collect();
+    let num_cols = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; num_cols];
+    let mut numeric = vec![true; num_cols];
+    for (i, row) in rows.iter().enumerate() {
+        for (j, cell) in row.iter().enumerate() {
+            widths[j] = widths[j].max(cell.chars().count());
+            if i >= header_rows && !cell.is_empty() && cell != na_rep && !looks_numeric(cell) {
+                numeric[j] = false;
+            }
         }
     }
-
-    fn update_aggregations(
-        &mut self,
-        indexname: String,
-        columnname: String,
-        input_str: &str,
-        line_num: usize,
-    ) -> CsvCliResult<()> {
-        let parsed_val = input_str.parse().or_else(|_| {
-            Err(CsvCliError::ParsingError {
-                line_num,
-                str_to_parse: input_str.to_string(),
-                err: self.describe_err(),
+    let mut table = String::new();
+    for row in &rows {
+        let cells: Vec<String> = row
+            .iter()
+            .enumerate()
+            .map(|(j, cell)| match numeric[j] {
+                true => format!("{:>width$}", cell, width = widths[j]),
+                false => format!("{:<width$}", cell, width = widths[j]),
             })
-        })?;
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
 
-        match self.aggregations.entry((indexname, columnname)) {
-            Entry::Occupied(entry) => {
-                entry.into_mut().update(parsed_val);
-            }
-            Entry::Vacant(entry) => {
-                entry.insert(T::new(parsed_val));
-            }
-        };
+/// Whether a cell of the output holds a number, including numbers with separators from --group-digits
+fn looks_numeric(cell: &str) -> bool {
+    cell.chars().any(|c| c.is_ascii_digit())
+        && cell.replace([',', '_', ' '], "").parse::<f64>().is_ok()
+}
 
-        Ok(())
-    }
+/// The cells of a row from `Aggregator::cells_by_row`, which doesn't have rows without any cells.
+fn row_cells<'a, T>(
+    cells: &'a HashMap<&str, Vec<(usize, &'a T)>>,
+    row: &str,
+) -> &'a [(usize, &'a T)] {
+    cells.get(row).map_or(&[], Vec::as_slice)
+}
 
-    fn sort_results(&mut self) {
-        match self.column_order {
-            OutputOrder::Ascending => self.columns.sort(),
-            OutputOrder::Descending => self.columns.sort_by(|a, b| b.cmp(a)),
-            OutputOrder::IndexOrder => {}
-        };
-        match self.row_order {
-            OutputOrder::Ascending => self.indexes.sort(),
-            OutputOrder::Descending => self.indexes.sort_by(|a, b| b.cmp(a)),
-            OutputOrder::IndexOrder => {}
-        };
+/// Replaces each cell in a pivot row (without its labels) with the running total of its column.
+/// Empty cells carry forward the running total from the rows above them.
+fn accumulate_row(cells: &mut [String], running_totals: &mut [Option<Decimal>]) {
+    for (cell, total) in cells.iter_mut().zip(running_totals.iter_mut()) {
+        if let Ok(value) = cell.parse::<Decimal>() {
+            *total = Some(total.unwrap_or_default() + value);
+        }
+        if let Some(total) = total {
+            *cell = total.to_string();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::aggfunc::Count;
+    use crate::aggfunc::{Count, First, Maximum, Mean, MinMax, Mode, StdDev, Sum};
     use csv::StringRecord;
     use indexmap::IndexSet;
 
@@ -278,8 +2496,8 @@ mod tests {
         let csv_record = StringRecord::from(record_vec);
         agg.add_record(&csv_record, 0).unwrap();
         let expected_record = (
-            "Columbus_<sep>_OH".to_string(),
-            "Blue Jackets_<sep>_Hockey".to_string(),
+            format!("Columbus{}OH", FIELD_SEPARATOR),
+            format!("Blue Jackets{}Hockey", FIELD_SEPARATOR),
         );
         assert!(agg.aggregations.contains_key(&expected_record));
         assert_eq!(
@@ -287,10 +2505,10 @@ mod tests {
             Some(1)
         );
         let mut expected_indexes = IndexSet::new();
-        expected_indexes.insert("Columbus_<sep>_OH".to_string());
+        expected_indexes.insert(format!("Columbus{}OH", FIELD_SEPARATOR));
         assert_eq!(agg.indexes, expected_indexes);
         let mut expected_columns = IndexSet::new();
-        expected_columns.insert("Blue Jackets_<sep>_Hockey".to_string());
+        expected_columns.insert(format!("Blue Jackets{}Hockey", FIELD_SEPARATOR));
         assert_eq!(agg.columns, expected_columns);
     }
 
@@ -330,6 +2548,22 @@ mod tests {
         assert!(agg.write_results(&mut writer).is_err());
     }
 
+    #[test]
+    fn test_describe_empty() {
+        let mut agg = setup_simple();
+        assert_eq!(
+            agg.describe_empty(),
+            "Did not read any records before finishing"
+        );
+        agg.skip_null = true;
+        agg.add_record(&StringRecord::from(vec!["a", "", "c", "d", "e"]), 0)
+            .unwrap();
+        assert!(agg.columns.is_empty());
+        assert!(agg.describe_empty().contains("remove -e"));
+        agg.set_na_numbers(vec![Decimal::new(-999, 0)]);
+        assert!(agg.describe_empty().contains("remove -e and --na-number"));
+    }
+
     #[test]
     fn test_vector_output() {
         let mut agg: Aggregator<Count<String>, String, usize> = Aggregator::new(
@@ -341,12 +2575,12 @@ mod tests {
             OutputOrder::Ascending,
             ParsingStrategy::Text,
         );
-        let data = vec![
+        let data = [
             StringRecord::from(vec!["example".to_string(), "record".to_string()]),
             StringRecord::from(vec!["example".to_string(), "again".to_string()]),
         ];
         for (count, record) in data.iter().enumerate() {
-            agg.add_record(&record, count).unwrap();
+            agg.add_record(record, count).unwrap();
         }
         agg.prepare_write().unwrap();
         let results = agg.to_vec();
@@ -356,4 +2590,554 @@ mod tests {
         ];
         assert_eq!(results, expected);
     }
+
+    #[test]
+    fn test_special_characters_round_trip() {
+        let mut agg: Aggregator<Mode<String>, String, String> = Aggregator::new(
+            vec![0],
+            vec![1],
+            2,
+            false,
+            OutputOrder::IndexOrder,
+            OutputOrder::IndexOrder,
+            ParsingStrategy::Text,
+        );
+        let data = [
+            StringRecord::from(vec!["say \"hi\"", "a,b", "line1\nline2"]),
+            StringRecord::from(vec!["plain", "c\r\nd", "\"quoted, with comma\""]),
+        ];
+        for (count, record) in data.iter().enumerate() {
+            agg.add_record(record, count).unwrap();
+        }
+        let mut writer = csv::Writer::from_writer(vec![]);
+        agg.write_results(&mut writer).unwrap();
+        let output = writer.into_inner().unwrap();
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(output.as_slice());
+        let results: Vec<Vec<String>> = rdr
+            .records()
+            .map(|r| r.unwrap().iter().map(String::from).collect())
+            .collect();
+        let expected = vec![
+            vec!["", "a,b", "c\r\nd"],
+            vec!["say \"hi\"", "line1\nline2", ""],
+            vec!["plain", "", "\"quoted, with comma\""],
+        ];
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_minmax_output_is_single_cell() {
+        let mut agg: Aggregator<MinMax<String>, String, String> = Aggregator::new(
+            vec![],
+            vec![],
+            0,
+            false,
+            OutputOrder::IndexOrder,
+            OutputOrder::Ascending,
+            ParsingStrategy::Text,
+        );
+        for (count, val) in vec!["b, \"2\"", "a,\n1"].into_iter().enumerate() {
+            agg.add_record(&StringRecord::from(vec![val]), count)
+                .unwrap();
+        }
+        let mut writer = csv::Writer::from_writer(vec![]);
+        agg.write_results(&mut writer).unwrap();
+        let output = writer.into_inner().unwrap();
+        let mut rdr = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(output.as_slice());
+        let record = rdr.records().next().unwrap().unwrap();
+        assert_eq!(record.len(), 2);
+        assert_eq!(&record[1], "a,\n1 - b, \"2\"");
+    }
+
+    #[test]
+    fn test_zero_dimension_output_is_scalar() {
+        let mut agg: Aggregator<Count<String>, String, usize> = Aggregator::new(
+            vec![],
+            vec![],
+            0,
+            false,
+            OutputOrder::IndexOrder,
+            OutputOrder::Ascending,
+            ParsingStrategy::Text,
+        );
+        for (count, val) in ["a", "b", "c"].iter().enumerate() {
+            agg.add_record(&StringRecord::from(vec![*val]), count)
+                .unwrap();
+        }
+        let mut writer = csv::Writer::from_writer(vec![]);
+        agg.write_results(&mut writer).unwrap();
+        let output = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(output, "total,3\n");
+    }
+
+    #[test]
+    fn test_every_sort_combination() {
+        let orders = || {
+            vec![
+                (OutputOrder::Ascending, vec!["a", "b", "c"]),
+                (OutputOrder::Descending, vec!["c", "b", "a"]),
+                (OutputOrder::IndexOrder, vec!["b", "c", "a"]),
+            ]
+        };
+        for (row_order, expected_rows) in orders() {
+            for (column_order, expected_cols) in orders() {
+                let mut agg: Aggregator<Count<String>, String, usize> = Aggregator::new(
+                    vec![0],
+                    vec![1],
+                    0,
+                    false,
+                    row_order,
+                    column_order,
+                    ParsingStrategy::Text,
+                );
+                for (count, val) in ["b", "c", "a"].iter().enumerate() {
+                    agg.add_record(&StringRecord::from(vec![*val, *val]), count)
+                        .unwrap();
+                }
+                agg.prepare_write().unwrap();
+                let results = agg.to_vec();
+                let rows: Vec<&str> = results[1..].iter().map(|row| row[0].as_str()).collect();
+                assert_eq!(rows, expected_rows);
+                assert_eq!(results[0][1..].to_vec(), expected_cols);
+            }
+        }
+    }
+
+    #[test]
+    fn test_late_columns() {
+        // `z` only shows up in the last row, and `y` never shows up in `c`
+        let data = [
+            ("b", "x", "1"),
+            ("a", "x", "2"),
+            ("b", "y", "3"),
+            ("a", "y", "5"),
+            ("c", "z", "4"),
+        ];
+        let orders = |labels: [&'static str; 3]| {
+            let mut ascending = labels.to_vec();
+            ascending.sort_unstable();
+            let descending = ascending.iter().rev().copied().collect();
+            vec![
+                (OutputOrder::Ascending, ascending),
+                (OutputOrder::Descending, descending),
+                (OutputOrder::IndexOrder, labels.to_vec()),
+            ]
+        };
+        for (row_order, expected_rows) in orders(["b", "a", "c"]) {
+            for (column_order, expected_cols) in orders(["x", "y", "z"]) {
+                let mut agg: Aggregator<Sum<DecimalWrapper>, DecimalWrapper, DecimalWrapper> =
+                    Aggregator::new(
+                        vec![0],
+                        vec![1],
+                        2,
+                        false,
+                        row_order,
+                        column_order,
+                        ParsingStrategy::Numeric,
+                    );
+                for (count, (row, col, val)) in data.iter().enumerate() {
+                    agg.add_record(&StringRecord::from(vec![*row, *col, *val]), count)
+                        .unwrap();
+                }
+                agg.prepare_write().unwrap();
+                let results = agg.to_vec();
+                assert_eq!(results[0][1..].to_vec(), expected_cols);
+                assert_eq!(results.len(), expected_rows.len() + 1);
+                for (result, row) in results[1..].iter().zip(&expected_rows) {
+                    assert_eq!(result[0], *row);
+                    for (cell, col) in result[1..].iter().zip(&expected_cols) {
+                        let expected = data
+                            .iter()
+                            .find(|(r, c, _)| r == row && c == col)
+                            .map_or("", |(_, _, val)| *val);
+                        assert_eq!(cell, expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_bins() {
+        let bins: Bins = "0, 10,1e2".parse().unwrap();
+        assert_eq!(bins.label(Decimal::new(-1, 0)), "..0");
+        assert_eq!(bins.label(Decimal::new(0, 0)), "0..10");
+        assert_eq!(bins.label(Decimal::new(999, 2)), "0..10");
+        assert_eq!(bins.label(Decimal::new(10, 0)), "10..100");
+        assert_eq!(bins.label(Decimal::new(100, 0)), "100..");
+        assert!("10,0".parse::<Bins>().is_err());
+        assert!("0,0".parse::<Bins>().is_err());
+        assert!("0,a".parse::<Bins>().is_err());
+    }
+
+    #[test]
+    fn test_keep_and_drop_columns() {
+        let data = [
+            StringRecord::from(vec!["CO", "2021", "a"]),
+            StringRecord::from(vec!["CO", "2022", "b"]),
+            StringRecord::from(vec!["NY", "2023", "c"]),
+        ];
+        let setup = || {
+            let mut agg: Aggregator<Count<String>, String, usize> = Aggregator::new(
+                vec![0],
+                vec![1],
+                2,
+                false,
+                OutputOrder::IndexOrder,
+                OutputOrder::Ascending,
+                ParsingStrategy::Text,
+            );
+            for (count, record) in data.iter().enumerate() {
+                agg.add_record(record, count).unwrap();
+            }
+            agg
+        };
+        let mut kept = setup();
+        assert_eq!(
+            kept.keep_columns(&["2021", "2023", "2030"]).unwrap(),
+            vec!["2030".to_string()]
+        );
+        assert_eq!(
+            kept.to_vec(),
+            vec![
+                vec!["", "2021", "2023"],
+                vec!["CO", "1", ""],
+                vec!["NY", "", "1"]
+            ]
+        );
+        let mut dropped = setup();
+        assert!(dropped.drop_columns(&["2022"]).unwrap().is_empty());
+        assert_eq!(dropped.to_vec()[0], vec!["", "2021", "2023"]);
+        assert!(setup().keep_columns(&["2030"]).is_err());
+        assert!(setup().drop_columns(&["2021", "2022", "2023"]).is_err());
+    }
+
+    #[test]
+    fn test_na_rep() {
+        let mut agg: Aggregator<StdDev, f64, f64> = Aggregator::new(
+            vec![0],
+            vec![1],
+            2,
+            false,
+            OutputOrder::IndexOrder,
+            OutputOrder::Ascending,
+            ParsingStrategy::Numeric,
+        );
+        agg.set_na_rep("NA");
+        let data = [
+            StringRecord::from(vec!["CO", "2019", "1"]),
+            StringRecord::from(vec!["CO", "2019", "3"]),
+            StringRecord::from(vec!["NY", "2020", "1"]),
+        ];
+        for (count, record) in data.iter().enumerate() {
+            agg.add_record(record, count).unwrap();
+        }
+        let expected = vec![
+            vec!["", "2019", "2020"],
+            vec!["CO", "1.4142135623730951", "NA"],
+            vec!["NY", "NA", "NA"],
+        ];
+        assert_eq!(agg.to_vec(), expected);
+    }
+
+    #[test]
+    fn test_count_format() {
+        let mut agg: Aggregator<Sum<DecimalWrapper>, DecimalWrapper, DecimalWrapper> =
+            Aggregator::new(
+                vec![0],
+                vec![1],
+                2,
+                true,
+                OutputOrder::IndexOrder,
+                OutputOrder::Ascending,
+                ParsingStrategy::Numeric,
+            );
+        agg.set_count_format("{value} (n={count})");
+        let data = [
+            StringRecord::from(vec!["CO", "2019", "1"]),
+            StringRecord::from(vec!["CO", "2019", "3.5"]),
+            StringRecord::from(vec!["CO", "2019", ""]),
+            StringRecord::from(vec!["NY", "2020", "1"]),
+        ];
+        for (count, record) in data.iter().enumerate() {
+            agg.add_record(record, count).unwrap();
+        }
+        let expected = vec![
+            vec!["", "2019", "2020"],
+            vec!["CO", "4.5 (n=2)", ""],
+            vec!["NY", "", "1 (n=1)"],
+        ];
+        assert_eq!(agg.to_vec(), expected);
+    }
+
+    #[test]
+    fn test_to_matrix() {
+        let mut agg: Aggregator<Sum<DecimalWrapper>, DecimalWrapper, DecimalWrapper> =
+            Aggregator::new(
+                vec![0],
+                vec![1],
+                2,
+                true,
+                OutputOrder::IndexOrder,
+                OutputOrder::IndexOrder,
+                ParsingStrategy::Numeric,
+            );
+        let data = [
+            StringRecord::from(vec!["CO", "2019", "1"]),
+            StringRecord::from(vec!["CO", "2019", "3.5"]),
+            StringRecord::from(vec!["NY", "2020", "-2"]),
+            StringRecord::from(vec!["NY", "2021", ""]),
+        ];
+        for (count, record) in data.iter().enumerate() {
+            agg.add_record(record, count).unwrap();
+        }
+        let (rows, columns, matrix) = agg.to_matrix();
+        assert_eq!(rows, vec!["CO", "NY"]);
+        assert_eq!(columns, vec!["2019", "2020"]);
+        assert_eq!(matrix, vec![vec![Some(4.5), None], vec![None, Some(-2.)]]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let new_agg = || -> Aggregator<Mean, DecimalWrapper, DecimalWrapper> {
+            Aggregator::new(
+                vec![0],
+                vec![1],
+                2,
+                true,
+                OutputOrder::IndexOrder,
+                OutputOrder::IndexOrder,
+                ParsingStrategy::Numeric,
+            )
+        };
+        let data = [
+            StringRecord::from(vec!["CO", "2019", "1"]),
+            StringRecord::from(vec!["NY", "2020", "-2"]),
+            StringRecord::from(vec!["CO", "2019", "4"]),
+            StringRecord::from(vec!["PA", "2021", "5"]),
+            StringRecord::from(vec!["NY", "2019", ""]),
+        ];
+        let mut single = new_agg();
+        let mut merged = new_agg();
+        let mut second = new_agg();
+        for (count, record) in data.iter().enumerate() {
+            single.add_record(record, count).unwrap();
+            match count < 2 {
+                true => merged.add_record(record, count).unwrap(),
+                false => second.add_record(record, count).unwrap(),
+            }
+        }
+        merged.merge(second).unwrap();
+        assert_eq!(merged.to_vec(), single.to_vec());
+        assert_eq!(merged.stats(), single.stats());
+    }
+
+    #[test]
+    fn test_unique_cells() {
+        let new_agg = || -> Aggregator<First<String>, String, String> {
+            let mut agg = Aggregator::new(
+                vec![0],
+                vec![1],
+                2,
+                true,
+                OutputOrder::IndexOrder,
+                OutputOrder::IndexOrder,
+                ParsingStrategy::Text,
+            );
+            agg.set_unique_cells();
+            agg
+        };
+        let mut agg = new_agg();
+        agg.add_record(&StringRecord::from(vec!["1", "h", "10"]), 1)
+            .unwrap();
+        agg.add_record(&StringRecord::from(vec!["1", "w", "3"]), 2)
+            .unwrap();
+        match agg.add_record(&StringRecord::from(vec!["1", "h", "12"]), 3) {
+            Err(CsvCliError::ParsingError { line_num, .. }) => assert_eq!(line_num, 3),
+            _ => panic!("expected a conflict in row 1 and column h"),
+        }
+        let mut other = new_agg();
+        other
+            .add_record(&StringRecord::from(vec!["1", "w", "4"]), 1)
+            .unwrap();
+        assert!(agg.merge(other).is_err());
+    }
+
+    #[test]
+    fn test_write_long() {
+        let mut agg: Aggregator<First<String>, String, String> = Aggregator::new(
+            vec![0],
+            vec![],
+            0,
+            true,
+            OutputOrder::IndexOrder,
+            OutputOrder::IndexOrder,
+            ParsingStrategy::Text,
+        );
+        agg.label_corner(&["id", "b", "a"]);
+        agg.set_melt(vec![(1, "b".to_string()), (2, "a".to_string())]);
+        agg.add_record(&StringRecord::from(vec!["1", "x", ""]), 0)
+            .unwrap();
+        agg.add_record(&StringRecord::from(vec!["2", "y", "z"]), 1)
+            .unwrap();
+        let mut writer = csv::Writer::from_writer(vec![]);
+        agg.write_long(&mut writer, "name", "value").unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            "id,name,value\n1,b,x\n2,b,y\n2,a,z\n"
+        );
+    }
+
+    #[test]
+    fn test_skip_empty_keys() {
+        let data = [
+            StringRecord::from(vec!["x", "a", "1"]),
+            StringRecord::from(vec!["", "a", "2"]),
+            StringRecord::from(vec![" ", "", "4"]),
+        ];
+        let setup = |mode| {
+            let mut agg: Aggregator<Count<String>, String, usize> = Aggregator::new(
+                vec![0],
+                vec![1],
+                2,
+                false,
+                OutputOrder::IndexOrder,
+                OutputOrder::IndexOrder,
+                ParsingStrategy::Text,
+            );
+            agg.set_skip_empty_keys(mode);
+            for (i, record) in data.iter().enumerate() {
+                agg.add_record(record, i).unwrap();
+            }
+            agg.indexes.into_iter().collect::<Vec<String>>()
+        };
+        assert_eq!(setup(EmptyKeys::Any), vec!["x"]);
+        assert_eq!(setup(EmptyKeys::All), vec!["x", ""]);
+    }
+
+    #[test]
+    fn test_lenient() {
+        let mut agg: Aggregator<Sum<DecimalWrapper>, DecimalWrapper, DecimalWrapper> =
+            Aggregator::new(
+                vec![0],
+                vec![],
+                1,
+                true,
+                OutputOrder::IndexOrder,
+                OutputOrder::IndexOrder,
+                ParsingStrategy::Numeric,
+            );
+        let data = "a,b\nx,1\nx\ny,2\n";
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        assert!(agg.aggregate(&mut rdr).is_err());
+        agg.set_lenient();
+        let mut rdr = csv::Reader::from_reader(data.as_bytes());
+        agg.aggregate(&mut rdr).unwrap();
+        assert_eq!(
+            agg.ragged_summary().unwrap(),
+            "Skipped 1 record(s) with the wrong number of fields:\n  \
+             Line 3 has 1 field(s), but the first line has 2\n"
+        );
+    }
+
+    #[test]
+    fn test_date_format() {
+        // each aggregator carries its own format, so this never touches the global one
+        let setup = |format: &str| {
+            let mut agg: Aggregator<Maximum<CustomDateObject>, CustomDateObject, CustomDateObject> =
+                Aggregator::new(
+                    vec![0],
+                    vec![],
+                    1,
+                    false,
+                    OutputOrder::IndexOrder,
+                    OutputOrder::IndexOrder,
+                    ParsingStrategy::Date,
+                );
+            agg.set_date_format(DateFormat::new(format));
+            agg
+        };
+        let mut dmy = setup("%d/%m/%Y");
+        let mut mdy = setup("%m/%d/%Y");
+        let record = StringRecord::from(vec!["a", "05/01/2023"]);
+        dmy.add_record(&record, 1).unwrap();
+        mdy.add_record(&record, 1).unwrap();
+        assert_eq!(dmy.to_vec()[1], vec!["a", "2023-01-05 00:00:00"]);
+        assert_eq!(mdy.to_vec()[1], vec!["a", "2023-05-01 00:00:00"]);
+        match dmy.add_record(&StringRecord::from(vec!["a", "2023-01-05"]), 2) {
+            Err(CsvCliError::ParsingError { err, .. }) => assert!(err.contains("`%d/%m/%Y`")),
+            _ => panic!("expected a parsing error"),
+        }
+        // the row field is parsed with the aggregator's format as well
+        let mut by_month: Aggregator<Count<String>, String, usize> = Aggregator::new(
+            vec![0],
+            vec![],
+            1,
+            false,
+            OutputOrder::IndexOrder,
+            OutputOrder::IndexOrder,
+            ParsingStrategy::Text,
+        );
+        by_month.set_row_date_format(DateFormat::new("%d/%m/%Y"));
+        by_month.set_row_bucket("month".parse().unwrap());
+        by_month
+            .add_record(&StringRecord::from(vec!["05/01/2023", "x"]), 1)
+            .unwrap();
+        assert_eq!(by_month.to_vec()[1][0], "2023-01");
+    }
+
+    #[test]
+    fn test_pretty_table() {
+        let rows = vec![
+            vec!["".to_string(), "total".to_string()],
+            vec!["apple".to_string(), "12".to_string()],
+        ];
+        assert_eq!(pretty_table(&rows, 1, Some(3), ""), "     to…\nap…   12\n");
+        assert_eq!(pretty_table(&rows, 1, Some(0), ""), "   …\n…  …\n");
+    }
+
+    #[test]
+    fn test_format_number() {
+        assert_eq!(
+            format_number("277.33216804431602", 2, Notation::Fixed),
+            "277.33"
+        );
+        // ties round to the even digit in both notations
+        assert_eq!(format_number("1.005", 2, Notation::Fixed), "1.00");
+        assert_eq!(format_number("1.015", 2, Notation::Fixed), "1.02");
+        assert_eq!(format_number("0.125", 2, Notation::General), "0.12");
+        assert_eq!(format_number("3", 2, Notation::Fixed), "3.00");
+        assert_eq!(
+            format_number("277.33216804431602", 4, Notation::General),
+            "277.3"
+        );
+        assert_eq!(
+            format_number("1414213.9159", 3, Notation::General),
+            "1.41e6"
+        );
+        assert_eq!(
+            format_number("0.0000070710", 3, Notation::General),
+            "7.07e-6"
+        );
+        assert_eq!(format_number("2.5", 3, Notation::General), "2.5");
+        assert_eq!(format_number("1 - 5", 2, Notation::Fixed), "1 - 5");
+        assert_eq!(
+            format_number("2020-01-01", 2, Notation::General),
+            "2020-01-01"
+        );
+    }
+
+    #[test]
+    fn test_group_digits() {
+        assert_eq!(group_digits("1234567", ",", "."), "1,234,567");
+        assert_eq!(group_digits("-1234.5678", ",", "."), "-1,234.5678");
+        assert_eq!(group_digits("123", ",", "."), "123");
+        assert_eq!(group_digits("1234.5", ".", ","), "1.234,5");
+        assert_eq!(group_digits("1.41e6", ",", "."), "1.41e6");
+        assert_eq!(group_digits("Columbus", ",", "."), "Columbus");
+    }
 }
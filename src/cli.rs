@@ -1,30 +1,101 @@
 use clap::{App, AppSettings, Arg, ArgMatches};
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use indexmap::IndexMap;
+use indicatif::{ProgressBar, ProgressStyle};
 use lazy_static::lazy_static;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
 use std::io;
+use std::io::{Read, Write};
+use std::rc::Rc;
 
 use rust_decimal::Decimal;
 
 use crate::aggfunc::*;
-use crate::aggregation::{Aggregator, OutputOrder, ParsingStrategy};
-use crate::cli_settings::CsvSettings;
+use crate::aggregation::{
+    is_null_value, Aggregator, EmptyKeys, Notation, OutputOrder, ParsingStrategy,
+};
+use crate::cli_settings::{read_field_descriptors, CsvSettings};
 use crate::errors::{CsvCliError, CsvCliResult};
-use crate::parsing::{CustomDateObject, DecimalWrapper};
-
-const ALLOWED_AGGFUNCS: [&str; 11] = [
-    "count",
-    "countunique",
-    "max",
-    "mean",
-    "median",
-    "min",
-    "minmax",
-    "mode",
-    "range",
-    "stddev",
-    "sum",
+use crate::expr::Expr;
+use crate::parsing;
+use crate::parsing::{BooleanWrapper, CustomDateObject, DateDifference, DecimalWrapper};
+
+/// A function, with the ways it can parse values and whether it accepts columns as well as rows.
+/// `run` chooses how to parse the values from these, and `--list-functions` describes them.
+struct FunctionSpec {
+    name: &'static str,
+    /// The ways the function can parse values
+    parsing: &'static [ParsingStrategy],
+    rows_and_columns: bool,
+}
+
+const fn function(name: &'static str, parsing: &'static [ParsingStrategy]) -> FunctionSpec {
+    FunctionSpec {
+        name,
+        parsing,
+        rows_and_columns: true,
+    }
+}
+
+const TEXT: &[ParsingStrategy] = &[ParsingStrategy::Text];
+const NUMERIC: &[ParsingStrategy] = &[ParsingStrategy::Numeric];
+const BOOLEAN: &[ParsingStrategy] = &[ParsingStrategy::Boolean];
+const NUMERIC_OR_DATE: &[ParsingStrategy] = &[ParsingStrategy::Numeric, ParsingStrategy::Date];
+const ANY_TYPE: &[ParsingStrategy] = &[
+    ParsingStrategy::Text,
+    ParsingStrategy::Numeric,
+    ParsingStrategy::Date,
+];
+
+const FUNCTIONS: [FunctionSpec; 30] = [
+    function("agg", NUMERIC),
+    function("cardinality", TEXT),
+    function("count", TEXT),
+    function("countif", TEXT),
+    function("countunique", ANY_TYPE),
+    function("crosstab", TEXT),
+    function("first", ANY_TYPE),
+    function("histogram", NUMERIC),
+    function("last", ANY_TYPE),
+    function("length", TEXT),
+    function("max", ANY_TYPE),
+    function("maxby", ANY_TYPE),
+    function("mean", NUMERIC),
+    function("median", NUMERIC),
+    function("min", ANY_TYPE),
+    function("minby", ANY_TYPE),
+    function("minmax", ANY_TYPE),
+    function("mode", ANY_TYPE),
+    function("percentilerank", NUMERIC),
+    // pivot-longer's columns are the fields that aren't row fields
+    FunctionSpec {
+        rows_and_columns: false,
+        ..function("pivot-longer", TEXT)
+    },
+    function("pivot-wider", TEXT),
+    function("range", NUMERIC_OR_DATE),
+    function("rate", BOOLEAN),
+    function("rms", NUMERIC),
+    function("sample", TEXT),
+    function("shape", TEXT),
+    function("spread", TEXT),
+    function("stddev", NUMERIC),
+    function("sum", NUMERIC),
+    function("sumsq", NUMERIC),
 ];
 
+/// The number of non-empty values the `--sample-check` flag looks at
+const SAMPLE_CHECK_SIZE: usize = 100;
+/// The format of cells annotated with `--with-count`
+const DEFAULT_COUNT_FORMAT: &str = "{value} ({count})";
+/// The most digits `--precision` accepts: the most decimal places a `Decimal` keeps
+const MAX_PRECISION: usize = 28;
+
 lazy_static! {
+    static ref FUNCTION_NAMES: Vec<&'static str> = FUNCTIONS.iter().map(|spec| spec.name).collect();
     pub static ref CLI_ARGS : ArgMatches<'static> = App::new("clipivot")
         .version(crate_version!())
         .author(crate_authors!())
@@ -32,62 +103,532 @@ lazy_static! {
         For more information, visit https://www.github.com/maxblee/clipivot")
         .setting(AppSettings::ArgRequiredElseHelp)
         .arg(Arg::with_name("aggfunc")
-            .required(true)
             .index(1)
-            .possible_values(&ALLOWED_AGGFUNCS)
-            .help("The function you use to run across the pivot table.
+            .required_unless_one(&["listfunctions", "agg"])
+            .help("The function you use to run across the pivot table. You can also choose it with --agg (e.g. --agg sum).
+            - agg computes each of the functions from --funcs (like sum,mean,count) in a single pass, with a column for each one.
+            - cardinality returns the number of unique records and the ratio of unique records to records, like '3 (0.75)'.
             - count counts the number of matching records.
-            - countunique counts the number of unique matching records.
+            - countif counts the matching records whose values match the regular expression from --match (like '^err').
+            - countunique counts the number of unique matching records. With -N, -F or --infer, equal numbers or dates written differently (like 1.0 and 1.00) count once.
+            - crosstab counts the records with each combination of rows and columns, without a values column.
+            - first returns the first value, or with --order-by, the value in the record with the smallest value of that field.
+            - histogram counts the number of records in each of the numeric ranges set by --bins.
+            - last returns the last value, or with --order-by, the value in the record with the largest value of that field.
+            - length returns the shortest and longest lengths of the values (in characters) and their mean length, like '2 - 7 (4.5)'.
             - max returns the maximum value of the records given a specified data type.
+            - maxby returns the value of the --pick field in the record with the maximum value.
             - mean returns the mean.
             - median returns the median value. Requires numeric data.
             - min returns the minimum value of the records given a specified data type.
+            - minby returns the value of the --pick field in the record with the minimum value.
             - minmax returns both the minimum and maximum values of the records, split by a hyphen.
-            - mode returns the most commonly appearing value.
+            - mode returns the most commonly appearing value, breaking ties with --tiebreak.
+            - percentilerank returns the fraction of the values that are less than or equal to the value from --at.
+            - pivot-longer reshapes wide data into long data, like tidyr's pivot_longer: each field besides the -r fields \
+            becomes a record with the field's name (under --names-to) and value (under --values-to).
+            - pivot-wider is spread, like tidyr's pivot_wider, with --names-from and --values-from for -c and -v.
+            - rate returns the fraction of the values that are true (like true, yes or 1) rather than false (like false, no or 0). Use --percent to display it as a percentage.
             - range returns the difference between the minimum and maximum values. Returns the number of days in the case of dates.
+            - rms returns the root mean square (quadratic mean).
+            - sample returns a random sample of the values (one by default, or --k of them), separated by semicolons.
+            - shape describes the size of the pivot table instead of writing it: the number of rows, columns and cells with values.
+            - spread reshapes long data into a wide table, with the one value in each cell. (See --on-conflict.)
             - stddev returns the sample standard deviation.
-            - sum returns the sum of the values."))
+            - sum returns the sum of the values.
+            - sumsq returns the sum of the squares of the values."))
+        .arg(Arg::with_name("agg")
+            .long("agg")
+            .takes_value(true)
+            .possible_values(&FUNCTION_NAMES)
+            .help("The function, for scripts that keep it in a variable. With --agg, the first positional \
+            argument is the first file instead of the function."))
+        .arg(Arg::with_name("listfunctions")
+            .long("list-functions")
+            .help("List the available functions, the ways each one can parse values, \
+            and whether each one accepts rows and columns, as tab-separated lines."))
+        .arg(Arg::with_name("json")
+            .long("json")
+            .requires("listfunctions")
+            .help("Print the --list-functions output as JSON."))
         .arg(Arg::with_name("filename")
             .index(2)
-            .help("The path to the file you want to create a pivot table from"))
+            .multiple(true)
+            .help("The path to the file you want to create a pivot table from. \
+            Reads from standard input if you leave it out or use '-'. With more than one file, \
+            the records of every file are aggregated together; the files need to have the same fields."))
+        .arg(Arg::with_name("mergefiles")
+            .long("merge-files")
+            .help("With more than one file, aggregate each file on its own and then combine the results, \
+            instead of reading every file into the same pivot table. The results are the same, \
+            except that ties in mode, the picks of sample and the estimate of median --fast-median can differ."))
+        .arg(Arg::with_name("splitby")
+            .long("split-by")
+            .takes_value(true)
+            .requires("outputdir")
+            .conflicts_with("samplecheck")
+            .help("Write a separate pivot table for each value of this field, to a file named after the value \
+            (like 'west.csv') in the directory from --output-dir. Reads the input once, but holds every pivot \
+            table in memory until the end."))
+        .arg(Arg::with_name("outputdir")
+            .long("output-dir")
+            .takes_value(true)
+            .requires("splitby")
+            .help("The directory --split-by writes its files to. It's created if it doesn't exist, \
+            and files that are already in it are overwritten."))
         .arg(Arg::with_name("rows")
             .long("rows")
             .short("r")
             .takes_value(true)
             .multiple(true)
             .help("The name of the index(es) to aggregate on. Accepts string fieldnames or 0-indexed fields."))
+        .arg(Arg::with_name("rowsfile")
+            .long("rows-file")
+            .takes_value(true)
+            .help("Read the row fields from a file, with one or more fields (separated by commas) on each line. \
+            They come after the fields from -r."))
+        .arg(Arg::with_name("rowbucket")
+            .long("row-bucket")
+            .takes_value(true)
+            .possible_values(&["year", "month", "week", "day", "hour"])
+            .help("Parse the first row field as a date (using -F if you set it) and group the rows by year, \
+            month, week (starting on Monday), day or hour."))
         .arg(Arg::with_name("columns")
             .long("cols")
             .short("c")
+            .visible_alias("names-from")
             .takes_value(true)
             .multiple(true)
             .help("The name of the column(s) to aggregate on. Accepts string fieldnames or 0-indexed fields."))
+        .arg(Arg::with_name("colskeep")
+            .long("cols-keep")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .conflicts_with("colsdrop")
+            .help("Only display the output columns with these names (e.g. 2021,2022 when pivoting on years)."))
+        .arg(Arg::with_name("colsdrop")
+            .long("cols-drop")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .help("Don't display the output columns with these names."))
+        .arg(Arg::with_name("colsfile")
+            .long("cols-file")
+            .takes_value(true)
+            .help("Read the column fields from a file, with one or more fields (separated by commas) on each line. \
+            They come after the fields from -c."))
         .arg(Arg::with_name("value")
             .long("val")
             .short("v")
+            .visible_alias("values-from")
+            .takes_value(true)
+            .help("The name of the field with the values you want to aggregate. Accepts a string fieldname or \
+            a 0-indexed field. Optional for count, which counts every record if you leave it out."))
+        .arg(Arg::with_name("valfile")
+            .long("val-file")
+            .takes_value(true)
+            .conflicts_with("value")
+            .help("Read the values field from a file, which should hold a single field."))
+        .arg(Arg::with_name("expr")
+            .long("expr")
+            .takes_value(true)
+            .conflicts_with_all(&["value", "valfile", "format"])
+            .help("Aggregate the result of an arithmetic expression on each record instead of a values field, \
+            like 'qty * price'. Supports + - * / and parentheses. Put field names that aren't made of letters, \
+            digits and underscores in double quotes. Values are parsed as numbers, like with -N."))
+        .arg(Arg::with_name("coalesce")
+            .long("coalesce")
+            .takes_value(true)
+            .conflicts_with_all(&["value", "valfile", "expr", "orderby"])
+            .help("Aggregate the first non-empty value among several fields instead of a values field, \
+            like 'amount_usd,amount_eur'. The fields are checked in the order you list them. Values that \
+            -e skips (like NA) count as empty with -e, and records where every field is empty have an empty value."))
+        .arg(Arg::with_name("match")
+            .long("match")
+            .takes_value(true)
+            .help("The regular expression countif counts the values that match, like '^err'. \
+            It matches anywhere in the value unless you anchor it with ^ or $."))
+        .arg(Arg::with_name("funcs")
+            .long("funcs")
+            .takes_value(true)
+            .help("The functions agg computes, separated by commas, like 'sum,mean,count'. Each one gets \
+            its own column (in each column of the pivot table). Works with count, max, mean, median, min, range, \
+            rms, stddev, sum and sumsq, which all parse the values as numbers."))
+        .arg(Arg::with_name("at")
+            .long("at")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("The value percentilerank finds the percentile rank of in each cell, like 100. \
+            The result is the fraction of the values that are less than or equal to it."))
+        .arg(Arg::with_name("namesto")
+            .long("names-to")
+            .takes_value(true)
+            .help("The name of the field pivot-longer puts the names of the fields in (name by default)."))
+        .arg(Arg::with_name("valuesto")
+            .long("values-to")
             .takes_value(true)
-            .required(true))
+            .help("The name of the field pivot-longer puts the values of the fields in (value by default)."))
+        .arg(Arg::with_name("onconflict")
+            .long("on-conflict")
+            .takes_value(true)
+            .possible_values(&["error", "first", "last", "sum"])
+            .help("What spread, pivot-wider and pivot-longer do when a cell gets more than one value: return an error (the default), \
+            keep the first or last value, or sum the values as numbers."))
+        .arg(Arg::with_name("weight")
+            .long("weight")
+            .takes_value(true)
+            .help("A field with a number of records that count uses in place of 1 for each record, \
+            for data that's already been counted (like a frequency field). The weights need to be numbers."))
+        .arg(Arg::with_name("pick")
+            .long("pick")
+            .takes_value(true)
+            .help("The field maxby and minby return the value of, from the record with the maximum \
+            or minimum value. Ties go to the first record."))
+        .arg(Arg::with_name("orderby")
+            .long("order-by")
+            .takes_value(true)
+            .help("The field first and last order the records by, instead of the input order. It's parsed \
+            as text unless you use -N or -F. Ties go to the first record for first and to the last record for last."))
+        .arg(Arg::with_name("bins")
+            .long("bins")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("The edges of the numeric ranges for histogram, separated by commas (e.g. 0,10,100). \
+            The ranges include their lower edges and exclude their upper edges, and values outside of \
+            the edges are counted in ranges like ..0 and 100.."))
+        .arg(Arg::with_name("minmaxsep")
+            .long("minmax-sep")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("The separator between the minimum and maximum values for minmax. Defaults to ' - '."))
+        .arg(Arg::with_name("tiebreak")
+            .long("tiebreak")
+            .takes_value(true)
+            .possible_values(&["first", "last", "min", "max"])
+            .help("How mode chooses between values that appear equally often. 'first' (the default) picks \
+            the value that reached the highest count first; 'last' picks the one that reached it last; \
+            'min' and 'max' pick the smallest and largest values, compared as numbers with -N or as dates with -F."))
+        .arg(Arg::with_name("percent")
+            .long("percent")
+            .conflicts_with_all(&["cumulative", "withcount"])
+            .help("Display the results of rate as percentages (like 62.5%) instead of fractions. \
+            With count, crosstab and sum, display each cell as a percentage of the total of its row."))
+        .arg(Arg::with_name("keepraw")
+            .long("keep-raw")
+            .requires("percent")
+            .help("Display each cell's value before its row percentage from --percent, like '123 (45.6%)'. \
+            Only works with count, crosstab and sum."))
+        .arg(Arg::with_name("fastmedian")
+            .long("fast-median")
+            .help("Estimate the median with the P² algorithm, which uses a constant amount of memory \
+            but is only approximate. Only works with median."))
+        .arg(Arg::with_name("k")
+            .long("k")
+            .takes_value(true)
+            .help("The number of values to sample from each cell with sample. Defaults to 1."))
+        .arg(Arg::with_name("seed")
+            .long("seed")
+            .takes_value(true)
+            .help("A number to seed the random sampling of sample with, so the same file always gets the same sample."))
+        .arg(Arg::with_name("infer")
+            .long("infer")
+            .help("For max, min, minmax and range, look at the first 100 non-empty values and parse them \
+            as numbers if they're all numbers, as dates if they're all dates (like 2019-01-01 00:00:00), or as text. \
+            -N and -F take precedence over this flag."))
         .arg(Arg::with_name("numeric")
             .short("N")
-            .help("Parse values as numeric data. This is only necessary for min, max, and minmax, which can parse strings."))
+            .help("Parse values as numeric data. This is only necessary for min, max, and minmax, which can parse strings, \
+            and for range, which needs either this flag or a date format (-F)."))
         .arg(Arg::with_name("format")
             .short("F")
+            .long("format")
+            .takes_value(true)
+            .help("The format of a date field (e.g. %Y-%m-%d for dates like 2010-09-21). You can list several \
+            formats separated by semicolons (e.g. '%Y-%m-%d;%m/%d/%Y'), and each date is parsed with the first one that matches."))
+        .arg(Arg::with_name("assumetz")
+            .long("assume-tz")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("The UTC offset of dates without one (e.g. +02:00, -0500 or UTC). Dates with offsets, \
+            like 2023-05-01T12:00:00+02:00 or dates parsed with %z, are always converted to UTC, so with this \
+            option every date is in UTC."))
+        .arg(Arg::with_name("durationformat")
+            .long("duration-format")
+            .takes_value(true)
+            .possible_values(&["days", "human", "ymd"])
+            .help("How to display the range of dates. 'days' (the default) displays the number of days, \
+            including fractions of days; 'human' displays days, hours, minutes and seconds (e.g. '731 days 6 hours'); \
+            'ymd' displays calendar years, months and days (e.g. '2y 0m 1d')."))
+        .arg(Arg::with_name("daterangeunit")
+            .long("date-range-unit")
+            .takes_value(true)
+            .possible_values(&["days", "hours", "seconds"])
+            .conflicts_with("durationformat")
+            .help("Display the range of dates as a number of days (the default), hours or seconds. \
+            Days and hours include fractions; whole numbers are displayed without decimals."))
+        .arg(Arg::with_name("check")
+            .long("check")
+            .help("Validate the options against the header row and print the resolved column indexes \
+            and the parsing strategy, without aggregating the file."))
+        .arg(Arg::with_name("carry")
+            .long("carry")
+            .takes_value(true)
+            .help("Display the first value of this field in each row in a column next to the row labels, \
+            for descriptive fields that don't change within a row. Accepts a string fieldname or a 0-indexed field."))
+        .arg(Arg::with_name("explode")
+            .long("explode")
+            .takes_value(true)
+            .help("Split the values of this field on --explode-sep and count each record once for each piece, \
+            like a record for each tag in 'tag1;tag2'. Accepts a string fieldname or a 0-indexed field."))
+        .arg(Arg::with_name("explodesep")
+            .long("explode-sep")
+            .takes_value(true)
+            .requires("explode")
+            .help("The separator between the values of the --explode field. Defaults to ';'."))
+        .arg(Arg::with_name("rowreplace")
+            .long("row-replace")
+            .takes_value(true)
+            .number_of_values(2)
+            .value_names(&["PATTERN", "REPLACEMENT"])
+            .help("Replace the matches of a regular expression in the values of the row fields before grouping them, \
+            like --row-replace ' \\(.*\\)$' '' to group 'Acme (123)' and 'Acme (456)' together. \
+            The replacement can refer to groups in the pattern, like '$1'."))
+        .arg(Arg::with_name("colreplace")
+            .long("col-replace")
+            .takes_value(true)
+            .number_of_values(2)
+            .value_names(&["PATTERN", "REPLACEMENT"])
+            .help("Like --row-replace, but for the values of the column fields."))
+        .arg(Arg::with_name("headercomment")
+            .long("header-comment")
+            .help("Before the pivot table, write a line starting with # describing the aggregation and the \
+            fields in the rows and columns, like '# values: sum(amount); rows: state; columns: year'."))
+        .arg(Arg::with_name("multiheader")
+            .long("multi-header")
+            .help("Write a second header row with the aggregation (like 'sum(amount)') above each column."))
+        .arg(Arg::with_name("explain")
+            .long("explain")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("Instead of the pivot table, write the records that went into one of its cells, \
+            given as ROW|COLUMN with the labels as they appear in the output (e.g. '2023-05|north'). \
+            Without a |, the column is 'total'. Only the records in that cell are kept in memory."))
+        .arg(Arg::with_name("addcolumn")
+            .long("add-column")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Add a column with the same value in every row, given as NAME=VALUE \
+            (e.g. 'source=2023 survey'), after the row labels. Can be used more than once."))
+        .arg(Arg::with_name("keysep")
+            .long("key-sep")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("The separator between the values of multiple row or column fields in the output \
+            (e.g. ' / '). Defaults to '_<sep>_'."))
+        .arg(Arg::with_name("narep")
+            .long("na-rep")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .help("The text to display in cells without a value (e.g. NA, 0, or -). Defaults to an empty string."))
+        .arg(Arg::with_name("nanumber")
+            .long("na-number")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .allow_hyphen_values(true)
+            .help("Skip values equal to this number (e.g. -999), like empty values. You can use this more \
+            than once. Only works with functions that parse values as numbers."))
+        .arg(Arg::with_name("skiperrors")
+            .long("skip-errors")
+            .help("Skip values that can't be parsed instead of stopping at the first one, printing the number \
+            of skipped values and the first few errors to standard error."))
+        .arg(Arg::with_name("lenient")
+            .long("lenient")
+            .help("Skip records with a different number of fields than the header instead of stopping at the \
+            first one, printing the number of skipped records and the lines of the first few to standard error."))
+        .arg(Arg::with_name("samplecheck")
+            .long("sample-check")
+            .help("Before aggregating, make sure at least one of the first 100 non-empty values can be parsed \
+            with the chosen function, failing early if none can. (By default, clipivot only fails once it \
+            reaches a value it can't parse.)"))
+        .arg(Arg::with_name("cumulative")
+            .long("cumulative")
+            .help("Display running totals down each column, from the first row to the current one. \
+            Only works with count, crosstab and sum."))
+        .arg(Arg::with_name("emptyzero")
+            .long("count-empty-as-zero")
+            .help("Display 0 in cells without any values, and keep rows and columns whose values \
+            were all skipped as empty (with -e or --na-number). Only works with count, crosstab and sum."))
+        .arg(Arg::with_name("withcount")
+            .long("with-count")
+            .conflicts_with("cumulative")
+            .help("Annotate each cell with the number of records in it, like '1234.5 (12)'. \
+            Only works with functions that parse values as numbers."))
+        .arg(Arg::with_name("countformat")
+            .long("count-format")
+            .takes_value(true)
+            .requires("withcount")
+            .help("The format of cells annotated with --with-count, where {value} is the value \
+            of the cell and {count} is the number of records. Defaults to '{value} ({count})'."))
+        .arg(Arg::with_name("rowcount")
+            .long("with-row-count")
+            .help("Add a 'count' column with the number of records in each row (after skipping empty values)."))
+        .arg(Arg::with_name("colcount")
+            .long("with-col-count")
+            .help("Add a 'count' row with the number of records in each column (after skipping empty values)."))
+        .arg(Arg::with_name("precision")
+            .long("precision")
+            .takes_value(true)
+            .help("Round the results to this many digits: digits after the decimal point by default, \
+            or significant digits with '--notation general'. Ties round to the even digit (1.005 becomes 1.00). \
+            Accepts up to 28 digits. Only works with functions that parse values as numbers."))
+        .arg(Arg::with_name("notation")
+            .long("notation")
+            .takes_value(true)
+            .requires("precision")
+            .possible_values(&["fixed", "general"])
+            .help("How --precision displays numbers: 'fixed' (the default) uses a fixed number of digits after \
+            the decimal point, and 'general' uses significant digits, like %g in C."))
+        .arg(Arg::with_name("groupdigits")
+            .long("group-digits")
+            .help("Separate the thousands in numeric results, like '1,234,567'. Only works with counts \
+            and functions that parse values as numbers."))
+        .arg(Arg::with_name("groupsep")
+            .long("group-sep")
             .takes_value(true)
-            .help("The format of a date field (e.g. %Y-%m-%d for dates like 2010-09-21)"))
+            .requires("groupdigits")
+            .help("The separator between groups of thousands with --group-digits. Defaults to ','."))
+        .arg(Arg::with_name("decimalsep")
+            .long("decimal-sep")
+            .takes_value(true)
+            .requires("groupdigits")
+            .help("The separator before the decimals with --group-digits. Defaults to '.'."))
+        .arg(Arg::with_name("decimalcomma")
+            .long("decimal-comma")
+            .help("Parse numbers with a decimal comma and periods between groups of thousands, like '1.234,56', \
+            and write numeric results with a decimal comma. Files like this are usually separated by semicolons, \
+            which clipivot detects unless you set another delimiter with -d. Only works with functions that \
+            parse values as numbers."))
+        .arg(Arg::with_name("limitinput")
+            .long("limit-input")
+            .takes_value(true)
+            .help("Only read the first N records, for a quick preview. The results only describe those records, \
+            so they're partial if the input has more."))
+        .arg(Arg::with_name("pretty")
+            .long("pretty")
+            .help("Print the pivot table with its columns lined up, for reading in a terminal, instead of as a CSV. \
+            Numeric columns are right-aligned."))
+        .arg(Arg::with_name("maxwidth")
+            .long("max-width")
+            .takes_value(true)
+            .requires("pretty")
+            .help("Cut off cells longer than this many characters with --pretty."))
+        .arg(Arg::with_name("progress")
+            .long("progress")
+            .help("Show a progress bar on standard error while reading the file \
+            (or a count of records read, when reading from standard input)."))
+        .arg(Arg::with_name("crlf")
+            .long("crlf")
+            .help("End output lines with \\r\\n (for Windows programs like Excel) instead of \\n."))
+        .arg(Arg::with_name("stats")
+            .long("stats")
+            .help("After writing the pivot table, print the number of distinct rows and columns \
+            and the number of records processed and skipped to standard error."))
         .arg(Arg::with_name("empty")
             .short("e")
             .help("Ignores empty/null values ('', NULL, NaN, NONE, NA, N/A)"))
+        .arg(Arg::with_name("nullskiprows")
+            .long("null-skip-rows")
+            .help("Skip records with empty row or column fields, instead of grouping them under an empty label. \
+            By default, a record is skipped if any of its row and column fields are empty."))
+        .arg(Arg::with_name("nullskipmode")
+            .long("null-skip-mode")
+            .takes_value(true)
+            .requires("nullskiprows")
+            .possible_values(&["any", "all"])
+            .help("Whether --null-skip-rows skips records where 'any' (the default) or 'all' of the row \
+            and column fields are empty."))
         .arg(Arg::with_name("noheader")
             .long("no-header")
             .help("Skip the header row of the CSV file."))
+        .arg(Arg::with_name("headernames")
+            .long("header-names")
+            .takes_value(true)
+            .multiple(true)
+            .use_delimiter(true)
+            .requires("noheader")
+            .help("Names for the columns of a file without a header row (e.g. region,year,amount), \
+            so you can select them by name. Requires --no-header."))
+        .arg(Arg::with_name("nooutputheader")
+            .long("no-output-header")
+            .help("Leave the header row out of the output, for appending it to a file that already has one."))
+        .arg(Arg::with_name("oneindexed")
+            .long("one-indexed")
+            .help("Number the fields from 1, like cut and awk, so -v 1 is the first field. \
+            (Selecting repeated names, like amount[1], is still 0-indexed.)"))
+        .arg(Arg::with_name("strictheaders")
+            .long("strict-headers")
+            .help("Return an error if more than one column has the same name, instead of \
+            selecting repeated names like amount[1]."))
+        .arg(Arg::with_name("cleanheaders")
+            .long("clean-headers")
+            .help("Clean up the names of the columns before selecting them, so '  Order Date ' becomes \
+            'order_date': trim them, lowercase them and replace the spaces in them with underscores. \
+            The cleaned names are also the ones in the output."))
+        .arg(Arg::with_name("skiprepeatedheader")
+            .long("skip-repeated-header")
+            .conflicts_with("noheader")
+            .help("Skip records that are identical to the header row, like the repeated headers \
+            from concatenating several CSV files."))
         .arg(Arg::with_name("delim")
             .short("d")
             .long("delim")
             .takes_value(true)
             .help("The delimiter used to separate fields. Defaults to ','."))
+        .arg(Arg::with_name("notrim")
+            .long("no-trim")
+            .help("Keep the leading and trailing whitespace in every field. By default, clipivot strips it."))
+        .arg(Arg::with_name("quote")
+            .long("quote")
+            .takes_value(true)
+            .conflicts_with("noquoting")
+            .help("The character used to quote fields with delimiters or newlines in them. Defaults to '\"'."))
+        .arg(Arg::with_name("noquoting")
+            .long("no-quoting")
+            .help("Don't treat any character as a quote, reading quotes like any other character."))
+        .arg(Arg::with_name("escape")
+            .long("escape")
+            .takes_value(true)
+            .conflicts_with("noquoting")
+            .help("The character used to escape quotes inside of quoted fields (e.g. \\). \
+            By default, quotes are escaped by doubling them."))
+        .arg(Arg::with_name("encoding")
+            .long("encoding")
+            .takes_value(true)
+            .help("The character encoding of the input (e.g. latin1 or windows-1252). \
+            Defaults to UTF-8. Output is always UTF-8."))
+        .arg(Arg::with_name("formatin")
+            .long("format-in")
+            .takes_value(true)
+            .possible_values(&["csv", "jsonl"])
+            .conflicts_with("noheader")
+            .help("The format of the input. 'jsonl' reads JSON Lines, with a JSON object on each line, \
+            whose fields can be selected by name; missing fields are empty. Files ending in .jsonl are \
+            read as JSON Lines by default. JSON Lines input is read into memory before it's aggregated, \
+            since any line can add a field."))
+        .arg(Arg::with_name("sheet")
+            .long("sheet")
+            .takes_value(true)
+            .help("The name or 0-indexed position of the sheet to read from an Excel (.xlsx) file. \
+            Defaults to the first sheet."))
         .arg(Arg::with_name("tab")
             .short("t")
             .help("Set the delimiter of the file to a tab."))
+        .arg(Arg::with_name("asccol")
+            .long("asc-cols")
+            .help("Display column names in sorted, ascending order (the default)."))
         .arg(Arg::with_name("indexcol")
             .short("I")
             .long("index-cols")
@@ -100,65 +641,790 @@ lazy_static! {
             .short("A")
             .long("asc-rows")
             .help("Displays the rows in sorted, ascending order (default is index order)."))
+        .arg(Arg::with_name("indexrow")
+            .long("index-rows")
+            .help("Displays the rows in index order (the default)."))
         .arg(Arg::with_name("descrow")
             .short("D")
             .long("desc-rows")
             .help("Displays the rows in sorted, descending order (default is index order)."))
+        .arg(Arg::with_name("sort")
+            .long("sort")
+            .takes_value(true)
+            .possible_values(&["lexical"])
+            .help("Sorts both the rows and the columns. 'lexical' sorts both in ascending order, like -A and \
+            --asc-cols, comparing labels from multiple fields field by field. Can't be combined with the flags \
+            that sort the rows or columns on their own."))
+        .arg(Arg::with_name("sortcolsbytotal")
+            .long("sort-cols-by-total")
+            .help("Display the columns in order of their totals, from the largest to the smallest. \
+            Only works with count, crosstab and sum, and can't be combined with the flags that sort the columns."))
+        .arg(Arg::with_name("zeropad")
+            .long("zero-pad")
+            .help("When sorting labels, treat labels made of digits as though they were padded with zeros to \
+            the same width, so 9 comes before 10. The labels are displayed without the padding."))
+        .arg(Arg::with_name("keeppadding")
+            .long("keep-padding")
+            .requires("zeropad")
+            .help("Display the labels padded by --zero-pad with their zeros (like 09 and 10)."))
         .get_matches();
 }
 
+/// The number of bytes `PeekedInput::open` reads to guess the delimiter
+const SAMPLE_BYTES: usize = 16 * 1024;
+
+/// A single input file (or standard input), with the start of it already read to guess its
+/// delimiter and, with --infer, how to parse its values.
+///
+/// Standard input and named pipes can only be read once, so `into_reader` puts what was read
+/// back in front of the rest of the input instead of opening it again.
+struct PeekedInput {
+    peeked: Vec<u8>,
+    rest: Box<dyn io::Read>,
+}
+
+impl PeekedInput {
+    /// Opens the file (or standard input, without a filename) and reads the first `SAMPLE_BYTES` of it.
+    fn open(filename: Option<&str>) -> io::Result<PeekedInput> {
+        let mut rest: Box<dyn io::Read> = match filename {
+            Some(filepath) => Box::new(fs::File::open(filepath)?),
+            None => Box::new(io::stdin()),
+        };
+        let mut peeked = Vec::new();
+        rest.by_ref()
+            .take(SAMPLE_BYTES as u64)
+            .read_to_end(&mut peeked)?;
+        Ok(PeekedInput { peeked, rest })
+    }
+
+    /// The start of the input, for guessing its delimiter
+    fn sample(&self) -> &[u8] {
+        &self.peeked[..self.peeked.len().min(SAMPLE_BYTES)]
+    }
+
+    /// Calls `read` with a reader over the input from the start, keeping whatever it reads
+    /// past the part that has already been peeked at, so it can be read again.
+    fn peek_with<T>(&mut self, read: impl FnOnce(Box<dyn io::Read + '_>) -> T) -> T {
+        let mut more = Vec::new();
+        let result = read(Box::new(io::Cursor::new(&self.peeked[..]).chain(
+            RecordingReader {
+                inner: &mut self.rest,
+                copy: &mut more,
+            },
+        )));
+        self.peeked.extend(more);
+        result
+    }
+
+    /// Returns a reader over the whole input, from the start.
+    fn into_reader(self) -> Box<dyn io::Read> {
+        Box::new(io::Cursor::new(self.peeked).chain(self.rest))
+    }
+}
+
+/// Keeps a copy of everything that's read from `inner`.
+struct RecordingReader<'a> {
+    inner: &'a mut dyn io::Read,
+    copy: &'a mut Vec<u8>,
+}
+
+impl io::Read for RecordingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.copy.extend_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
 fn run_and_init<T, I, O>(
     arg_matches: &ArgMatches,
+    input: Option<PeekedInput>,
+    parsing_strategy: ParsingStrategy,
+) -> CsvCliResult<()>
+where
+    T: Accumulate<I, O> + 'static,
+    I: std::str::FromStr + 'static,
+    O: std::fmt::Display,
+{
+    run_with_init::<T, I, O, fn(I) -> T>(arg_matches, input, parsing_strategy, None)
+}
+
+/// Like `run_and_init`, but creates each accumulator with `initializer` (if it's `Some`)
+/// instead of `Accumulate::new`.
+///
+/// `input` is the input `infer_parsing_strategy` already started reading, if it did.
+fn run_with_init<T, I, O, F>(
+    arg_matches: &ArgMatches,
+    input: Option<PeekedInput>,
+    parsing_strategy: ParsingStrategy,
+    initializer: Option<F>,
+) -> CsvCliResult<()>
+where
+    T: Accumulate<I, O> + 'static,
+    I: std::str::FromStr + 'static,
+    O: std::fmt::Display,
+    F: Fn(I) -> T + 'static,
+{
+    run_with_initializers::<T, I, O, F, fn(I, Function) -> T>(
+        arg_matches,
+        input,
+        parsing_strategy,
+        initializer,
+        None,
+    )
+}
+
+/// Like `run_with_init`, but also creates the accumulators for the functions from `--funcs`
+/// with `function_initializer` (if it's `Some`), for the agg function.
+fn run_with_initializers<T, I, O, F, G>(
+    arg_matches: &ArgMatches,
+    input: Option<PeekedInput>,
     parsing_strategy: ParsingStrategy,
+    initializer: Option<F>,
+    function_initializer: Option<G>,
 ) -> CsvCliResult<()>
 where
     T: Accumulate<I, O>,
     I: std::str::FromStr,
     O: std::fmt::Display,
+    F: Fn(I) -> T + 'static,
+    G: Fn(I, Function) -> T + 'static,
 {
-    // fn run_and_init<T: Accumulate<I,O>, I: std::str::FromStr, O: std::fmt::Display>(arg_matches: &ArgMatches) -> CsvCliResult<()> {
-    let filename = arg_matches.value_of("filename");
+    let filename = input_filename(arg_matches);
+    if let Some(filepath) = filename.filter(|f| f.ends_with(".xlsx")) {
+        let settings = csv_settings(arg_matches, None)?;
+        let rdr = settings.get_reader_from_xlsx(
+            filepath,
+            arg_matches.value_of("sheet"),
+            xlsx_date_format(arg_matches),
+        )?;
+        return agg_from_readers::<T, I, O, F, G, _>(
+            arg_matches,
+            &settings,
+            parsing_strategy,
+            initializer,
+            function_initializer,
+            vec![rdr],
+        );
+    }
+    let input = match input {
+        Some(input) => input,
+        None => PeekedInput::open(filename)?,
+    };
+    let settings = csv_settings(arg_matches, Some(&input))?;
+    let filenames = input_filenames(arg_matches);
+    if filenames.len() > 1 {
+        // the first file is only opened to guess the delimiter, so it's read again like the others
+        let readers = filenames
+            .iter()
+            .map(|filepath| file_reader(arg_matches, &settings, filepath))
+            .collect::<CsvCliResult<Vec<_>>>()?;
+        agg_from_readers::<T, I, O, F, G, _>(
+            arg_matches,
+            &settings,
+            parsing_strategy,
+            initializer,
+            function_initializer,
+            readers,
+        )
+    } else if jsonl_input(arg_matches) {
+        let source = decode_input(arg_matches, input.into_reader())?;
+        let rdr = settings.get_reader_from_jsonl(source)?;
+        agg_from_readers::<T, I, O, F, G, _>(
+            arg_matches,
+            &settings,
+            parsing_strategy,
+            initializer,
+            function_initializer,
+            vec![rdr],
+        )
+    } else {
+        let source = decode_input(arg_matches, input.into_reader())?;
+        let rdr = settings.get_reader_from_reader(source);
+        agg_from_readers::<T, I, O, F, G, _>(
+            arg_matches,
+            &settings,
+            parsing_strategy,
+            initializer,
+            function_initializer,
+            vec![rdr],
+        )
+    }
+}
+
+/// Creates the settings for reading the input from the command-line arguments, guessing the
+/// delimiter from the start of `input` if there isn't one.
+fn csv_settings(
+    arg_matches: &ArgMatches,
+    input: Option<&PeekedInput>,
+) -> CsvCliResult<CsvSettings> {
+    let filename = input_filename(arg_matches);
     let delim_values = if arg_matches.is_present("tab") {
         Some(r"\t")
     } else {
         arg_matches.value_of("delim")
     };
-    let settings =
-        CsvSettings::parse_new(&filename, delim_values, !arg_matches.is_present("noheader"))?;
-    if let Some(filepath) = filename {
-        let rdr = settings.get_reader_from_path(filepath)?;
-        agg_from_reader::<T, I, O, std::fs::File>(arg_matches, &settings, parsing_strategy, rdr)?;
+    // without a delimiter, the delimiter is guessed from the start of the input
+    let sniffable = !filename.is_some_and(|f| f.ends_with(".xlsx"))
+        && !arg_matches.is_present("encoding")
+        && !jsonl_input(arg_matches);
+    let sample = input
+        .filter(|_| delim_values.is_none() && sniffable)
+        .map(PeekedInput::sample);
+    let mut settings = CsvSettings::parse_new(
+        &filename,
+        delim_values,
+        !arg_matches.is_present("noheader"),
+        sample,
+    )?;
+    if let Some(names) = arg_matches.values_of("headernames") {
+        settings.set_header_names(names.map(String::from).collect());
+    }
+    if arg_matches.is_present("notrim") {
+        settings.set_no_trim();
+    }
+    if arg_matches.is_present("oneindexed") {
+        settings.set_one_indexed();
+    }
+    if arg_matches.is_present("strictheaders") {
+        settings.set_strict_headers();
+    }
+    if arg_matches.is_present("cleanheaders") {
+        settings.set_clean_headers();
+    }
+    if let Some(quote) = arg_matches.value_of("quote") {
+        settings.set_quote(quote)?;
+    }
+    if arg_matches.is_present("noquoting") {
+        settings.set_no_quoting();
+    }
+    if let Some(escape) = arg_matches.value_of("escape") {
+        settings.set_escape(escape)?;
+    }
+    Ok(settings)
+}
+
+/// The format Excel dates are written in, so they can be parsed as dates: the first of the -F formats.
+fn xlsx_date_format<'a>(arg_matches: &'a ArgMatches) -> Option<&'a str> {
+    arg_matches
+        .value_of("format")
+        .and_then(|formats| formats.split(';').next())
+}
+
+/// Returns the function, from --agg or the first positional argument.
+fn function_name<'a>(arg_matches: &'a ArgMatches) -> Option<&'a str> {
+    arg_matches
+        .value_of("agg")
+        .or_else(|| arg_matches.value_of("aggfunc"))
+}
+
+/// Returns the name of the input file, or `None` if the input comes from standard input
+/// (either because there isn't a filename or because the filename is `-`).
+fn input_filename<'a>(arg_matches: &'a ArgMatches) -> Option<&'a str> {
+    input_filenames(arg_matches)
+        .first()
+        .copied()
+        .filter(|&f| f != "-")
+}
+
+/// Returns the names of the input files. With --agg, the first positional argument
+/// is the first file rather than the function.
+fn input_filenames<'a>(arg_matches: &'a ArgMatches) -> Vec<&'a str> {
+    let first = arg_matches
+        .value_of("aggfunc")
+        .filter(|_| arg_matches.is_present("agg"));
+    first
+        .into_iter()
+        .chain(arg_matches.values_of("filename").into_iter().flatten())
+        .collect()
+}
+
+/// Whether the input is JSON Lines, either with `--format-in jsonl` or because
+/// the (first) file ends with `.jsonl`
+fn jsonl_input(arg_matches: &ArgMatches) -> bool {
+    match arg_matches.value_of("formatin") {
+        Some(format) => format == "jsonl",
+        None => input_filename(arg_matches).is_some_and(|f| f.ends_with(".jsonl")),
+    }
+}
+
+/// Transcodes the input into UTF-8 with `--encoding`, if there is one.
+fn decode_input<'a>(
+    arg_matches: &ArgMatches,
+    source: Box<dyn io::Read + 'a>,
+) -> CsvCliResult<Box<dyn io::Read + 'a>> {
+    Ok(match arg_matches.value_of("encoding") {
+        Some(label) => Box::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(parse_encoding(label)?))
+                .build(source),
+        ),
+        None => source,
+    })
+}
+
+/// Opens one of several input files. Every file is read with the same settings, so each one
+/// needs to be a CSV (or JSON Lines) file rather than standard input or an Excel file.
+fn file_reader(
+    arg_matches: &ArgMatches,
+    settings: &CsvSettings,
+    filepath: &str,
+) -> CsvCliResult<csv::Reader<Box<dyn io::Read>>> {
+    if filepath == "-" || filepath.ends_with(".xlsx") {
+        return Err(CsvCliError::InvalidConfiguration(format!(
+            "`{}` can't be combined with other files. With more than one file, every file needs to be a CSV file",
+            filepath
+        )));
+    }
+    let source = decode_input(arg_matches, Box::new(fs::File::open(filepath)?))?;
+    if jsonl_input(arg_matches) {
+        let data = settings.jsonl_to_csv(source)?;
+        return Ok(settings.get_reader_from_converted(Box::new(io::Cursor::new(data))));
+    }
+    Ok(settings.get_reader_from_reader(source))
+}
+
+/// Chooses how to parse values for the `--infer` flag, by sampling the first non-empty values
+/// in the values column: as numbers if they all parse as numbers, as dates if they all parse as dates,
+/// and as text otherwise.
+///
+/// Standard input and named pipes can only be read once, so this returns the input it read
+/// (if it isn't an Excel file) for `run_with_init` to read again from the start. It only reads
+/// as far as the sampled values.
+fn infer_parsing_strategy(
+    arg_matches: &ArgMatches,
+) -> CsvCliResult<(ParsingStrategy, Option<PeekedInput>)> {
+    let filename = input_filename(arg_matches);
+    let (values, input) = if let Some(filepath) = filename.filter(|f| f.ends_with(".xlsx")) {
+        let settings = csv_settings(arg_matches, None)?;
+        let rdr = settings.get_reader_from_xlsx(
+            filepath,
+            arg_matches.value_of("sheet"),
+            xlsx_date_format(arg_matches),
+        )?;
+        (sample_values(arg_matches, &settings, rdr)?, None)
     } else {
-        let rdr = settings.get_reader_from_stdin();
-        agg_from_reader::<T, I, O, io::Stdin>(arg_matches, &settings, parsing_strategy, rdr)?;
+        let mut input = PeekedInput::open(filename)?;
+        let settings = csv_settings(arg_matches, Some(&input))?;
+        let values = input.peek_with(|source| {
+            let source = decode_input(arg_matches, source)?;
+            match jsonl_input(arg_matches) {
+                true => sample_values(
+                    arg_matches,
+                    &settings,
+                    settings.get_reader_from_jsonl(source)?,
+                ),
+                false => sample_values(
+                    arg_matches,
+                    &settings,
+                    settings.get_reader_from_reader(source),
+                ),
+            }
+        })?;
+        (values, Some(input))
+    };
+    let strategy = if values.is_empty() {
+        ParsingStrategy::Text
+    } else if values
+        .iter()
+        .all(|v| match arg_matches.is_present("decimalcomma") {
+            true => DecimalWrapper::parse_decimal_comma(v).is_ok(),
+            false => v.parse::<DecimalWrapper>().is_ok(),
+        })
+    {
+        ParsingStrategy::Numeric
+    } else if values.iter().all(|v| v.parse::<CustomDateObject>().is_ok()) {
+        ParsingStrategy::Date
+    } else {
+        ParsingStrategy::Text
+    };
+    Ok((strategy, input))
+}
+
+/// Combines the fields given on the command line in `arg` with the lines of the file in `file_arg`.
+fn field_descriptors(
+    arg_matches: &ArgMatches,
+    arg: &str,
+    file_arg: &str,
+) -> CsvCliResult<Vec<String>> {
+    let mut descriptors: Vec<String> = arg_matches
+        .values_of(arg)
+        .map_or(vec![], |v| v.map(String::from).collect());
+    if let Some(path) = arg_matches.value_of(file_arg) {
+        descriptors.extend(read_field_descriptors(path)?);
     }
-    Ok(())
+    Ok(descriptors)
+}
+
+/// Finds the values field from -v or --val-file, if either is present.
+fn value_descriptor(arg_matches: &ArgMatches) -> CsvCliResult<Option<String>> {
+    if let Some(path) = arg_matches.value_of("valfile") {
+        let mut descriptors = read_field_descriptors(path)?;
+        if descriptors.len() != 1 {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "The --val-file file needs to hold exactly one field, but `{}` has {} non-empty lines",
+                path,
+                descriptors.len()
+            )));
+        }
+        return Ok(descriptors.pop());
+    }
+    Ok(arg_matches.value_of("value").map(String::from))
 }
 
-fn agg_from_reader<T, I, O, R>(
+/// Reads the first `SAMPLE_CHECK_SIZE` non-empty values in the values column.
+fn sample_values<R: io::Read>(
+    arg_matches: &ArgMatches,
+    settings: &CsvSettings,
+    mut rdr: csv::Reader<R>,
+) -> CsvCliResult<Vec<String>> {
+    let headers = settings.get_headers(&mut rdr)?;
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    // with --coalesce, the value is the first non-empty one of its fields
+    let values_cols = match (
+        arg_matches.value_of("coalesce"),
+        value_descriptor(arg_matches)?,
+    ) {
+        (Some(fields), _) => settings.get_field_indexes(&vec![fields], &headers)?,
+        (None, Some(value)) => vec![settings.get_field_index(&value, &headers)?],
+        (None, None) => vec![0],
+    };
+    let skip_null = arg_matches.is_present("empty");
+    let mut values = Vec::new();
+    let mut record = csv::StringRecord::new();
+    while values.len() < SAMPLE_CHECK_SIZE {
+        match rdr.read_record(&mut record).map_err(CsvCliError::from) {
+            Ok(true) => {}
+            Ok(false) => break,
+            // the aggregator reports these when it skips them
+            Err(CsvCliError::UnequalLengths { .. }) if arg_matches.is_present("lenient") => {
+                continue
+            }
+            Err(err) => return Err(err),
+        }
+        let value = values_cols
+            .iter()
+            .filter_map(|&col| record.get(col))
+            .find(|value| !(value.is_empty() || skip_null && is_null_value(value)));
+        if let Some(value) = value {
+            values.push(value.to_string());
+        }
+    }
+    Ok(values)
+}
+
+/// Creates a progress bar (on standard error) tracking the bytes read from a file,
+/// or a spinner counting records if the input comes from standard input.
+fn progress_bar(filename: Option<&str>) -> CsvCliResult<ProgressBar> {
+    let progress = match filename {
+        Some(filepath) => {
+            let bar = ProgressBar::new(fs::metadata(filepath)?.len());
+            bar.set_style(
+                ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} ({eta} left)")
+                    .unwrap(),
+            );
+            bar
+        }
+        None => {
+            let spinner = ProgressBar::new_spinner();
+            spinner.set_style(ProgressStyle::with_template("{spinner} {pos} records").unwrap());
+            spinner
+        }
+    };
+    Ok(progress)
+}
+
+/// Finds the encoding matching a label like `latin1` or `windows-1252`.
+fn parse_encoding(label: &str) -> CsvCliResult<&'static Encoding> {
+    Encoding::for_label(label.as_bytes()).ok_or_else(|| {
+        CsvCliError::InvalidConfiguration(format!("`{}` is not a supported encoding", label))
+    })
+}
+
+/// Aggregates the records of each reader (one for each input file) and writes the results.
+fn agg_from_readers<T, I, O, F, G, R>(
     arg_matches: &ArgMatches,
     settings: &CsvSettings,
     parsing_strategy: ParsingStrategy,
-    mut reader: csv::Reader<R>,
+    initializer: Option<F>,
+    function_initializer: Option<G>,
+    readers: Vec<csv::Reader<R>>,
 ) -> CsvCliResult<()>
 where
     T: Accumulate<I, O>,
     I: std::str::FromStr,
     O: std::fmt::Display,
+    F: Fn(I) -> T + 'static,
+    G: Fn(I, Function) -> T + 'static,
     R: io::Read,
 {
-    let headers = reader.headers()?;
-    let mut agg = get_aggregator::<T, I, O>(
-        arg_matches,
-        &settings,
-        parsing_strategy,
-        &headers.iter().collect(),
-    )?;
-    agg.aggregate(&mut reader)?;
-    agg.write_results(&mut csv::Writer::from_writer(io::stdout()))?;
+    let mut readers = readers.into_iter();
+    let mut reader = readers.next().unwrap();
+    let headers = settings.get_headers(&mut reader)?;
+    let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+    let raw_headers = reader.headers()?.clone();
+    let initializer = initializer.map(Rc::new);
+    let function_initializer = function_initializer.map(Rc::new);
+    let new_aggregator = || {
+        prepare_aggregator(
+            arg_matches,
+            settings,
+            parsing_strategy,
+            &initializer,
+            &function_initializer,
+            &raw_headers,
+            &headers,
+        )
+    };
+    let mut agg = new_aggregator()?;
+    let max_width = max_width(arg_matches)?;
+    if arg_matches.is_present("check") {
+        // `print!` panics on a closed pipe, so this writes to stdout directly
+        write!(io::stdout(), "{}", agg.describe_config())?;
+        return Ok(());
+    }
+    // the progress bar follows the size of the first file, so it's only shown for a single input
+    if arg_matches.is_present("progress") && readers.len() == 0 {
+        agg.set_progress(progress_bar(input_filename(arg_matches))?);
+    }
+    let split = match arg_matches.value_of("splitby") {
+        Some(field) => Some(settings.get_field_index(field, &headers)?),
+        None => None,
+    };
+    // with --split-by, `agg` only reads the records, handing each one to the aggregator for its split value
+    let mut parts = IndexMap::new();
+    let mut aggregate = |agg: &mut Aggregator<T, I, O>, reader: &mut csv::Reader<R>| match split {
+        Some(split_col) => agg.aggregate_split(reader, split_col, &mut parts, new_aggregator),
+        None => agg.aggregate(reader),
+    };
+    aggregate(&mut agg, &mut reader)?;
+    for (filepath, mut reader) in input_filenames(arg_matches)
+        .into_iter()
+        .skip(1)
+        .zip(readers)
+    {
+        if settings.get_headers(&mut reader)? != headers {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "`{}` has different fields than `{}`, so they can't be aggregated together",
+                filepath,
+                input_filename(arg_matches).unwrap_or_default()
+            )));
+        }
+        // each part already gets the records of every file
+        if arg_matches.is_present("mergefiles") && split.is_none() {
+            let mut file_agg = new_aggregator()?;
+            file_agg.aggregate(&mut reader)?;
+            agg.merge(file_agg)?;
+        } else {
+            aggregate(&mut agg, &mut reader)?;
+        }
+    }
+    agg.check_has_records(!arg_matches.is_present("noheader"))?;
+    let output_dir = match arg_matches.value_of("outputdir") {
+        Some(output_dir) => output_dir,
+        None => {
+            print_warnings(arg_matches, &agg);
+            return write_output(arg_matches, &mut agg, &headers, max_width, io::stdout());
+        }
+    };
+    if let Some(summary) = agg.ragged_summary() {
+        eprint!("{}", summary);
+    }
+    // checking every name first means a clash doesn't leave some of the files written
+    let mut filenames: HashMap<String, &str> = HashMap::new();
+    for value in parts.keys() {
+        let filename = split_filename(value);
+        if let Some(other) = filenames.insert(filename.clone(), value) {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "The split values `{}` and `{}` would both be written to `{}`",
+                other, value, filename
+            )));
+        }
+    }
+    fs::create_dir_all(output_dir)?;
+    for (value, mut part) in parts {
+        let filename = split_filename(&value);
+        print_warnings(arg_matches, &part);
+        let file = fs::File::create(std::path::Path::new(output_dir).join(&filename))?;
+        write_output(
+            arg_matches,
+            &mut part,
+            &headers,
+            max_width,
+            io::BufWriter::new(file),
+        )?;
+    }
     Ok(())
 }
 
+/// The name of the file `--split-by` writes the pivot table for a split value to. Characters
+/// that can't be in file names on some platforms (like `/`) are replaced with `_`, and an empty
+/// value is written to `_.csv`.
+fn split_filename(value: &str) -> String {
+    let name: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match name.is_empty() {
+        true => "_.csv".to_string(),
+        false => format!("{}.csv", name),
+    }
+}
+
+/// Prints the warnings about an aggregation (and the summaries of anything it skipped) to standard error
+fn print_warnings<T, I, O>(arg_matches: &ArgMatches, agg: &Aggregator<T, I, O>)
+where
+    T: Accumulate<I, O>,
+    I: std::str::FromStr,
+    O: std::fmt::Display,
+{
+    if let Some(summary) = agg.failure_summary() {
+        eprint!("{}", summary);
+    }
+    if let Some(summary) = agg.ragged_summary() {
+        eprint!("{}", summary);
+    }
+    if let Some((line_num, value)) = agg.precision_loss() {
+        eprintln!(
+            "Warning: `{}` in record {} has more digits than clipivot can represent exactly, \
+             so the results may be wrong",
+            value, line_num
+        );
+    }
+    for row in agg.carry_conflicts() {
+        eprintln!(
+            "Warning: `{}` has more than one value in the `{}` row, so only its first value is displayed",
+            arg_matches.value_of("carry").unwrap_or_default(),
+            row
+        );
+    }
+}
+
+/// Writes the results of an aggregation to `out`, in the format chosen on the command line
+fn write_output<T, I, O, W>(
+    arg_matches: &ArgMatches,
+    agg: &mut Aggregator<T, I, O>,
+    headers: &[&str],
+    max_width: Option<usize>,
+    mut out: W,
+) -> CsvCliResult<()>
+where
+    T: Accumulate<I, O>,
+    I: std::str::FromStr,
+    O: std::fmt::Display,
+    W: io::Write,
+{
+    let missing = if let Some(labels) = arg_matches.values_of("colskeep") {
+        agg.keep_columns(&labels.collect::<Vec<&str>>())?
+    } else if let Some(labels) = arg_matches.values_of("colsdrop") {
+        agg.drop_columns(&labels.collect::<Vec<&str>>())?
+    } else {
+        vec![]
+    };
+    for label in missing {
+        eprintln!("Warning: `{}` is not a column in the pivot table", label);
+    }
+    let terminator = if arg_matches.is_present("crlf") {
+        csv::Terminator::CRLF
+    } else {
+        csv::Terminator::Any(b'\n')
+    };
+    if let Some(cell) = arg_matches.value_of("explain") {
+        let records = agg.explained_records();
+        if records.is_empty() {
+            eprintln!("Warning: no records went into the `{}` cell", cell);
+        }
+        let mut writer = csv::WriterBuilder::new()
+            .terminator(terminator)
+            .from_writer(&mut out);
+        if !arg_matches.is_present("noheader") {
+            writer.write_record(headers)?;
+        }
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.flush()?;
+        return Ok(());
+    }
+    if arg_matches.is_present("headercomment") {
+        let line_ending = if arg_matches.is_present("crlf") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        write!(out, "{}{}", agg.header_comment(headers), line_ending)?;
+    }
+    if function_name(arg_matches) == Some("shape") {
+        let mut writer = csv::WriterBuilder::new()
+            .terminator(terminator)
+            .from_writer(&mut out);
+        agg.write_shape(&mut writer)?;
+    } else if function_name(arg_matches) == Some("pivot-longer") {
+        let mut writer = csv::WriterBuilder::new()
+            .terminator(terminator)
+            .from_writer(&mut out);
+        agg.write_long(
+            &mut writer,
+            arg_matches.value_of("namesto").unwrap_or("name"),
+            arg_matches.value_of("valuesto").unwrap_or("value"),
+        )?;
+    } else if arg_matches.is_present("pretty") {
+        agg.write_pretty(&mut out, max_width)?;
+    } else {
+        let mut writer = csv::WriterBuilder::new()
+            .terminator(terminator)
+            .from_writer(&mut out);
+        agg.write_results(&mut writer)?;
+    }
+    if arg_matches.is_present("stats") {
+        eprint!("{}", agg.stats());
+    }
+    Ok(())
+}
+
+/// Creates an aggregator from the command-line arguments, ready to aggregate the records of a
+/// reader whose header row is `raw_headers`
+fn prepare_aggregator<T, I, O, F, G>(
+    arg_matches: &ArgMatches,
+    settings: &CsvSettings,
+    parsing_strategy: ParsingStrategy,
+    initializer: &Option<Rc<F>>,
+    function_initializer: &Option<Rc<G>>,
+    raw_headers: &csv::StringRecord,
+    headers: &Vec<&str>,
+) -> CsvCliResult<Aggregator<T, I, O>>
+where
+    T: Accumulate<I, O>,
+    I: std::str::FromStr,
+    O: std::fmt::Display,
+    F: Fn(I) -> T + 'static,
+    G: Fn(I, Function) -> T + 'static,
+{
+    let mut agg = get_aggregator::<T, I, O>(arg_matches, settings, parsing_strategy, headers)?;
+    // the first field of pivot-longer's output needs a name, like the other two
+    if arg_matches.is_present("headernames") || function_name(arg_matches) == Some("pivot-longer") {
+        agg.label_corner(headers);
+    }
+    if arg_matches.is_present("skiprepeatedheader") {
+        agg.set_skip_repeated_header(raw_headers.clone());
+    }
+    if let Some(init) = initializer {
+        let init = Rc::clone(init);
+        agg.set_initializer(move |item| init(item));
+    }
+    if let (Some(init), Some(funcs)) = (function_initializer, arg_matches.value_of("funcs")) {
+        let init = Rc::clone(init);
+        agg.set_functions(agg_functions(funcs)?, move |item, function| {
+            init(item, function)
+        });
+    }
+    Ok(agg)
+}
+
 fn get_aggregator<T, I, O>(
     arg_matches: &ArgMatches,
     settings: &CsvSettings,
@@ -170,125 +1436,1089 @@ where
     I: std::str::FromStr,
     O: std::fmt::Display,
 {
-    let str_indexes = arg_matches
-        .values_of("rows")
-        .map_or(vec![], |v| v.collect());
-    let index_cols = settings.get_field_indexes(&str_indexes, headers)?;
-    let str_cols = arg_matches
-        .values_of("columns")
-        .map_or(vec![], |v| v.collect());
-    let column_cols = settings.get_field_indexes(&str_cols, headers)?;
-    let values_col = settings.get_field_index(arg_matches.value_of("value").unwrap(), headers)?;
+    let str_indexes = field_descriptors(arg_matches, "rows", "rowsfile")?;
+    let index_cols =
+        settings.get_field_indexes(&str_indexes.iter().map(String::as_str).collect(), headers)?;
+    let str_cols = field_descriptors(arg_matches, "columns", "colsfile")?;
+    let column_cols =
+        settings.get_field_indexes(&str_cols.iter().map(String::as_str).collect(), headers)?;
+    let value = value_descriptor(arg_matches)?;
     let skip_null = arg_matches.is_present("empty");
-    let row_ordering_pair = (
-        arg_matches.is_present("ascrow"),
-        arg_matches.is_present("descrow"),
-    );
-    let row_order = match row_ordering_pair {
-        (true, true) => Err(CsvCliError::InvalidConfiguration(
-            "You can only enter one of the -A and -D flags".to_string(),
-        )),
-        (true, false) => Ok(OutputOrder::Ascending),
-        (false, true) => Ok(OutputOrder::Descending),
-        (false, false) => Ok(OutputOrder::IndexOrder),
-    }?;
-    let column_ordering_pair = (
-        arg_matches.is_present("indexcol"),
-        arg_matches.is_present("desccol"),
-    );
-    let column_order = match column_ordering_pair {
-        (true, true) => Err(CsvCliError::InvalidConfiguration(
-            "You can only enter one of the -I and -R flags".to_string(),
-        )),
-        (true, false) => Ok(OutputOrder::IndexOrder),
-        (false, true) => Ok(OutputOrder::Descending),
-        (false, false) => Ok(OutputOrder::Ascending),
-    }?;
-    let agg = Aggregator::new(
+    let aggfunc = function_name(arg_matches).unwrap_or_default();
+    // crosstab and shape only look at the rows and columns of each record
+    let without_values = matches!(aggfunc, "crosstab" | "shape");
+    let counts_records = aggfunc == "count" || without_values;
+    let expr = match arg_matches.value_of("expr") {
+        Some(_) if without_values => {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "{} counts records, so it doesn't use --expr. Use count with --expr instead",
+                aggfunc
+            )))
+        }
+        Some(source) => Some(Expr::parse(source, |name| {
+            settings.get_field_index(name, headers)
+        })?),
+        None => None,
+    };
+    let coalesce = match arg_matches.value_of("coalesce") {
+        Some(_) if without_values => {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+            "{} counts records, so it doesn't use --coalesce. Use count with --coalesce instead",
+            aggfunc
+        )))
+        }
+        Some(fields) => settings.get_field_indexes(&vec![fields], headers)?,
+        None => vec![],
+    };
+    if aggfunc == "pivot-longer"
+        && (value.is_some() || expr.is_some() || !coalesce.is_empty() || !column_cols.is_empty())
+    {
+        return Err(CsvCliError::InvalidConfiguration(
+            "pivot-longer reshapes every field besides the -r fields, so it doesn't use \
+             -c, -v, --expr or --coalesce"
+                .to_string(),
+        ));
+    }
+    let values_col = match &value {
+        // the expression is evaluated on the whole record, so the values column isn't used
+        None if expr.is_some() => 0,
+        // pivot-longer's values come from every field besides the row fields
+        None if aggfunc == "pivot-longer" => 0,
+        None if !coalesce.is_empty() => coalesce[0],
+        Some(_) if without_values => {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "{} counts records, so it doesn't use a values column. Use count with -v instead",
+                aggfunc
+            )))
+        }
+        Some(value) => settings.get_field_index(value, headers)?,
+        // every record has a first field, so counting it counts every record
+        None if counts_records && !skip_null => 0,
+        None if counts_records => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The -e flag needs a values column (-v) to check for empty values".to_string(),
+            ))
+        }
+        None => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "You need to choose a values column with -v (it's only optional for count, crosstab and shape)"
+                    .to_string(),
+            ))
+        }
+    };
+    // with --order-by, first and last compare the order field and return the values field,
+    // the way maxby and minby compare the values field and return the --pick field,
+    // so the aggregator parses the order field while the output is labeled with the values field
+    let (parsed_col, order_pick) = match arg_matches.value_of("orderby") {
+        Some(order_by) if matches!(aggfunc, "first" | "last") => (
+            settings.get_field_index(order_by, headers)?,
+            Some(values_col),
+        ),
+        Some(_) => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --order-by option only works with the first and last functions".to_string(),
+            ))
+        }
+        None => (values_col, None),
+    };
+    let row_flags = [
+        ("ascrow", "-A"),
+        ("descrow", "-D"),
+        ("indexrow", "--index-rows"),
+    ];
+    let column_flags = [
+        ("asccol", "--asc-cols"),
+        ("desccol", "-R"),
+        ("indexcol", "-I"),
+    ];
+    let (row_order, column_order) = if arg_matches.is_present("sort") {
+        if let Some((_, flag)) = row_flags
+            .iter()
+            .chain(column_flags.iter())
+            .chain([("sortcolsbytotal", "--sort-cols-by-total")].iter())
+            .find(|(name, _)| arg_matches.is_present(name))
+        {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "The --sort option sorts both the rows and the columns, so you can't combine it with {}",
+                flag
+            )));
+        }
+        // only `lexical` is allowed
+        (OutputOrder::Ascending, OutputOrder::Ascending)
+    } else if arg_matches.is_present("sortcolsbytotal") {
+        if let Some((_, flag)) = column_flags
+            .iter()
+            .find(|(name, _)| arg_matches.is_present(name))
+        {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "The --sort-cols-by-total flag sorts the columns, so you can't combine it with {}",
+                flag
+            )));
+        }
+        (
+            get_output_order(arg_matches, row_flags, OutputOrder::IndexOrder)?,
+            OutputOrder::IndexOrder,
+        )
+    } else {
+        (
+            get_output_order(arg_matches, row_flags, OutputOrder::IndexOrder)?,
+            // pivot-longer's columns are fields, which stay in the order they're in
+            get_output_order(
+                arg_matches,
+                column_flags,
+                match aggfunc {
+                    "pivot-longer" => OutputOrder::IndexOrder,
+                    _ => OutputOrder::Ascending,
+                },
+            )?,
+        )
+    };
+    // pivot-longer aggregates every field that doesn't identify the record
+    let melted = (0..headers.len())
+        .filter(|i| !index_cols.contains(i))
+        .map(|i| (i, headers[i].to_string()))
+        .collect();
+    let index_cols_empty = index_cols.is_empty();
+    let column_cols_empty = column_cols.is_empty();
+    let mut agg = Aggregator::new(
         index_cols,
         column_cols,
-        values_col,
+        parsed_col,
         skip_null,
         row_order,
         column_order,
         parsing_strategy,
     );
+    match (function_name(arg_matches), arg_matches.value_of("bins")) {
+        (Some("histogram"), Some(bins)) => agg.set_bins(bins.parse()?),
+        (Some("histogram"), None) => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The histogram function needs bin edges from --bins".to_string(),
+            ))
+        }
+        (_, Some(_)) => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --bins option only works with the histogram function".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    if let Some(bucket) = arg_matches.value_of("rowbucket") {
+        if index_cols_empty {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --row-bucket option needs a row field (-r) with dates".to_string(),
+            ));
+        }
+        agg.set_row_bucket(bucket.parse().map_err(CsvCliError::InvalidConfiguration)?);
+    }
+    if let Some(carry) = arg_matches.value_of("carry") {
+        if index_cols_empty {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --carry option needs a row field (-r) to carry values into".to_string(),
+            ));
+        }
+        let carry_col = settings.get_field_index(carry, headers)?;
+        agg.set_carry(carry_col, headers[carry_col]);
+    }
+    if let Some((pattern, replacement)) =
+        label_replacement(arg_matches, "rowreplace", "--row-replace")?
+    {
+        if index_cols_empty {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --row-replace option needs a row field (-r) to replace values in".to_string(),
+            ));
+        }
+        agg.set_row_replace(pattern, replacement);
+    }
+    if let Some((pattern, replacement)) =
+        label_replacement(arg_matches, "colreplace", "--col-replace")?
+    {
+        if column_cols_empty {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --col-replace option needs a column field (-c) to replace values in"
+                    .to_string(),
+            ));
+        }
+        agg.set_col_replace(pattern, replacement);
+    }
+    if let Some(explode) = arg_matches.value_of("explode") {
+        let separator = arg_matches.value_of("explodesep").unwrap_or(";");
+        if separator.is_empty() {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --explode-sep separator can't be empty".to_string(),
+            ));
+        }
+        agg.set_explode(settings.get_field_index(explode, headers)?, separator);
+    }
+    if arg_matches.is_present("headercomment") || arg_matches.is_present("multiheader") {
+        let value_name = match (&expr, value) {
+            (Some(expr), _) => expr.to_string(),
+            (None, Some(_)) => headers[values_col].to_string(),
+            (None, None) if !coalesce.is_empty() => format!(
+                "coalesce({})",
+                coalesce
+                    .iter()
+                    .map(|&i| headers[i])
+                    .collect::<Vec<&str>>()
+                    .join(", ")
+            ),
+            (None, None) => "*".to_string(),
+        };
+        agg.set_value_label(&format!(
+            "{}({})",
+            function_name(arg_matches).unwrap_or_default(),
+            value_name
+        ));
+        agg.set_multi_header(arg_matches.is_present("multiheader"));
+    }
+    let decimal_comma = arg_matches.is_present("decimalcomma");
+    if decimal_comma {
+        if parsing_strategy != ParsingStrategy::Numeric {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --decimal-comma flag only works with functions that parse values as numbers"
+                    .to_string(),
+            ));
+        }
+        agg.set_decimal_comma();
+    }
+    if let Some(mut expr) = expr {
+        if decimal_comma {
+            expr.set_decimal_comma();
+        }
+        agg.set_expr(expr);
+    }
+    if !coalesce.is_empty() {
+        agg.set_coalesce(coalesce);
+    }
+    // these functions parse values as `Decimal`s, which quietly drop digits past the 28th or so
+    let parses_decimals = matches!(
+        function_name(arg_matches),
+        Some(
+            "agg"
+                | "countunique"
+                | "mean"
+                | "median"
+                | "percentilerank"
+                | "pivot-longer"
+                | "pivot-wider"
+                | "range"
+                | "spread"
+                | "sum"
+        )
+    );
+    if parses_decimals && parsing_strategy == ParsingStrategy::Numeric {
+        agg.set_precision_check();
+    }
+    if arg_matches.is_present("zeropad") {
+        agg.set_zero_pad(arg_matches.is_present("keeppadding"));
+    }
+    if arg_matches.is_present("nooutputheader") {
+        agg.set_no_output_header();
+    }
+    if let Some(separator) = arg_matches.value_of("keysep") {
+        agg.set_key_separator(separator);
+    }
+    if let Some(columns) = arg_matches.values_of("addcolumn") {
+        for column in columns {
+            let (name, value) = column
+                .split_once('=')
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| {
+                    CsvCliError::InvalidConfiguration(format!(
+                        "--add-column needs a NAME=VALUE pair, like `source=survey`, not `{}`",
+                        column
+                    ))
+                })?;
+            agg.add_constant(name, value);
+        }
+    }
+    if let Some(cell) = arg_matches.value_of("explain") {
+        let (row, column) = cell.split_once('|').unwrap_or((cell, "total"));
+        agg.set_explain(row, column);
+    }
+    if let Some(na_rep) = arg_matches.value_of("narep") {
+        agg.set_na_rep(na_rep);
+    }
+    if let Some(na_numbers) = arg_matches.values_of("nanumber") {
+        if parsing_strategy != ParsingStrategy::Numeric {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --na-number option only works with numeric aggregations".to_string(),
+            ));
+        }
+        let na_numbers = na_numbers
+            .map(|na_number| {
+                na_number
+                    .parse::<DecimalWrapper>()
+                    .map(|v| v.item)
+                    .map_err(|_| {
+                        CsvCliError::InvalidConfiguration(format!(
+                            "Could not parse `{}` from --na-number as a number",
+                            na_number
+                        ))
+                    })
+            })
+            .collect::<CsvCliResult<Vec<Decimal>>>()?;
+        agg.set_na_numbers(na_numbers);
+    }
+    if arg_matches.is_present("lenient") {
+        agg.set_lenient();
+    }
+    if arg_matches.is_present("nullskiprows") {
+        agg.set_skip_empty_keys(match arg_matches.value_of("nullskipmode") {
+            Some("all") => EmptyKeys::All,
+            _ => EmptyKeys::Any,
+        });
+    }
+    if arg_matches.is_present("skiperrors") {
+        agg.set_skip_errors(true);
+    }
+    if arg_matches.is_present("samplecheck") {
+        agg.set_sample_check(SAMPLE_CHECK_SIZE);
+    }
+    if arg_matches.is_present("withcount") {
+        if parsing_strategy != ParsingStrategy::Numeric {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --with-count flag only works with numeric aggregations".to_string(),
+            ));
+        }
+        agg.set_count_format(
+            arg_matches
+                .value_of("countformat")
+                .unwrap_or(DEFAULT_COUNT_FORMAT),
+        );
+    }
+    if arg_matches.is_present("match") && function_name(arg_matches) != Some("countif") {
+        return Err(CsvCliError::InvalidConfiguration(
+            "The --match option only works with the countif function".to_string(),
+        ));
+    }
+    match (function_name(arg_matches), arg_matches.value_of("funcs")) {
+        // the functions are set in `prepare_aggregator`, along with their initializer
+        (Some("agg"), Some(funcs)) => {
+            agg_functions(funcs)?;
+        }
+        (Some("agg"), None) => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The agg function needs a list of functions from --funcs, like sum,mean,count"
+                    .to_string(),
+            ))
+        }
+        (_, Some(_)) => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --funcs option only works with the agg function".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    match (
+        function_name(arg_matches),
+        arg_matches.value_of("onconflict"),
+    ) {
+        (Some("spread" | "pivot-longer" | "pivot-wider"), None | Some("error")) => {
+            agg.set_unique_cells()
+        }
+        (Some("spread" | "pivot-longer" | "pivot-wider"), _) => {}
+        (_, Some(_)) => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --on-conflict option only works with the spread, pivot-wider and pivot-longer functions"
+                    .to_string(),
+            ))
+        }
+        _ => {}
+    }
+    if aggfunc == "pivot-longer" {
+        if index_cols_empty {
+            return Err(CsvCliError::InvalidConfiguration(
+                "pivot-longer needs the fields that identify each record with -r (like tidyr's id_cols)"
+                    .to_string(),
+            ));
+        }
+        agg.set_melt(melted);
+    } else if arg_matches.is_present("namesto") || arg_matches.is_present("valuesto") {
+        return Err(CsvCliError::InvalidConfiguration(
+            "The --names-to and --values-to options only work with the pivot-longer function"
+                .to_string(),
+        ));
+    }
+    if arg_matches.is_present("at") && function_name(arg_matches) != Some("percentilerank") {
+        return Err(CsvCliError::InvalidConfiguration(
+            "The --at option only works with the percentilerank function".to_string(),
+        ));
+    }
+    agg.set_row_count(arg_matches.is_present("rowcount"));
+    agg.set_col_count(arg_matches.is_present("colcount"));
+    match (function_name(arg_matches), arg_matches.value_of("pick")) {
+        (Some("maxby") | Some("minby"), Some(pick)) => {
+            agg.set_pick(settings.get_field_index(pick, headers)?)
+        }
+        (Some("maxby") | Some("minby"), None) => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The maxby and minby functions need a field to return the value of from --pick"
+                    .to_string(),
+            ))
+        }
+        (_, Some(_)) => {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --pick option only works with the maxby and minby functions".to_string(),
+            ))
+        }
+        _ => {}
+    }
+    if let Some(col) = order_pick {
+        agg.set_pick(col);
+    }
+    if let Some(weight) = arg_matches.value_of("weight") {
+        if aggfunc != "count" {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --weight option only works with the count function".to_string(),
+            ));
+        }
+        agg.set_weight(settings.get_field_index(weight, headers)?);
+    }
+    if let Some(precision) = arg_matches.value_of("precision") {
+        if parsing_strategy != ParsingStrategy::Numeric
+            || function_name(arg_matches) == Some("histogram")
+        {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --precision option only works with numeric aggregations".to_string(),
+            ));
+        }
+        let digits = match precision.parse() {
+            Ok(digits) if digits <= MAX_PRECISION => digits,
+            _ => {
+                return Err(CsvCliError::InvalidConfiguration(format!(
+                    "--precision needs a whole number of digits from 0 to {}, not `{}`",
+                    MAX_PRECISION, precision
+                )))
+            }
+        };
+        let notation = match arg_matches.value_of("notation") {
+            Some("general") => Notation::General,
+            _ => Notation::Fixed,
+        };
+        agg.set_precision(digits, notation);
+    }
+    if let Some(limit) = arg_matches.value_of("limitinput") {
+        match limit.parse() {
+            Ok(limit) if limit > 0 => agg.set_input_limit(limit),
+            _ => {
+                return Err(CsvCliError::InvalidConfiguration(format!(
+                    "--limit-input needs a positive whole number of records, not `{}`",
+                    limit
+                )))
+            }
+        }
+    }
+    if arg_matches.is_present("groupdigits") {
+        let counts = matches!(
+            function_name(arg_matches),
+            Some("count" | "countunique" | "crosstab")
+        );
+        if parsing_strategy != ParsingStrategy::Numeric && !counts {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The --group-digits flag only works with counts and numeric aggregations"
+                    .to_string(),
+            ));
+        }
+        let (group_separator, decimal_separator) = match arg_matches.is_present("decimalcomma") {
+            true => (".", ","),
+            false => (",", "."),
+        };
+        agg.set_digit_grouping(
+            arg_matches.value_of("groupsep").unwrap_or(group_separator),
+            arg_matches
+                .value_of("decimalsep")
+                .unwrap_or(decimal_separator),
+        );
+    } else if arg_matches.is_present("decimalcomma") {
+        // without grouping, this only replaces the decimal point
+        agg.set_digit_grouping("", ",");
+    }
+    if arg_matches.is_present("cumulative") {
+        match function_name(arg_matches) {
+            Some("count") | Some("crosstab") | Some("sum") => agg.set_cumulative(true),
+            _ => {
+                return Err(CsvCliError::InvalidConfiguration(
+                    "The --cumulative flag only works with the count, crosstab and sum functions"
+                        .to_string(),
+                ))
+            }
+        }
+    }
+    if arg_matches.is_present("sortcolsbytotal") {
+        match function_name(arg_matches) {
+            Some("count") | Some("crosstab") | Some("sum") => agg.set_sort_cols_by_total(),
+            _ => {
+                return Err(CsvCliError::InvalidConfiguration(
+                    "The --sort-cols-by-total flag only works with the count, crosstab and sum functions"
+                        .to_string(),
+                ))
+            }
+        }
+    }
+    if arg_matches.is_present("percent") {
+        match function_name(arg_matches) {
+            Some("count") | Some("crosstab") | Some("sum") => {
+                agg.set_row_percent(arg_matches.is_present("keepraw"))
+            }
+            Some("rate") if !arg_matches.is_present("keepraw") => {}
+            Some("rate") => {
+                return Err(CsvCliError::InvalidConfiguration(
+                    "The --keep-raw flag only works with the count, crosstab and sum functions"
+                        .to_string(),
+                ))
+            }
+            _ => return Err(CsvCliError::InvalidConfiguration(
+                "The --percent flag only works with the count, crosstab, rate and sum functions"
+                    .to_string(),
+            )),
+        }
+    }
+    if arg_matches.is_present("emptyzero") {
+        match function_name(arg_matches) {
+            Some("count") | Some("crosstab") | Some("sum") => agg.set_empty_as_zero(true),
+            _ => {
+                return Err(CsvCliError::InvalidConfiguration(
+                    "The --count-empty-as-zero flag only works with the count, crosstab and sum functions"
+                        .to_string(),
+                ))
+            }
+        }
+    }
     Ok(agg)
 }
 
-pub fn run() -> CsvCliResult<()> {
-    match CLI_ARGS.value_of("aggfunc").unwrap() {
-        "count" => run_and_init::<Count<String>, String, usize>(&CLI_ARGS, ParsingStrategy::Text),
-        "countunique" => {
-            run_and_init::<CountUnique<String>, String, usize>(&CLI_ARGS, ParsingStrategy::Text)
-        }
-        "mode" => run_and_init::<Mode<String>, String, String>(&CLI_ARGS, ParsingStrategy::Text),
-        "max" if (CLI_ARGS.is_present("numeric") && CLI_ARGS.is_present("format")) => {
-            Err(CsvCliError::InvalidConfiguration(
+/// Describes each function for the `--list-functions` flag, either as tab-separated lines or as JSON.
+fn list_functions(json: bool) -> String {
+    let strategy_names =
+        |spec: &FunctionSpec| -> Vec<&str> { spec.parsing.iter().map(|s| s.name()).collect() };
+    if json {
+        let functions: Vec<serde_json::Value> = FUNCTIONS
+            .iter()
+            .map(|spec| {
+                serde_json::json!({
+                    "function": spec.name,
+                    "parsing": strategy_names(spec),
+                    "rows_and_columns": spec.rows_and_columns,
+                })
+            })
+            .collect();
+        serde_json::to_string(&functions).unwrap() + "\n"
+    } else {
+        let mut lines = vec!["function\tparsing\trows_and_columns".to_string()];
+        for spec in FUNCTIONS.iter() {
+            lines.push(format!(
+                "{}\t{}\t{}",
+                spec.name,
+                strategy_names(spec).join(","),
+                spec.rows_and_columns
+            ));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Chooses how `spec` parses values. Functions that can only parse values one way ignore -N and -F;
+/// the others parse them as numbers with -N (or --infer finding numbers), as dates with -F
+/// (or --infer finding dates), and as text otherwise, if they can.
+fn parsing_strategy(
+    spec: &FunctionSpec,
+    numeric: bool,
+    date: bool,
+    inferring: bool,
+) -> CsvCliResult<ParsingStrategy> {
+    let strategy = match spec.parsing {
+        [strategy] => return Ok(*strategy),
+        _ if numeric && date => {
+            return Err(CsvCliError::InvalidConfiguration(
                 "You can only enter one of the -N and -F flags/options".to_string(),
             ))
         }
-        "max" if CLI_ARGS.is_present("numeric") => {
-            run_and_init::<Maximum<f64>, f64, f64>(&CLI_ARGS, ParsingStrategy::Numeric)
+        _ if numeric => ParsingStrategy::Numeric,
+        _ if date => ParsingStrategy::Date,
+        _ => ParsingStrategy::Text,
+    };
+    match spec.parsing.contains(&strategy) {
+        true => Ok(strategy),
+        false if inferring => Err(CsvCliError::InvalidConfiguration(format!(
+            "The {} function needs numeric or date data, but --infer found values that \
+             are neither numbers nor dates. If they are dates, set their format with -F",
+            spec.name
+        ))),
+        false => Err(CsvCliError::InvalidConfiguration(format!(
+            "The {} function needs numeric or date data. Use the -N flag to parse values \
+             as numbers or set a date format with -F",
+            spec.name
+        ))),
+    }
+}
+
+/// Finds the order of the rows or columns from three flags: one each for ascending, descending
+/// and index order (in that order, as pairs of argument names and the flags users type).
+/// Returns an error if more than one of the flags is present.
+fn get_output_order(
+    arg_matches: &ArgMatches,
+    flags: [(&str, &str); 3],
+    default: OutputOrder,
+) -> CsvCliResult<OutputOrder> {
+    let present: Vec<usize> = (0..3)
+        .filter(|&i| arg_matches.is_present(flags[i].0))
+        .collect();
+    match present.as_slice() {
+        [] => Ok(default),
+        [0] => Ok(OutputOrder::Ascending),
+        [1] => Ok(OutputOrder::Descending),
+        [2] => Ok(OutputOrder::IndexOrder),
+        _ => Err(CsvCliError::InvalidConfiguration(format!(
+            "You can only enter one of the {}, {} and {} flags",
+            flags[0].1, flags[1].1, flags[2].1
+        ))),
+    }
+}
+
+/// Parses the number of values to sample from each cell (`--k`) and the seed (`--seed`) for sample.
+fn sample_config() -> CsvCliResult<(usize, Option<u64>)> {
+    let k = match CLI_ARGS.value_of("k") {
+        Some(k) => match k.parse::<usize>() {
+            Ok(k) if k > 0 => k,
+            _ => {
+                return Err(CsvCliError::InvalidConfiguration(format!(
+                    "Could not parse `{}` from --k as a positive whole number",
+                    k
+                )))
+            }
+        },
+        None => 1,
+    };
+    let seed = match CLI_ARGS.value_of("seed") {
+        Some(seed) => Some(seed.parse::<u64>().map_err(|_| {
+            CsvCliError::InvalidConfiguration(format!(
+                "Could not parse `{}` from --seed as a non-negative whole number",
+                seed
+            ))
+        })?),
+        None => None,
+    };
+    Ok((k, seed))
+}
+
+/// Compiles the regular expression from --match for `countif`.
+fn countif_pattern() -> CsvCliResult<Regex> {
+    let pattern = CLI_ARGS.value_of("match").ok_or_else(|| {
+        CsvCliError::InvalidConfiguration(
+            "The countif function needs a regular expression to match from --match".to_string(),
+        )
+    })?;
+    Regex::new(pattern).map_err(|err| {
+        CsvCliError::InvalidConfiguration(format!(
+            "Could not parse `{}` from --match as a regular expression: {}",
+            pattern, err
+        ))
+    })
+}
+
+/// Compiles the pattern from --row-replace or --col-replace (named `arg`, typed as `flag`),
+/// returning it along with its replacement.
+fn label_replacement<'a>(
+    arg_matches: &'a ArgMatches,
+    arg: &str,
+    flag: &str,
+) -> CsvCliResult<Option<(Regex, &'a str)>> {
+    let values: Vec<&str> = match arg_matches.values_of(arg) {
+        Some(values) => values.collect(),
+        None => return Ok(None),
+    };
+    let pattern = Regex::new(values[0]).map_err(|err| {
+        CsvCliError::InvalidConfiguration(format!(
+            "Could not parse `{}` from {} as a regular expression: {}",
+            values[0], flag, err
+        ))
+    })?;
+    Ok(Some((pattern, values[1])))
+}
+
+/// Parses the widest cell --pretty displays from --max-width.
+fn max_width(arg_matches: &ArgMatches) -> CsvCliResult<Option<usize>> {
+    match arg_matches.value_of("maxwidth") {
+        Some(width) => match width.parse::<usize>() {
+            Ok(width) if width > 0 => Ok(Some(width)),
+            _ => Err(CsvCliError::InvalidConfiguration(format!(
+                "Could not parse `{}` from --max-width as a positive whole number",
+                width
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Parses the list of functions from --funcs for `agg`.
+fn agg_functions(funcs: &str) -> CsvCliResult<Vec<Function>> {
+    let mut functions = Vec::new();
+    for name in funcs.split(',').map(str::trim) {
+        let function: Function = name.parse().map_err(|err| {
+            CsvCliError::InvalidConfiguration(format!("Could not parse --funcs: {}", err))
+        })?;
+        if functions.contains(&function) {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "The --funcs list has `{}` more than once",
+                name
+            )));
+        }
+        functions.push(function);
+    }
+    Ok(functions)
+}
+
+/// Parses the value from --at for `percentilerank`.
+fn percentile_rank_target() -> CsvCliResult<DecimalWrapper> {
+    let target = CLI_ARGS.value_of("at").ok_or_else(|| {
+        CsvCliError::InvalidConfiguration(
+            "The percentilerank function needs a value to rank from --at".to_string(),
+        )
+    })?;
+    target.parse().map_err(|_| {
+        CsvCliError::InvalidConfiguration(format!(
+            "Could not parse `{}` from --at as a number",
+            target
+        ))
+    })
+}
+
+fn mode_tiebreak() -> Tiebreak {
+    CLI_ARGS
+        .value_of("tiebreak")
+        .and_then(|tiebreak| tiebreak.parse().ok())
+        .unwrap_or(Tiebreak::First)
+}
+
+fn minmax_separator() -> &'static str {
+    CLI_ARGS
+        .value_of("minmaxsep")
+        .unwrap_or(DEFAULT_MINMAX_SEPARATOR)
+}
+
+pub fn run() -> CsvCliResult<()> {
+    if CLI_ARGS.is_present("listfunctions") {
+        write!(
+            io::stdout(),
+            "{}",
+            list_functions(CLI_ARGS.is_present("json"))
+        )?;
+        return Ok(());
+    }
+    if let Some(offset) = CLI_ARGS.value_of("assumetz") {
+        let offset = parsing::parse_offset(offset).map_err(|err| {
+            CsvCliError::InvalidConfiguration(format!("Could not parse --assume-tz: {}", err))
+        })?;
+        parsing::set_assumed_offset(offset);
+    }
+    let aggfunc = function_name(&CLI_ARGS).unwrap();
+    let spec = FUNCTIONS
+        .iter()
+        .find(|spec| spec.name == aggfunc)
+        .ok_or_else(|| {
+            CsvCliError::InvalidConfiguration(format!(
+                "`{}` isn't one of the functions. Use --list-functions to see them",
+                aggfunc
+            ))
+        })?;
+    // with --agg, the first positional argument is a file, so a function name there is a mistake
+    if let Some(name) = CLI_ARGS
+        .value_of("aggfunc")
+        .filter(|name| CLI_ARGS.is_present("agg") && FUNCTION_NAMES.contains(name))
+    {
+        return Err(CsvCliError::InvalidConfiguration(format!(
+            "You chose both the `{}` and `{}` functions. Use either --agg or the function name, not both",
+            name, aggfunc
+        )));
+    }
+    // expressions always have numeric results
+    let numeric_flag = CLI_ARGS.is_present("numeric") || CLI_ARGS.is_present("expr");
+    let inferring = CLI_ARGS.is_present("infer") && !numeric_flag && !CLI_ARGS.is_present("format");
+    // -N and -F take precedence over --infer, which samples the values column
+    // (and so can't choose how first and last parse the --order-by field)
+    let (numeric, date, input) = match aggfunc {
+        "first" | "last" => (numeric_flag, CLI_ARGS.is_present("format"), None),
+        _ if inferring && spec.parsing.len() > 1 => match infer_parsing_strategy(&CLI_ARGS)? {
+            (ParsingStrategy::Numeric, input) => (true, false, input),
+            (ParsingStrategy::Date, input) => (false, true, input),
+            (_, input) => (false, false, input),
+        },
+        _ => (numeric_flag, CLI_ARGS.is_present("format"), None),
+    };
+    if matches!(aggfunc, "first" | "last") && (numeric || date) && !CLI_ARGS.is_present("orderby") {
+        return Err(CsvCliError::InvalidConfiguration(
+            "The first and last functions only parse the --order-by field, so -N and -F need --order-by"
+                .to_string(),
+        ));
+    }
+    let strategy = parsing_strategy(spec, numeric, date, inferring)?;
+    let numeric = strategy == ParsingStrategy::Numeric;
+    let date = strategy == ParsingStrategy::Date;
+    match aggfunc {
+        "count" if CLI_ARGS.is_present("weight") => {
+            run_and_init::<WeightedCount, DecimalWrapper, DecimalWrapper>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Numeric,
+            )
+        }
+        "count" | "crosstab" | "shape" => {
+            run_and_init::<Count<String>, String, usize>(&CLI_ARGS, input, ParsingStrategy::Text)
+        }
+        "cardinality" => run_and_init::<Cardinality<String>, String, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Text,
+        ),
+        "length" => {
+            run_and_init::<LengthStats, String, String>(&CLI_ARGS, input, ParsingStrategy::Text)
+        }
+        "countif" => {
+            let pattern = countif_pattern()?;
+            run_with_init::<CountIf, String, usize, _>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Text,
+                Some(move |item| CountIf::with_pattern(item, pattern.clone())),
+            )
+        }
+        // parsing the values means equal numbers or dates written differently count once
+        "countunique" if numeric => run_and_init::<
+            CountUnique<DecimalWrapper>,
+            DecimalWrapper,
+            usize,
+        >(&CLI_ARGS, input, ParsingStrategy::Numeric),
+        "countunique" if date => run_and_init::<
+            CountUnique<CustomDateObject>,
+            CustomDateObject,
+            usize,
+        >(&CLI_ARGS, input, ParsingStrategy::Date),
+        "countunique" => run_and_init::<CountUnique<String>, String, usize>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Text,
+        ),
+        "histogram" => {
+            run_and_init::<Count<String>, String, usize>(&CLI_ARGS, input, ParsingStrategy::Numeric)
         }
-        "max" if CLI_ARGS.is_present("format") => run_and_init::<
+        "mode" if numeric => {
+            run_with_init::<Mode<DecimalWrapper>, DecimalWrapper, DecimalWrapper, _>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Numeric,
+                Some(|item| Mode::with_tiebreak(item, mode_tiebreak())),
+            )
+        }
+        "mode" if date => {
+            run_with_init::<Mode<CustomDateObject>, CustomDateObject, CustomDateObject, _>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Date,
+                Some(|item| Mode::with_tiebreak(item, mode_tiebreak())),
+            )
+        }
+        "mode" => run_with_init::<Mode<String>, String, String, _>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Text,
+            Some(|item| Mode::with_tiebreak(item, mode_tiebreak())),
+        ),
+        "agg" => run_with_initializers::<AnyAccumulator, DecimalWrapper, AnyOutput, _, _>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Numeric,
+            None::<fn(DecimalWrapper) -> AnyAccumulator>,
+            Some(AnyAccumulator::with_function),
+        ),
+        "percentilerank" => {
+            let target = percentile_rank_target()?;
+            run_with_init::<PercentileRank, DecimalWrapper, DecimalWrapper, _>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Numeric,
+                Some(move |item| PercentileRank::with_target(item, target)),
+            )
+        }
+        "rate" => {
+            let percent = CLI_ARGS.is_present("percent");
+            run_with_init::<Rate, BooleanWrapper, String, _>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Boolean,
+                Some(move |item| Rate::with_percent(item, percent)),
+            )
+        }
+        "sample" => {
+            let (k, seed) = sample_config()?;
+            run_with_init::<Sample, String, String, _>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Text,
+                Some(move |item| Sample::with_config(item, k, seed)),
+            )
+        }
+        "max" if numeric => {
+            run_and_init::<Maximum<f64>, f64, f64>(&CLI_ARGS, input, ParsingStrategy::Numeric)
+        }
+        "max" if date => run_and_init::<
             Maximum<CustomDateObject>,
             CustomDateObject,
             CustomDateObject,
-        >(&CLI_ARGS, ParsingStrategy::Date),
-        "max" => run_and_init::<Maximum<String>, String, String>(&CLI_ARGS, ParsingStrategy::Text),
+        >(&CLI_ARGS, input, ParsingStrategy::Date),
+        "max" => {
+            run_and_init::<Maximum<String>, String, String>(&CLI_ARGS, input, ParsingStrategy::Text)
+        }
+        "first" if !CLI_ARGS.is_present("orderby") => {
+            run_and_init::<First<String>, String, String>(&CLI_ARGS, input, ParsingStrategy::Text)
+        }
+        "first" if numeric => run_and_init::<First<Picked<f64>>, Picked<f64>, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Numeric,
+        ),
+        "first" if date => run_and_init::<
+            First<Picked<CustomDateObject>>,
+            Picked<CustomDateObject>,
+            String,
+        >(&CLI_ARGS, input, ParsingStrategy::Date),
+        "first" => run_and_init::<First<Picked<String>>, Picked<String>, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Text,
+        ),
+        "last" if !CLI_ARGS.is_present("orderby") => {
+            run_and_init::<Last<String>, String, String>(&CLI_ARGS, input, ParsingStrategy::Text)
+        }
+        "last" if numeric => run_and_init::<Last<Picked<f64>>, Picked<f64>, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Numeric,
+        ),
+        "last" if date => run_and_init::<
+            Last<Picked<CustomDateObject>>,
+            Picked<CustomDateObject>,
+            String,
+        >(&CLI_ARGS, input, ParsingStrategy::Date),
+        "last" => run_and_init::<Last<Picked<String>>, Picked<String>, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Text,
+        ),
+        "maxby" if numeric => run_and_init::<MaxBy<f64>, Picked<f64>, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Numeric,
+        ),
+        "maxby" if date => {
+            run_and_init::<MaxBy<CustomDateObject>, Picked<CustomDateObject>, String>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Date,
+            )
+        }
+        "maxby" => run_and_init::<MaxBy<String>, Picked<String>, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Text,
+        ),
+        "minby" if numeric => run_and_init::<MinBy<f64>, Picked<f64>, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Numeric,
+        ),
+        "minby" if date => {
+            run_and_init::<MinBy<CustomDateObject>, Picked<CustomDateObject>, String>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Date,
+            )
+        }
+        "minby" => run_and_init::<MinBy<String>, Picked<String>, String>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Text,
+        ),
         "mean" => run_and_init::<Mean, DecimalWrapper, DecimalWrapper>(
             &CLI_ARGS,
+            input,
             ParsingStrategy::Numeric,
         ),
+        "median" if CLI_ARGS.is_present("fastmedian") => {
+            run_and_init::<ApproxMedian, f64, f64>(&CLI_ARGS, input, ParsingStrategy::Numeric)
+        }
         "median" => run_and_init::<Median, DecimalWrapper, DecimalWrapper>(
             &CLI_ARGS,
+            input,
             ParsingStrategy::Numeric,
         ),
-        "min" if (CLI_ARGS.is_present("numeric") && CLI_ARGS.is_present("format")) => {
-            Err(CsvCliError::InvalidConfiguration(
-                "You can only enter one of the -N and -F flags/options".to_string(),
-            ))
-        }
-        "min" if CLI_ARGS.is_present("numeric") => {
-            run_and_init::<Minimum<f64>, f64, f64>(&CLI_ARGS, ParsingStrategy::Numeric)
+        "min" if numeric => {
+            run_and_init::<Minimum<f64>, f64, f64>(&CLI_ARGS, input, ParsingStrategy::Numeric)
         }
-        "min" if CLI_ARGS.is_present("format") => run_and_init::<
+        "min" if date => run_and_init::<
             Minimum<CustomDateObject>,
             CustomDateObject,
             CustomDateObject,
-        >(&CLI_ARGS, ParsingStrategy::Date),
-        "min" => run_and_init::<Minimum<String>, String, String>(&CLI_ARGS, ParsingStrategy::Text),
-        "minmax" if (CLI_ARGS.is_present("numeric") && CLI_ARGS.is_present("format")) => {
-            Err(CsvCliError::InvalidConfiguration(
-                "You can only enter one of the -N and -F flags/options".to_string(),
-            ))
+        >(&CLI_ARGS, input, ParsingStrategy::Date),
+        "min" => {
+            run_and_init::<Minimum<String>, String, String>(&CLI_ARGS, input, ParsingStrategy::Text)
         }
-        "minmax" if CLI_ARGS.is_present("numeric") => {
-            run_and_init::<MinMax<f64>, f64, String>(&CLI_ARGS, ParsingStrategy::Numeric)
-        }
-        "minmax" if CLI_ARGS.is_present("format") => run_and_init::<
-            MinMax<CustomDateObject>,
-            CustomDateObject,
-            String,
-        >(&CLI_ARGS, ParsingStrategy::Date),
-        "minmax" => {
-            run_and_init::<MinMax<String>, String, String>(&CLI_ARGS, ParsingStrategy::Text)
-        }
-        "range" if CLI_ARGS.is_present("format") => run_and_init::<
-            Range<CustomDateObject, f64>,
-            CustomDateObject,
-            f64,
-        >(&CLI_ARGS, ParsingStrategy::Date),
-        "range" => run_and_init::<Range<DecimalWrapper, Decimal>, DecimalWrapper, Decimal>(
+        "minmax" if numeric => run_with_init::<MinMax<f64>, f64, String, _>(
             &CLI_ARGS,
+            input,
             ParsingStrategy::Numeric,
+            Some(|item| MinMax::with_separator(item, minmax_separator())),
         ),
-        "stddev" => run_and_init::<StdDev, f64, f64>(&CLI_ARGS, ParsingStrategy::Numeric),
+        "minmax" if date => run_with_init::<MinMax<CustomDateObject>, CustomDateObject, String, _>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Date,
+            Some(|item| MinMax::with_separator(item, minmax_separator())),
+        ),
+        "minmax" => run_with_init::<MinMax<String>, String, String, _>(
+            &CLI_ARGS,
+            input,
+            ParsingStrategy::Text,
+            Some(|item| MinMax::with_separator(item, minmax_separator())),
+        ),
+        "range" if date => run_and_init::<
+            Range<CustomDateObject, DateDifference>,
+            CustomDateObject,
+            DateDifference,
+        >(&CLI_ARGS, input, ParsingStrategy::Date),
+        "range" if numeric => {
+            run_and_init::<Range<DecimalWrapper, Decimal>, DecimalWrapper, Decimal>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Numeric,
+            )
+        }
+        "rms" => run_and_init::<Rms, f64, f64>(&CLI_ARGS, input, ParsingStrategy::Numeric),
+        "stddev" => run_and_init::<StdDev, f64, f64>(&CLI_ARGS, input, ParsingStrategy::Numeric),
         "sum" => run_and_init::<Sum<DecimalWrapper>, DecimalWrapper, DecimalWrapper>(
             &CLI_ARGS,
+            input,
             ParsingStrategy::Numeric,
         ),
+        // spread doesn't aggregate, so it keeps the values as they're written unless it has to sum them
+        "spread" | "pivot-longer" | "pivot-wider" => match CLI_ARGS.value_of("onconflict") {
+            Some("sum") => run_and_init::<Sum<DecimalWrapper>, DecimalWrapper, DecimalWrapper>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Numeric,
+            ),
+            Some("last") => run_and_init::<Last<String>, String, String>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Text,
+            ),
+            _ => run_and_init::<First<String>, String, String>(
+                &CLI_ARGS,
+                input,
+                ParsingStrategy::Text,
+            ),
+        },
+        "sumsq" => run_and_init::<SumSquares, f64, f64>(&CLI_ARGS, input, ParsingStrategy::Numeric),
         _ => unreachable!(),
     }
 }
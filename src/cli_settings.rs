@@ -40,9 +40,12 @@
 //! let settings = CsvSettings::parse_new(
 //!          &filename,
 //!         matches.value_of("delimiter"),
-//!         !matches.is_present("noheader")
+//!         !matches.is_present("noheader"),
+//!         None,
 //!     ).expect("Couldn't properly parse the delimiter");
 //! ```
+//! (The last argument is an optional sample of the start of the file, which `CsvSettings`
+//! uses to guess the delimiter if there isn't one and the file isn't a `.tsv` or `.tab` file.)
 //!
 //! From there, you can easily create a `csv::Reader` object:
 //! ```ignore
@@ -96,9 +99,63 @@
 //! returns the first row of your file regardless of whether or not the file has a header row,
 //! you don't need to change a line of code to get it to work.
 use crate::errors::{CsvCliError, CsvCliResult};
+use calamine::{Data, Reader, Xlsx, XlsxError};
+use chrono::format::{Item, StrftimeItems};
+use indexmap::IndexSet;
+use serde_json::Value as JsonValue;
 use std::fs;
 use std::io;
 
+/// The delimiters `sniff_delimiter` chooses between, in order of preference
+const SNIFFED_DELIMITERS: [u8; 4] = [b',', b'\t', b';', b'|'];
+/// The number of lines `sniff_delimiter` looks at
+const SNIFFED_LINES: usize = 10;
+
+/// Guesses the delimiter of a file from the start of it: the delimiter that splits the most
+/// of the first few lines into the same number of fields as the first line. Ties go to the
+/// delimiter that splits the first line into more fields, and then to the delimiter that comes
+/// first in `SNIFFED_DELIMITERS`. Returns `None` if none of them split the first line.
+pub fn sniff_delimiter(sample: &[u8]) -> Option<u8> {
+    // the last line of the sample may be cut off partway through
+    let sample = match sample.iter().rposition(|&byte| byte == b'\n') {
+        Some(end) => &sample[..end],
+        None => sample,
+    };
+    let mut best: Option<(usize, usize, u8)> = None;
+    for &delimiter in SNIFFED_DELIMITERS.iter() {
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(delimiter)
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(sample);
+        let field_counts: Vec<usize> = rdr
+            .records()
+            .take(SNIFFED_LINES)
+            .map_while(Result::ok)
+            .map(|record| record.len())
+            .collect();
+        let fields = match field_counts.first() {
+            Some(&fields) if fields > 1 => fields,
+            _ => continue,
+        };
+        let consistent = field_counts.iter().filter(|&&n| n == fields).count();
+        match best {
+            Some((best_consistent, best_fields, _))
+                if (best_consistent, best_fields) >= (consistent, fields) => {}
+            _ => best = Some((consistent, fields, delimiter)),
+        }
+    }
+    best.map(|(_, _, delimiter)| delimiter)
+}
+
+/// Trims and lowercases the name of a column, replacing each run of whitespace in it with an underscore
+fn clean_header(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("_")
+        .to_lowercase()
+}
+
 /// The core struct of the settings module, providing general settings and utilities for
 /// writing CSV command-line tools.
 #[derive(Debug, PartialEq)]
@@ -107,6 +164,22 @@ pub struct CsvSettings {
     delimiter: u8,
     /// Whether or not the CSV file has a field separator
     has_header: bool,
+    /// Names for the columns of a file without a header row, in order
+    header_names: Option<Vec<String>>,
+    /// Whether to strip leading and trailing whitespace from every field
+    trim: bool,
+    /// The character surrounding fields with delimiters or newlines in them, or `None` if
+    /// fields aren't quoted at all
+    quote: Option<u8>,
+    /// The character escaping quotes inside of quoted fields, or `None` if quotes are
+    /// escaped by doubling them (`""`)
+    escape: Option<u8>,
+    /// Whether numeric field selections start at 1 (like `cut`) instead of 0
+    one_indexed: bool,
+    /// Whether a header row with repeated names is an error, rather than needing `name[n]` selections
+    strict_headers: bool,
+    /// Whether the names of the columns are trimmed, lowercased, and have their spaces replaced with underscores
+    clean_headers: bool,
 }
 
 impl Default for CsvSettings {
@@ -114,6 +187,13 @@ impl Default for CsvSettings {
         CsvSettings {
             delimiter: b',',
             has_header: true,
+            header_names: None,
+            trim: true,
+            quote: Some(b'"'),
+            escape: None,
+            one_indexed: false,
+            strict_headers: false,
+            clean_headers: false,
         }
     }
 }
@@ -121,35 +201,293 @@ impl Default for CsvSettings {
 impl CsvSettings {
     /// Tries to create a new CSVSettings struct. Returns an error if it fails to parse the delimiter.
     /// (If this happens, it is likely because the delimiter **must be a single UTF-8 byte.**)
+    ///
+    /// Without a delimiter, this guesses the delimiter from `sample` (the start of the file),
+    /// if there is one, using `sniff_delimiter`.
     pub fn parse_new(
         fname: &Option<&str>,
         delim: Option<&str>,
         has_header: bool,
+        sample: Option<&[u8]>,
     ) -> CsvCliResult<CsvSettings> {
-        let delimiter = CsvSettings::parse_delimiter(&fname, delim)?;
+        let delimiter = CsvSettings::parse_delimiter(fname, delim, sample)?;
         let settings = CsvSettings {
             delimiter,
             has_header,
+            header_names: None,
+            trim: true,
+            quote: Some(b'"'),
+            escape: None,
+            one_indexed: false,
+            strict_headers: false,
+            clean_headers: false,
         };
         Ok(settings)
     }
 
+    /// Names the columns of a file without a header row, so they can be selected by name.
+    /// The names map to the columns in order.
+    pub fn set_header_names(&mut self, names: Vec<String>) {
+        self.header_names = Some(names);
+    }
+
+    /// Treats numeric field selections as 1-indexed, like `cut` and `awk`, so `1` is the first field.
+    /// (The `name[n]` selections for repeated names stay 0-indexed.)
+    pub fn set_one_indexed(&mut self) {
+        self.one_indexed = true;
+    }
+
+    /// Makes `get_headers` return an error if more than one column has the same name,
+    /// since repeated names are often a sign of a problem earlier in a pipeline.
+    pub fn set_strict_headers(&mut self) {
+        self.strict_headers = true;
+    }
+
+    /// Makes `get_headers` clean up the names of the columns before they're selected or displayed,
+    /// so a column named `  Order Date ` becomes `order_date`: the names are trimmed and lowercased,
+    /// and each run of whitespace in them becomes an underscore.
+    pub fn set_clean_headers(&mut self) {
+        self.clean_headers = true;
+    }
+
+    /// Keeps the leading and trailing whitespace in every field, instead of stripping it.
+    pub fn set_no_trim(&mut self) {
+        self.trim = false;
+    }
+
+    /// Sets the quote character (e.g. `'`), returning an error if it isn't a single byte.
+    pub fn set_quote(&mut self, quote: &str) -> CsvCliResult<()> {
+        self.quote = Some(CsvSettings::parse_single_byte(quote, "quote")?);
+        Ok(())
+    }
+
+    /// Reads quote characters like any other character, instead of using them to quote fields.
+    pub fn set_no_quoting(&mut self) {
+        self.quote = None;
+    }
+
+    /// Sets the character escaping quotes inside of quoted fields (e.g. `\`), returning an error
+    /// if it isn't a single byte. By default, quotes are escaped by doubling them.
+    pub fn set_escape(&mut self, escape: &str) -> CsvCliResult<()> {
+        self.escape = Some(CsvSettings::parse_single_byte(escape, "escape")?);
+        Ok(())
+    }
+
+    /// Returns the names of the columns: the header row, or the names from `set_header_names`
+    /// if the file doesn't have a header row. (Without either, this is the first row of the file.)
+    ///
+    /// Returns an error if the file is empty, if the number of names set with `set_header_names`
+    /// doesn't match the number of columns in the file, or (with `set_strict_headers`) if
+    /// a name appears more than once (after cleaning it up with `set_clean_headers`).
+    pub fn get_headers<R: io::Read>(&self, rdr: &mut csv::Reader<R>) -> CsvCliResult<Vec<String>> {
+        let first_row = rdr.headers()?;
+        if first_row.is_empty() {
+            return Err(CsvCliError::InvalidConfiguration(
+                "The input is empty, so there's no header row or records to aggregate".to_string(),
+            ));
+        }
+        let headers = match &self.header_names {
+            Some(names) if names.len() != first_row.len() => {
+                return Err(CsvCliError::InvalidConfiguration(format!(
+                    "You named {} columns, but the file has {} columns",
+                    names.len(),
+                    first_row.len()
+                )));
+            }
+            Some(names) => names.clone(),
+            None => first_row.iter().map(String::from).collect(),
+        };
+        let headers = match self.clean_headers {
+            true => headers.iter().map(|name| clean_header(name)).collect(),
+            false => headers,
+        };
+        if self.strict_headers {
+            for (i, name) in headers.iter().enumerate() {
+                if let Some(j) = headers[i + 1..].iter().position(|other| other == name) {
+                    return Err(CsvCliError::InvalidConfiguration(format!(
+                        "The header row has more than one column named `{}` (columns {} and {})",
+                        name,
+                        self.display_index(i),
+                        self.display_index(i + 1 + j)
+                    )));
+                }
+            }
+        }
+        Ok(headers)
+    }
+
+    /// Numbers a column the way field selections do, starting at 1 with `set_one_indexed`
+    fn display_index(&self, idx: usize) -> usize {
+        idx + self.one_indexed as usize
+    }
+
     /// Returns a `csv::Reader` object from a filepath, returning an error if the file doesn't exist.
     pub fn get_reader_from_path(&self, filename: &str) -> csv::Result<csv::Reader<fs::File>> {
-        csv::ReaderBuilder::new()
-            .delimiter(self.delimiter)
-            .trim(csv::Trim::All)
-            .has_headers(self.has_header)
-            .from_path(filename)
+        self.reader_builder().from_path(filename)
     }
 
     /// Returns a `csv::Reader` object from standard input.
     pub fn get_reader_from_stdin(&self) -> csv::Reader<io::Stdin> {
-        csv::ReaderBuilder::new()
+        self.get_reader_from_reader(io::stdin())
+    }
+
+    /// Returns a `csv::Reader` object from anything that implements `Read`
+    /// (for instance, a reader that transcodes a file into UTF-8).
+    pub fn get_reader_from_reader<R: io::Read>(&self, rdr: R) -> csv::Reader<R> {
+        self.reader_builder().from_reader(rdr)
+    }
+
+    /// Configures a `csv::ReaderBuilder` with the delimiter, header and quoting settings.
+    fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
             .delimiter(self.delimiter)
-            .trim(csv::Trim::All)
+            .trim(if self.trim {
+                csv::Trim::All
+            } else {
+                csv::Trim::None
+            })
             .has_headers(self.has_header)
-            .from_reader(io::stdin())
+            .quoting(self.quote.is_some())
+            .quote(self.quote.unwrap_or(b'"'))
+            .escape(self.escape)
+            .double_quote(self.escape.is_none());
+        builder
+    }
+
+    /// Returns a `csv::Reader` object over a sheet of an Excel (`.xlsx`) file, returning an error
+    /// if the file or the sheet doesn't exist.
+    ///
+    /// The sheet can be selected by name or by its 0-indexed position, and defaults to the first sheet.
+    /// Excel stores dates as serial numbers, which this returns as they are unless you pass a
+    /// `date_format`, in which case it formats them as strings with that format.
+    pub fn get_reader_from_xlsx(
+        &self,
+        filename: &str,
+        sheet: Option<&str>,
+        date_format: Option<&str>,
+    ) -> CsvCliResult<csv::Reader<io::Cursor<Vec<u8>>>> {
+        // checks the format up front, because formatting a date with a bad one panics
+        if let Some(format) = date_format {
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(CsvCliError::InvalidConfiguration(format!(
+                    "`{}` is not a valid date format",
+                    format
+                )));
+            }
+        }
+        let file = io::BufReader::new(fs::File::open(filename)?);
+        let mut workbook = Xlsx::new(file).map_err(xlsx_to_io_error)?;
+        let sheet = sheet.unwrap_or("0");
+        let range = match sheet.parse::<usize>() {
+            Ok(idx) => workbook.worksheet_range_at(idx),
+            Err(_) => match workbook.sheet_names().iter().any(|name| name == sheet) {
+                true => Some(workbook.worksheet_range(sheet)),
+                false => None,
+            },
+        }
+        .ok_or_else(|| {
+            CsvCliError::InvalidConfiguration(format!(
+                "Could not find the `{}` sheet in {}",
+                sheet, filename
+            ))
+        })?
+        .map_err(xlsx_to_io_error)?;
+        // writes the sheet as a CSV, so the records have the same shape as any other file's
+        let mut wtr = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .from_writer(vec![]);
+        for row in range.rows() {
+            let record = row.iter().map(|cell| match (cell, date_format) {
+                (Data::DateTime(date), Some(format)) => date
+                    .as_datetime()
+                    .map_or_else(|| cell.to_string(), |d| d.format(format).to_string()),
+                _ => cell.to_string(),
+            });
+            wtr.write_record(record)?;
+        }
+        let data = wtr
+            .into_inner()
+            .map_err(|err| io::Error::other(err.to_string()))?;
+        Ok(self.get_reader_from_converted(io::Cursor::new(data)))
+    }
+
+    /// Returns a `csv::Reader` object over JSON Lines input (with one JSON object on each line),
+    /// converted with `jsonl_to_csv`.
+    pub fn get_reader_from_jsonl<R: io::Read>(
+        &self,
+        rdr: R,
+    ) -> CsvCliResult<csv::Reader<io::Cursor<Vec<u8>>>> {
+        let data = self.jsonl_to_csv(rdr)?;
+        Ok(self.get_reader_from_converted(io::Cursor::new(data)))
+    }
+
+    /// Converts JSON Lines input into a CSV, so the records have the same shape as any other file's.
+    /// The header row has every field that appears in any of the objects, in the order they first
+    /// appear, and fields an object doesn't have (or that are `null`) are empty. Nested arrays and
+    /// objects are written as JSON. Blank lines are skipped.
+    ///
+    /// The last object can add a field, so this holds all of the values in memory until the input
+    /// is read, without their field names. Returns a `ParsingError` (with the 1-based line number)
+    /// if a line isn't a JSON object.
+    pub fn jsonl_to_csv<R: io::Read>(&self, rdr: R) -> CsvCliResult<Vec<u8>> {
+        let mut fields = IndexSet::new();
+        // each record has a value for every field seen so far, in the order of `fields`
+        let mut records = Vec::new();
+        for (idx, line) in io::BufRead::lines(io::BufReader::new(rdr)).enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let err = match serde_json::from_str(&line) {
+                Ok(JsonValue::Object(object)) => {
+                    fields.extend(object.keys().cloned());
+                    let record: csv::StringRecord = fields
+                        .iter()
+                        .map(|field| match object.get(field) {
+                            None | Some(JsonValue::Null) => String::new(),
+                            Some(JsonValue::String(s)) => s.clone(),
+                            Some(value) => value.to_string(),
+                        })
+                        .collect();
+                    records.push(record);
+                    continue;
+                }
+                Ok(_) => "Expected a JSON object".to_string(),
+                Err(err) => format!("Failed to parse as JSON: {}", err),
+            };
+            return Err(CsvCliError::ParsingError {
+                line_num: idx + 1,
+                str_to_parse: line,
+                err,
+            });
+        }
+        let mut wtr = csv::WriterBuilder::new()
+            .delimiter(self.delimiter)
+            .from_writer(vec![]);
+        if !fields.is_empty() {
+            wtr.write_record(&fields)?;
+        }
+        for record in &records {
+            // the fields that first appeared after this record are empty
+            let missing = fields.len() - record.len();
+            wtr.write_record(record.iter().chain(std::iter::repeat_n("", missing)))?;
+        }
+        Ok(wtr
+            .into_inner()
+            .map_err(|err| io::Error::other(err.to_string()))?)
+    }
+
+    /// Returns a `csv::Reader` object over a CSV that was converted from another format
+    /// (like an Excel file), which is always written with the default quoting, whatever
+    /// the quoting of other files is.
+    pub fn get_reader_from_converted<R: io::Read>(&self, data: R) -> csv::Reader<R> {
+        self.reader_builder()
+            .quoting(true)
+            .quote(b'"')
+            .escape(None)
+            .double_quote(true)
+            .from_reader(data)
     }
 
     /// Parses the 1-byte value of a delimiter, for parsing as a CSV
@@ -158,9 +496,16 @@ impl CsvSettings {
     /// adding a `-t` or `-d` flag. It will return an error if you try to pass a multi-character
     /// string.
     ///
+    /// For other files, it guesses the delimiter from `sample` with `sniff_delimiter`,
+    /// falling back on a comma.
+    ///
     /// **Note**, though, that what counts as a "character" for this function is really a single
     /// byte, so single characters like 'त' will return errors here.
-    fn parse_delimiter(fname: &Option<&str>, delim: Option<&str>) -> CsvCliResult<u8> {
+    fn parse_delimiter(
+        fname: &Option<&str>,
+        delim: Option<&str>,
+        sample: Option<&[u8]>,
+    ) -> CsvCliResult<u8> {
         // Some(vec![u8]) if the user explicitly states a delimiter, None otherwise
         let explicit_delim = match delim {
             Some(r"\t") => Some(vec![b'\t']),
@@ -171,7 +516,7 @@ impl CsvSettings {
             _ if explicit_delim.is_some() => explicit_delim.unwrap(),
             // altered from https://github.com/BurntSushi/xsv/blob/master/src/config.rs
             Some(fname) if fname.ends_with(".tsv") || fname.ends_with(".tab") => vec![b'\t'],
-            _ => vec![b','],
+            _ => vec![sample.and_then(sniff_delimiter).unwrap_or(b',')],
         };
         if expected_delim.len() != 1 {
             let msg = format!(
@@ -183,25 +528,41 @@ impl CsvSettings {
         Ok(expected_delim[0])
     }
 
+    /// Parses the 1-byte value of a quote or escape character, returning an error if
+    /// the string isn't a single byte.
+    fn parse_single_byte(s: &str, name: &str) -> CsvCliResult<u8> {
+        match s.as_bytes() {
+            [byte] => Ok(*byte),
+            _ => Err(CsvCliError::InvalidConfiguration(format!(
+                "Could not convert `{}` {} character to a single ASCII character",
+                s, name
+            ))),
+        }
+    }
+
     /// Returns a single index where a single string appears. Allows you to validate a single column, rather
     /// than multiple columns.
     pub fn get_field_index(&self, colname: &str, headers: &Vec<&str>) -> CsvCliResult<usize> {
-        let infered_num = match self.get_numeric_index(&colname) {
-            Some(num) if num < headers.len() => Ok(Some(num)),
+        let first = if self.one_indexed { 1 } else { 0 };
+        let infered_num = match self.get_numeric_index(colname) {
+            Some(num) if num >= first && num - first < headers.len() => Ok(Some(num - first)),
             Some(_num) => Err(CsvCliError::InvalidConfiguration(format!(
-                "Could not properly configure. Column selection needs to be between 0 and `{}`",
+                "Could not properly configure. Column selection needs to be between {} and `{}`",
+                first,
                 headers.len()
             ))),
-            None if !self.has_header => Err(CsvCliError::InvalidConfiguration(
-                "Columns must be numeric if you don't have a header".to_string(),
-            )),
+            None if !self.has_header && self.header_names.is_none() => {
+                Err(CsvCliError::InvalidConfiguration(
+                    "Columns must be numeric if you don't have a header".to_string(),
+                ))
+            }
             None => Ok(None),
         }?;
         // TODO There's probably a way to handle this with combinators
         if let Some(num) = infered_num {
             return Ok(num);
         }
-        let str_idx = self.get_string_index(&colname, headers)?;
+        let str_idx = self.get_string_index(colname, headers)?;
         Ok(str_idx)
     }
 
@@ -256,7 +617,7 @@ impl CsvSettings {
         // ignore leading whitespace
         let parsed_str = colname.trim();
         // because of `unwrap` at the end here, we need to check for empty string
-        if parsed_str == "" {
+        if parsed_str.is_empty() {
             return None;
         }
         for char in parsed_str.chars() {
@@ -303,11 +664,11 @@ impl CsvSettings {
         if expected_order.is_empty() {
             expected_order = "0".to_string();
         }
-        let order = expected_order.parse::<usize>().or_else(|_| {
-            Err(CsvCliError::InvalidConfiguration(format!(
+        let order = expected_order.parse::<usize>().map_err(|_| {
+            CsvCliError::InvalidConfiguration(format!(
                 "Could not convert column name `{}`. Hint: consider enclosing the column in quotes",
                 colname
-            )))
+            ))
         })?;
         self.find_index_from_expected(&expected_header, order, headers)
     }
@@ -320,13 +681,21 @@ impl CsvSettings {
     ) -> CsvCliResult<usize> {
         let mut count = 0;
         for (i, field) in headers.iter().enumerate() {
-            if &expected_header == field {
+            // headers keep their whitespace without trimming, so names match either way
+            if &expected_header == field || expected_header == field.trim() {
                 if count == expected_order {
                     return Ok(i);
                 }
                 count += 1;
             }
         }
+        if count > 0 {
+            return Err(CsvCliError::InvalidConfiguration(format!(
+                "`{0}` only appears {1} time(s) in the header row, so there isn't a `{0}[{2}]` \
+                 (the numbers in brackets start at 0)",
+                expected_header, count, expected_order
+            )));
+        }
         Err(CsvCliError::InvalidConfiguration(format!(
             "Could not find `{}` in header row",
             expected_header
@@ -334,6 +703,26 @@ impl CsvSettings {
     }
 }
 
+/// Reads column descriptors from a file, one or more (separated by commas) on each line,
+/// skipping blank lines. Each line can then be passed to `get_field_indexes`.
+pub fn read_field_descriptors(path: &str) -> CsvCliResult<Vec<String>> {
+    let contents = fs::read_to_string(path)?;
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Converts errors from reading an Excel file into IO errors, since they
+/// come from trouble reading the file (rather than from parsing its values)
+fn xlsx_to_io_error(err: XlsxError) -> io::Error {
+    match err {
+        XlsxError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -345,7 +734,7 @@ mod tests {
         #[test]
         fn delimiter_never_panics(s in "\\PC*") {
             let result = panic::catch_unwind(|| {
-                let _settings = CsvSettings::parse_new(&None, Some(&s), true);
+                let _settings = CsvSettings::parse_new(&None, Some(&s), true, None);
             });
             assert!(result.is_ok());
         }
@@ -396,11 +785,42 @@ mod tests {
             let settings = CsvSettings::default();
             assert_eq!(settings.get_string_index(&s, &header).unwrap(), 0);
             let header = vec![s.as_ref(), s.as_ref()];
-            let new_str = format!("{}{}", s, "[1]".to_string());
+            let new_str = format!("{}{}", s, "[1]");
             assert_eq!(settings.get_string_index(&new_str, &header).unwrap(), 1);
         }
     }
 
+    #[test]
+    fn test_sniff_delimiter() {
+        assert_eq!(sniff_delimiter(b"a;b;c\n1;2,5;3\n4;5;6\n"), Some(b';'));
+        assert_eq!(sniff_delimiter(b"a|b\n1|2\n3|4"), Some(b'|'));
+        assert_eq!(sniff_delimiter(b"a\tb\n1\t2\n"), Some(b'\t'));
+        // the semicolons are inside quoted fields, so they don't split anything
+        assert_eq!(
+            sniff_delimiter(b"name,notes\nann,\"a;b;c\"\nbob,\"d;e\"\n"),
+            Some(b',')
+        );
+        assert_eq!(sniff_delimiter(b"values\n1\n2\n"), None);
+        assert_eq!(
+            CsvSettings::parse_new(&Some("data.csv"), None, true, Some(b"a|b\n1|2\n"))
+                .unwrap()
+                .delimiter,
+            b'|'
+        );
+        assert_eq!(
+            CsvSettings::parse_new(&Some("data.tsv"), None, true, Some(b"a|b\n1|2\n"))
+                .unwrap()
+                .delimiter,
+            b'\t'
+        );
+        assert_eq!(
+            CsvSettings::parse_new(&None, Some(","), true, Some(b"a|b\n1|2\n"))
+                .unwrap()
+                .delimiter,
+            b','
+        );
+    }
+
     #[test]
     fn test_split_single_str() {
         let settings = CsvSettings::default();
@@ -486,10 +906,140 @@ mod tests {
 
     #[test]
     fn test_no_header_doesnt_parse() {
-        let no_header_set = CsvSettings::parse_new(&None, None, false).unwrap();
+        let no_header_set = CsvSettings::parse_new(&None, None, false, None).unwrap();
         let header_row = vec!["a", "b"];
         assert!(no_header_set
             .get_field_indexes(&vec!["a"], &header_row)
             .is_err());
     }
+
+    #[test]
+    fn test_one_indexed() {
+        let mut settings = CsvSettings::default();
+        settings.set_one_indexed();
+        let header_row = vec!["a", "b", "a"];
+        assert_eq!(settings.get_field_index("1", &header_row).unwrap(), 0);
+        assert_eq!(settings.get_field_index("3", &header_row).unwrap(), 2);
+        assert!(settings.get_field_index("0", &header_row).is_err());
+        assert!(settings.get_field_index("4", &header_row).is_err());
+        // selecting repeated names is still 0-indexed
+        assert_eq!(settings.get_field_index("a[1]", &header_row).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_header_names() {
+        let mut named_set = CsvSettings::parse_new(&None, None, false, None).unwrap();
+        named_set.set_header_names(vec!["region".to_string(), "amount".to_string()]);
+        let mut rdr = named_set.get_reader_from_reader("west,1\neast,2\n".as_bytes());
+        let headers = named_set.get_headers(&mut rdr).unwrap();
+        assert_eq!(headers, vec!["region", "amount"]);
+        let header_row = headers.iter().map(String::as_str).collect();
+        assert_eq!(named_set.get_field_index("amount", &header_row).unwrap(), 1);
+        named_set.set_header_names(vec!["region".to_string()]);
+        assert!(named_set.get_headers(&mut rdr).is_err());
+    }
+
+    #[test]
+    fn test_jsonl_to_csv() {
+        let settings = CsvSettings::default();
+        let data = "{\"state\": \"OH\", \"amount\": 1.5}\n\n\
+                    {\"state\": \"PA\", \"year\": 2020, \"amount\": null}\n\
+                    {\"tags\": [\"a\", \"b\"], \"ok\": true}\n";
+        let csv = settings.jsonl_to_csv(data.as_bytes()).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "state,amount,year,tags,ok\nOH,1.5,,,\nPA,,2020,,\n,,,\"[\"\"a\"\",\"\"b\"\"]\",true\n"
+        );
+        match settings.jsonl_to_csv("{}\n\n[1]\n".as_bytes()) {
+            Err(CsvCliError::ParsingError { line_num, .. }) => assert_eq!(line_num, 3),
+            _ => panic!("a line that isn't an object should be an error"),
+        }
+        match settings.jsonl_to_csv("{}\n{\"a\": }\n".as_bytes()) {
+            Err(CsvCliError::ParsingError { line_num, err, .. }) => {
+                assert_eq!(line_num, 2);
+                assert!(err.contains("expected value"));
+            }
+            _ => panic!("a line that isn't valid JSON should be an error"),
+        }
+    }
+
+    #[test]
+    fn test_clean_headers() {
+        let mut settings = CsvSettings::default();
+        settings.set_no_trim();
+        settings.set_clean_headers();
+        let data = "  Order Date ,Region\tName,TOTAL\n2020-01-01,west,2\n";
+        let mut rdr = settings.get_reader_from_reader(data.as_bytes());
+        let headers = settings.get_headers(&mut rdr).unwrap();
+        assert_eq!(headers, vec!["order_date", "region_name", "total"]);
+        let headers: Vec<&str> = headers.iter().map(String::as_str).collect();
+        assert_eq!(settings.get_field_index("order_date", &headers).unwrap(), 0);
+        assert!(settings.get_field_index("Order Date", &headers).is_err());
+        // names that only differ in case or spacing are repeats once they're cleaned
+        settings.set_strict_headers();
+        let mut rdr = settings.get_reader_from_reader("Amount,amount \n1,2\n".as_bytes());
+        assert!(settings.get_headers(&mut rdr).is_err());
+    }
+
+    #[test]
+    fn test_strict_headers() {
+        let mut settings = CsvSettings::default();
+        let data = "amount,region,amount\n1,west,2\n";
+        let mut rdr = settings.get_reader_from_reader(data.as_bytes());
+        assert_eq!(
+            settings.get_headers(&mut rdr).unwrap(),
+            vec!["amount", "region", "amount"]
+        );
+        settings.set_strict_headers();
+        settings.set_one_indexed();
+        match settings.get_headers(&mut rdr) {
+            Err(CsvCliError::InvalidConfiguration(msg)) => assert_eq!(
+                msg,
+                "The header row has more than one column named `amount` (columns 1 and 3)"
+            ),
+            _ => panic!("repeated names should be an error"),
+        }
+    }
+
+    #[test]
+    fn test_quoting() {
+        let read_all = |settings: &CsvSettings, data: &'static str| -> Vec<Vec<String>> {
+            let mut rdr = settings.get_reader_from_reader(data.as_bytes());
+            rdr.records()
+                .map(|r| r.unwrap().iter().map(String::from).collect())
+                .collect()
+        };
+        let mut single_quoted = CsvSettings::default();
+        single_quoted.set_quote("'").unwrap();
+        assert_eq!(
+            read_all(&single_quoted, "name,city\n'Lee, Max',\"Denver\"\n"),
+            vec![vec!["Lee, Max", "\"Denver\""]]
+        );
+        let mut unquoted = CsvSettings::default();
+        unquoted.set_no_quoting();
+        assert_eq!(
+            read_all(&unquoted, "name,city\n\"Max\",Denver\n"),
+            vec![vec!["\"Max\"", "Denver"]]
+        );
+        let mut escaped = CsvSettings::default();
+        escaped.set_escape("\\").unwrap();
+        assert_eq!(
+            read_all(&escaped, "name,quote\nMax,\"He said \\\"hi\\\"\"\n"),
+            vec![vec!["Max", "He said \"hi\""]]
+        );
+        assert!(CsvSettings::default().set_quote("''").is_err());
+    }
+
+    #[test]
+    fn test_no_trim() {
+        let mut untrimmed = CsvSettings::default();
+        untrimmed.set_no_trim();
+        let mut rdr = untrimmed.get_reader_from_reader(" name ,city\nMax , \n".as_bytes());
+        let headers = untrimmed.get_headers(&mut rdr).unwrap();
+        let header_row = headers.iter().map(String::as_str).collect();
+        assert_eq!(untrimmed.get_field_index("name", &header_row).unwrap(), 0);
+        assert_eq!(untrimmed.get_field_index(" 1", &header_row).unwrap(), 1);
+        let record = rdr.records().next().unwrap().unwrap();
+        assert_eq!(record.iter().collect::<Vec<&str>>(), vec!["Max ", " "]);
+    }
 }
@@ -6,7 +6,7 @@
 //! > in Rust. If you're hoping to implement you're own library or binary in Rust,
 //! > I highly recommend looking at both (and, especiialy, the guide).
 //!
-//! You can characterize all four error types in two general categories:
+//! You can characterize all of the error types in two general categories:
 //! errors configuring the CSV reader and errors parsing individual lines.
 //! For errors relating to configuration, my goal is simply to be as specific
 //! and clear as possible about the nature of a given error. For errors relating to
@@ -38,8 +38,9 @@ pub type CsvCliResult<T> = result::Result<T, CsvCliError>;
 pub enum CsvCliError {
     /// Errors from reading a CSV file.
     ///
-    /// This should be limited to inconsistencies in the number of lines appearing in a given row
-    /// or errors parsing data as UTF-8.
+    /// This should be limited to errors parsing data as UTF-8 (and IO errors while reading).
+    /// Records with the wrong number of fields are converted into `UnequalLengths`, which says
+    /// which line they're on.
     CsvError(csv::Error),
     /// Errors in the initial configuration from command-line arguments.
     ///
@@ -48,6 +49,18 @@ pub enum CsvCliError {
     InvalidConfiguration(String),
     /// A standard IO error. Typically from trying to read a file that does not exist
     Io(io::Error),
+    /// A record with a different number of fields than the first row (usually the header).
+    ///
+    /// Unlike the other errors about records, this refers to the line of the file the record
+    /// starts on (counting from 1), since the record's fields can't be trusted to find it.
+    UnequalLengths {
+        /// The line the record starts on
+        line: u64,
+        /// The number of fields in the first row
+        expected_len: u64,
+        /// The number of fields in the record
+        len: u64,
+    },
     /// Errors trying to parse a new value.
 
     /// The way in which `clipivot` parses values depends on the aggregation function
@@ -69,11 +82,28 @@ pub enum CsvCliError {
 impl fmt::Display for CsvCliError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            CsvCliError::CsvError(ref err) => err.fmt(f),
+            CsvCliError::CsvError(ref err) => match err.kind() {
+                csv::ErrorKind::Utf8 { .. } => write!(
+                    f,
+                    "{}. If your file isn't encoded as UTF-8, try setting its encoding \
+                     with the --encoding option",
+                    err
+                ),
+                _ => err.fmt(f),
+            },
             CsvCliError::InvalidConfiguration(ref err) => {
                 write!(f, "Could not properly configure the aggregator: {}", err)
             }
             CsvCliError::Io(ref err) => err.fmt(f),
+            CsvCliError::UnequalLengths {
+                ref line,
+                ref expected_len,
+                ref len,
+            } => write!(
+                f,
+                "Line {} has {} field(s), but the first line has {}",
+                line, len, expected_len
+            ),
             // adapted from https://github.com/BurntSushi/rust-csv/blob/master/src/error.rs
             CsvCliError::ParsingError {
                 ref line_num,
@@ -88,6 +118,23 @@ impl fmt::Display for CsvCliError {
     }
 }
 
+impl CsvCliError {
+    /// Whether the error came from writing to a pipe that was closed, like when the output
+    /// is piped into `head`. That means the reader has everything it wants, so it isn't
+    /// really an error.
+    pub fn is_broken_pipe(&self) -> bool {
+        let io_err = match self {
+            CsvCliError::Io(err) => err,
+            CsvCliError::CsvError(err) => match err.kind() {
+                csv::ErrorKind::Io(err) => err,
+                _ => return false,
+            },
+            _ => return false,
+        };
+        io_err.kind() == io::ErrorKind::BrokenPipe
+    }
+}
+
 impl From<io::Error> for CsvCliError {
     fn from(err: io::Error) -> CsvCliError {
         CsvCliError::Io(err)
@@ -96,6 +143,17 @@ impl From<io::Error> for CsvCliError {
 
 impl From<csv::Error> for CsvCliError {
     fn from(err: csv::Error) -> CsvCliError {
-        CsvCliError::CsvError(err)
+        match *err.kind() {
+            csv::ErrorKind::UnequalLengths {
+                ref pos,
+                expected_len,
+                len,
+            } => CsvCliError::UnequalLengths {
+                line: pos.as_ref().map_or(0, |pos| pos.line()),
+                expected_len,
+                len,
+            },
+            _ => CsvCliError::CsvError(err),
+        }
     }
 }
@@ -0,0 +1,387 @@
+//! A small evaluator for arithmetic on the fields of a record, used by the `--expr` option
+//! to aggregate computed values (like `qty * price`) instead of the values in a single column.
+//!
+//! Expressions can use `+`, `-`, `*` and `/`, parentheses, numbers, and fields. `*` and `/` come
+//! before `+` and `-`, and otherwise expressions are evaluated from left to right, so `a - b - c`
+//! means `(a - b) - c`. Fields are either bare names made of letters, digits and underscores
+//! that don't start with a digit (like `unit_price`) or any field description you could pass
+//! to `-v` in double quotes (like `"unit price"`, `"amount[1]"` or `"3"`).
+//!
+//! All of the arithmetic uses `Decimal`, like `sum` and `mean`, so it's exact.
+use crate::aggregation::is_null_value;
+use crate::errors::{CsvCliError, CsvCliResult};
+use crate::parsing::DecimalWrapper;
+use rust_decimal::Decimal;
+use std::fmt;
+
+/// A parsed expression, along with the text it was parsed from.
+#[derive(Debug)]
+pub struct Expr {
+    source: String,
+    root: Node,
+    /// Whether the fields have decimal commas, like `1.234,56`
+    decimal_comma: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Number(Decimal),
+    /// The index of a field, along with the way it was written in the expression
+    Field(usize, String),
+    Negate(Box<Node>),
+    Binary(Box<Node>, Operator, Box<Node>),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Number(Decimal),
+    Field(String),
+    Operator(Operator),
+    LeftParen,
+    RightParen,
+}
+
+/// The reasons an expression can't be evaluated for a record.
+#[derive(Debug, PartialEq)]
+pub enum EvalError {
+    /// One of the fields is empty (or a null value like `NA`), so the record doesn't have a value.
+    Empty,
+    /// One of the fields (written the way it was in the expression) couldn't be parsed as a number.
+    NotANumber {
+        field: String,
+        value: String,
+    },
+    DivisionByZero,
+    /// The result is too large to be a `Decimal`
+    Overflow,
+}
+
+impl Expr {
+    /// Parses an expression, finding the index of each field with `find_field`.
+    ///
+    /// Returns an `InvalidConfiguration` error if the expression isn't valid
+    /// or if `find_field` can't find one of its fields.
+    pub fn parse<F>(source: &str, find_field: F) -> CsvCliResult<Expr>
+    where
+        F: Fn(&str) -> CsvCliResult<usize>,
+    {
+        let invalid = |msg: String| {
+            CsvCliError::InvalidConfiguration(format!(
+                "Could not parse the --expr expression `{}`: {}",
+                source, msg
+            ))
+        };
+        let tokens = tokenize(source).map_err(invalid)?;
+        let mut parser = Parser {
+            source,
+            tokens,
+            pos: 0,
+            find_field: &find_field,
+        };
+        let root = parser.parse_sum()?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(invalid(format!("unexpected {}", describe_token(token))));
+        }
+        Ok(Expr {
+            source: source.to_string(),
+            root,
+            decimal_comma: false,
+        })
+    }
+
+    /// Parses the fields as numbers with a decimal comma, like `1.234,56`. Numbers in the expression
+    /// itself still use a decimal point.
+    pub fn set_decimal_comma(&mut self) {
+        self.decimal_comma = true;
+    }
+
+    /// Evaluates the expression with the values of a record.
+    pub fn eval(&self, record: &csv::StringRecord) -> Result<Decimal, EvalError> {
+        eval_node(&self.root, record, self.decimal_comma)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+fn eval_node(
+    node: &Node,
+    record: &csv::StringRecord,
+    decimal_comma: bool,
+) -> Result<Decimal, EvalError> {
+    match node {
+        Node::Number(num) => Ok(*num),
+        Node::Field(idx, name) => {
+            let value = record.get(*idx).unwrap_or("");
+            if is_null_value(value.trim()) {
+                return Err(EvalError::Empty);
+            }
+            let parsed = match decimal_comma {
+                true => DecimalWrapper::parse_decimal_comma(value),
+                false => value.parse::<DecimalWrapper>(),
+            };
+            parsed
+                .map(|num| num.item)
+                .map_err(|_| EvalError::NotANumber {
+                    field: name.to_string(),
+                    value: value.to_string(),
+                })
+        }
+        Node::Negate(inner) => Ok(-eval_node(inner, record, decimal_comma)?),
+        Node::Binary(left, op, right) => {
+            let left = eval_node(left, record, decimal_comma)?;
+            let right = eval_node(right, record, decimal_comma)?;
+            match op {
+                Operator::Add => left.checked_add(right).ok_or(EvalError::Overflow),
+                Operator::Subtract => left.checked_sub(right).ok_or(EvalError::Overflow),
+                Operator::Multiply => left.checked_mul(right).ok_or(EvalError::Overflow),
+                Operator::Divide if right == Decimal::new(0, 0) => Err(EvalError::DivisionByZero),
+                Operator::Divide => left.checked_div(right).ok_or(EvalError::Overflow),
+            }
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '+' | '-' | '*' | '/' => {
+                chars.next();
+                tokens.push(Token::Operator(match c {
+                    '+' => Operator::Add,
+                    '-' => Operator::Subtract,
+                    '*' => Operator::Multiply,
+                    _ => Operator::Divide,
+                }));
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LeftParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RightParen);
+            }
+            '"' => {
+                chars.next();
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => name.push(c),
+                        None => {
+                            return Err(format!("the quote at position {} isn't closed", start))
+                        }
+                    }
+                }
+                tokens.push(Token::Field(name));
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut number = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_ascii_digit() || c == '.') {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                let num = number
+                    .parse::<DecimalWrapper>()
+                    .map_err(|_| format!("`{}` isn't a number", number))?;
+                tokens.push(Token::Number(num.item));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&(_, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_') {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Field(name));
+            }
+            c => return Err(format!("unexpected `{}` at position {}", c, start)),
+        }
+    }
+    Ok(tokens)
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Number(num) => format!("number `{}`", num),
+        Token::Field(name) => format!("field `{}`", name),
+        Token::Operator(op) => {
+            let symbol = match op {
+                Operator::Add => '+',
+                Operator::Subtract => '-',
+                Operator::Multiply => '*',
+                Operator::Divide => '/',
+            };
+            format!("`{}`", symbol)
+        }
+        Token::LeftParen => "`(`".to_string(),
+        Token::RightParen => "`)`".to_string(),
+    }
+}
+
+/// A recursive descent parser over the tokens of an expression
+struct Parser<'a, F> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+    find_field: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> CsvCliResult<usize>,
+{
+    fn error(&self, msg: &str) -> CsvCliError {
+        let found = match self.tokens.get(self.pos) {
+            Some(token) => describe_token(token),
+            None => "the end of the expression".to_string(),
+        };
+        CsvCliError::InvalidConfiguration(format!(
+            "Could not parse the --expr expression `{}`: expected {}, but found {}",
+            self.source, msg, found
+        ))
+    }
+
+    /// Parses terms separated by `+` and `-`
+    fn parse_sum(&mut self) -> CsvCliResult<Node> {
+        let mut node = self.parse_product()?;
+        while let Some(Token::Operator(op @ (Operator::Add | Operator::Subtract))) =
+            self.tokens.get(self.pos)
+        {
+            let op = *op;
+            self.pos += 1;
+            node = Node::Binary(Box::new(node), op, Box::new(self.parse_product()?));
+        }
+        Ok(node)
+    }
+
+    /// Parses factors separated by `*` and `/`
+    fn parse_product(&mut self) -> CsvCliResult<Node> {
+        let mut node = self.parse_factor()?;
+        while let Some(Token::Operator(op @ (Operator::Multiply | Operator::Divide))) =
+            self.tokens.get(self.pos)
+        {
+            let op = *op;
+            self.pos += 1;
+            node = Node::Binary(Box::new(node), op, Box::new(self.parse_factor()?));
+        }
+        Ok(node)
+    }
+
+    /// Parses a number, a field, a negated factor, or an expression in parentheses
+    fn parse_factor(&mut self) -> CsvCliResult<Node> {
+        let node = match self.tokens.get(self.pos) {
+            Some(Token::Number(num)) => Node::Number(*num),
+            Some(Token::Field(name)) => Node::Field((self.find_field)(name)?, name.to_string()),
+            Some(Token::Operator(Operator::Subtract)) => {
+                self.pos += 1;
+                return Ok(Node::Negate(Box::new(self.parse_factor()?)));
+            }
+            Some(Token::LeftParen) => {
+                self.pos += 1;
+                let node = self.parse_sum()?;
+                if self.tokens.get(self.pos) != Some(&Token::RightParen) {
+                    return Err(self.error("`)`"));
+                }
+                node
+            }
+            _ => return Err(self.error("a number, a field or `(`")),
+        };
+        self.pos += 1;
+        Ok(node)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use csv::StringRecord;
+
+    fn find_field(name: &str) -> CsvCliResult<usize> {
+        match name {
+            "qty" => Ok(0),
+            "price" => Ok(1),
+            "unit price" => Ok(2),
+            _ => Err(CsvCliError::InvalidConfiguration(format!(
+                "Could not find `{}`",
+                name
+            ))),
+        }
+    }
+
+    fn eval(source: &str, record: Vec<&str>) -> Result<String, EvalError> {
+        let expr = Expr::parse(source, find_field).unwrap();
+        expr.eval(&StringRecord::from(record))
+            .map(|num| num.to_string())
+    }
+
+    #[test]
+    fn test_precedence_and_parens() {
+        let record = vec!["3", "2.5", "4"];
+        assert_eq!(eval("qty * price", record.clone()), Ok("7.5".to_string()));
+        assert_eq!(
+            eval("qty + price * 2", record.clone()),
+            Ok("8.0".to_string())
+        );
+        assert_eq!(
+            eval("(qty + price) * 2", record.clone()),
+            Ok("11.0".to_string())
+        );
+        assert_eq!(
+            eval("qty - price - 1", record.clone()),
+            Ok("-0.5".to_string())
+        );
+        assert_eq!(
+            eval("-qty / \"unit price\"", record),
+            Ok("-0.75".to_string())
+        );
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        assert_eq!(
+            eval("qty * price", vec!["3", "", "4"]),
+            Err(EvalError::Empty)
+        );
+        assert_eq!(
+            eval("qty * price", vec!["3", "abc", "4"]),
+            Err(EvalError::NotANumber {
+                field: "price".to_string(),
+                value: "abc".to_string()
+            })
+        );
+        assert_eq!(
+            eval("qty / price", vec!["3", "0", "4"]),
+            Err(EvalError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for source in &["qty *", "qty price", "(qty", "qty $ price", "cost", "\"qty"] {
+            assert!(Expr::parse(source, find_field).is_err());
+        }
+    }
+}
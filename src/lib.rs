@@ -14,22 +14,22 @@
 //!
 //! ## Requires programming experience
 //! - Performance: I've tried to design `clipivot` to be reasonably performant, but I'm sure there
-//! are places where performance could be optimized. If you have any suggestions, I'd love to hear them.
-//! (Note: I'm aware that there are technically faster algorithms for computing median than the one I
-//! wound up with, the [`BTreeMap`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html)
-//! in Rust's standard library. The reason I chose the `BTreeMap` is that it is well-suited for
-//! adding items from a stream and it is more memory efficient than other algorithms I'm aware of.
-//! But let me know if you're aware of a way to improve the speed of the median computation
-//! while maintaining the best case memory efficiency of `BTreeMap`.)
+//!   are places where performance could be optimized. If you have any suggestions, I'd love to hear them.
+//!   (Note: I'm aware that there are technically faster algorithms for computing median than the one I
+//!   wound up with, the [`BTreeMap`](https://doc.rust-lang.org/std/collections/struct.BTreeMap.html)
+//!   in Rust's standard library. The reason I chose the `BTreeMap` is that it is well-suited for
+//!   adding items from a stream and it is more memory efficient than other algorithms I'm aware of.
+//!   But let me know if you're aware of a way to improve the speed of the median computation
+//!   while maintaining the best case memory efficiency of `BTreeMap`.)
 //! - Coding style: This is my first project in Rust, so I'm sure there are parts of the code
-//! that are not idiomatic in Rust or that are poorly structured.
+//!   that are not idiomatic in Rust or that are poorly structured.
 //! - Testing: I think I've included fairly decent testing for this tool, but I'm sure there are places
-//! where my testing can improve.
+//!   where my testing can improve.
 //!
 //! ## Doesn't require programming experience
 //! - Bugs: If something in this program doesn't work like you think it's supposed to, please let me know.
 //! - Error handling: I've tried to make error handling as clear and helpful as possible, so if an error
-//!  message you get from `clipivot` confuses you, let me know and I'll do what I can to fix it.
+//!   message you get from `clipivot` confuses you, let me know and I'll do what I can to fix it.
 //!
 //! In particular, pretty much nothing you run should ever result in what Rust calls a "panic" — basically an unanticipated,
 //! fast exit from a program. Panics look something like:
@@ -40,10 +40,10 @@
 //! ```
 //!
 //! - Documentation: You shouldn't be confused about how to get `clipivot` to work. If you've read the guide
-//! on GitHub and the help message and are confused by part of it, please let me know.
+//!   on GitHub and the help message and are confused by part of it, please let me know.
 //!
 //! - Features: I don't have any new features in mind for `clipivot`, but if you do,
-//! let me know and I'll consider whether or not I think it makes sense to add the feature.
+//!   let me know and I'll consider whether or not I think it makes sense to add the feature.
 //!
 //! # Development Environment
 //! In order to contribute code, first clone the repository to install the source code:
@@ -92,4 +92,5 @@ pub mod aggregation;
 pub mod cli;
 pub mod cli_settings;
 pub mod errors;
+pub mod expr;
 pub mod parsing;
@@ -1,14 +1,42 @@
 use std::process;
 
 use clipivot::cli;
+use clipivot::errors::CsvCliError;
 use clipivot::parsing;
+
+/// Exit codes for each kind of error, so scripts can tell them apart
+const CONFIG_ERROR: i32 = 2;
+const IO_ERROR: i32 = 3;
+const PARSE_ERROR: i32 = 4;
+
 fn main() {
     if let Some(date_format) = cli::CLI_ARGS.value_of("format") {
         parsing::set_date_format(date_format.to_string());
     }
+    // --date-range-unit is a numeric --duration-format, so they can't both be set
+    let duration_format = cli::CLI_ARGS
+        .value_of("durationformat")
+        .or_else(|| cli::CLI_ARGS.value_of("daterangeunit"));
+    if let Some(duration_format) = duration_format {
+        if let Ok(format) = duration_format.parse() {
+            parsing::set_duration_format(format);
+        }
+    }
 
     if let Err(err) = cli::run() {
+        // the output was piped into something like `head`, which stopped reading early
+        if err.is_broken_pipe() {
+            return;
+        }
         eprintln!("{}", err);
-        process::exit(1);
+        let exit_code = match err {
+            CsvCliError::InvalidConfiguration(_) => CONFIG_ERROR,
+            CsvCliError::Io(_) => IO_ERROR,
+            CsvCliError::CsvError(ref csv_err) if csv_err.is_io_error() => IO_ERROR,
+            CsvCliError::CsvError(_)
+            | CsvCliError::ParsingError { .. }
+            | CsvCliError::UnequalLengths { .. } => PARSE_ERROR,
+        };
+        process::exit(exit_code);
     }
 }
@@ -5,30 +5,228 @@
 //! in the case of `DecimalWrapper`.
 //!
 //! This is necessary because `chrono` doesn't use `FromStr` (because it doesn't know the format it needs to parse)
-//! and because I wanted to return the number of days between datetimes for range (overwriting `std::ops::Sub`).
+//! and because I wanted to return the difference between datetimes for range (overwriting `std::ops::Sub`
+//! to return a `DateDifference`, which displays as a number of days by default).
 //! And decimal has a way of parsing values in scientific notation and parsing normal numbers. So I added
 //! the scientific notation parsing to the implementation of `FromStr`.
-use chrono::{NaiveDate, NaiveDateTime};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime};
 use lazy_static::lazy_static;
 use rust_decimal::Decimal;
+use std::borrow::Cow;
 use std::fmt;
 use std::sync::Mutex;
 
 lazy_static! {
     static ref OUTPUT_DATE_FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
-    pub static ref INPUT_DATE_FORMAT: Mutex<String> = Mutex::new("%Y-%m-%d %H:%M:%S".to_string());
+    pub static ref INPUT_DATE_FORMAT: Mutex<Vec<String>> =
+        Mutex::new(vec!["%Y-%m-%d %H:%M:%S".to_string()]);
+    pub static ref OUTPUT_DURATION_FORMAT: Mutex<DurationFormat> = Mutex::new(DurationFormat::Days);
+    pub static ref ASSUMED_OFFSET: Mutex<Option<FixedOffset>> = Mutex::new(None);
 }
 
 /// Sets `INPUT_DATE_FORMAT` so that date parsing can work with `std::str::FromStr
 ///
+/// You can set several formats by separating them with semicolons (e.g. `%Y-%m-%d;%m/%d/%Y`),
+/// in which case dates are parsed with the first format that matches.
+///
 /// Keep in mind that as this sets a mutable global variable, any changes to this
 /// function could affect other code you write.
 pub fn set_date_format(s: String) {
-    *INPUT_DATE_FORMAT.lock().unwrap() = s;
+    *INPUT_DATE_FORMAT.lock().unwrap() = s.split(';').map(String::from).collect();
+}
+
+/// Lists the formats in `INPUT_DATE_FORMAT` for error messages, like "the `%Y-%m-%d` format"
+/// or "any of the `%Y-%m-%d`, `%m/%d/%Y` formats".
+pub fn describe_date_formats() -> String {
+    describe_formats(&INPUT_DATE_FORMAT.lock().unwrap())
+}
+
+fn describe_formats(formats: &[String]) -> String {
+    match formats.len() {
+        1 => format!("the `{}` format", formats[0]),
+        _ => format!("any of the `{}` formats", formats.join("`, `")),
+    }
+}
+
+/// The formats (and the assumed UTC offset) for parsing dates with `CustomDateObject::parse_with_format`.
+///
+/// This does the same thing as `set_date_format` and `set_assumed_offset` without setting
+/// any global variables, so code that parses dates in different formats (like two `Aggregator`s
+/// on different threads) doesn't need to share them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DateFormat {
+    formats: Vec<String>,
+    assumed_offset: Option<FixedOffset>,
+}
+
+impl DateFormat {
+    /// Creates a date format from one or more formats separated by semicolons, like `set_date_format`
+    pub fn new(formats: &str) -> DateFormat {
+        DateFormat {
+            formats: formats.split(';').map(String::from).collect(),
+            assumed_offset: None,
+        }
+    }
+
+    /// Converts dates without a UTC offset to UTC, like `set_assumed_offset`
+    pub fn with_assumed_offset(mut self, offset: FixedOffset) -> DateFormat {
+        self.assumed_offset = Some(offset);
+        self
+    }
+
+    pub fn formats(&self) -> &[String] {
+        &self.formats
+    }
+
+    /// Describes the formats for error messages, like `describe_date_formats`
+    pub fn describe(&self) -> String {
+        describe_formats(&self.formats)
+    }
+}
+
+/// Sets `ASSUMED_OFFSET`, the UTC offset of dates that don't have one (like `2023-05-01 12:00:00`),
+/// so they can be converted to UTC and compared with dates that do.
+///
+/// Like `set_date_format`, this sets a mutable global variable.
+pub fn set_assumed_offset(offset: FixedOffset) {
+    *ASSUMED_OFFSET.lock().unwrap() = Some(offset);
+}
+
+/// Parses a UTC offset like `+02:00`, `-0500`, `+05` or `UTC`.
+pub fn parse_offset(s: &str) -> Result<FixedOffset, String> {
+    let invalid = || format!("`{}` is not a UTC offset like +02:00 or -0500", s);
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return Ok(FixedOffset::east_opt(0).unwrap());
+    }
+    let sign = match s.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _ => return Err(invalid()),
+    };
+    let digits = s[1..].replacen(':', "", 1);
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let (hours, minutes) = match digits.len() {
+        2 => (&digits[..], "0"),
+        4 => (&digits[..2], &digits[2..]),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes): (i32, i32) = (hours.parse().unwrap(), minutes.parse().unwrap());
+    if minutes >= 60 {
+        return Err(invalid());
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60)).ok_or_else(invalid)
+}
+
+/// Sets `OUTPUT_DURATION_FORMAT`, which determines how `DateDifference` is displayed.
+///
+/// Like `set_date_format`, this sets a mutable global variable.
+pub fn set_duration_format(format: DurationFormat) {
+    *OUTPUT_DURATION_FORMAT.lock().unwrap() = format;
+}
+
+/// The ways of displaying the difference between two dates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DurationFormat {
+    /// The total number of days, including fractional days (e.g. `731.25`)
+    Days,
+    /// The number of days, hours, minutes and seconds (e.g. `731 days 6 hours`)
+    Human,
+    /// The number of calendar years, months and days (e.g. `2y 0m 1d`)
+    YearMonthDay,
+    /// The total number of hours, including fractional hours (e.g. `17550.5`)
+    Hours,
+    /// The total number of seconds (e.g. `63180000`)
+    Seconds,
+}
+
+impl std::str::FromStr for DurationFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "days" => Ok(DurationFormat::Days),
+            "human" => Ok(DurationFormat::Human),
+            "ymd" => Ok(DurationFormat::YearMonthDay),
+            "hours" => Ok(DurationFormat::Hours),
+            "seconds" => Ok(DurationFormat::Seconds),
+            _ => Err(format!("`{}` is not a valid duration format", s)),
+        }
+    }
+}
+
+/// The periods of time that dates can be grouped (or truncated) into.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeBucket {
+    /// Labeled like `2020`
+    Year,
+    /// Labeled like `2020-03`
+    Month,
+    /// Weeks start on Monday and are labeled by the date of that Monday (like `2020-03-09`)
+    Week,
+    /// Labeled like `2020-03-11`
+    Day,
+    /// Labeled like `2020-03-11 14:00`
+    Hour,
+}
+
+impl std::str::FromStr for TimeBucket {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "year" => Ok(TimeBucket::Year),
+            "month" => Ok(TimeBucket::Month),
+            "week" => Ok(TimeBucket::Week),
+            "day" => Ok(TimeBucket::Day),
+            "hour" => Ok(TimeBucket::Hour),
+            _ => Err(format!("`{}` is not a valid time bucket", s)),
+        }
+    }
+}
+
+impl TimeBucket {
+    /// Returns the label of the bucket a date falls into. The labels sort in chronological order.
+    pub fn label(self, date: &CustomDateObject) -> String {
+        let date = date.0;
+        match self {
+            TimeBucket::Year => date.format("%Y").to_string(),
+            TimeBucket::Month => date.format("%Y-%m").to_string(),
+            TimeBucket::Week => {
+                let days_since_monday = date.weekday().num_days_from_monday() as i64;
+                (date.date() - chrono::Duration::days(days_since_monday))
+                    .format("%Y-%m-%d")
+                    .to_string()
+            }
+            TimeBucket::Day => date.format("%Y-%m-%d").to_string(),
+            TimeBucket::Hour => date.format("%Y-%m-%d %H:00").to_string(),
+        }
+    }
+}
+
+/// A true or false value, for `rate`. Parses `true`/`false`, `t`/`f`, `yes`/`no`, `y`/`n`
+/// and `1`/`0`, ignoring case.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BooleanWrapper(pub bool);
+
+impl std::str::FromStr for BooleanWrapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "true" | "t" | "yes" | "y" | "1" => Ok(BooleanWrapper(true)),
+            "false" | "f" | "no" | "n" | "0" => Ok(BooleanWrapper(false)),
+            _ => Err(format!("`{}` is not a boolean", s)),
+        }
+    }
 }
 
 /// A light wrapper over `rust_decimal::Decimal`.
-#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+///
+/// Equal numbers with different numbers of decimal places (like `1.0` and `1.00`) are equal
+/// and have the same hash, so they count as one value in `countunique`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct DecimalWrapper {
     pub item: Decimal,
 }
@@ -37,15 +235,70 @@ impl std::str::FromStr for DecimalWrapper {
     type Err = rust_decimal::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the reader trims whitespace around fields, but not with --no-trim
+        let s = s.trim();
         Decimal::from_str(s)
             .or_else(|_| Decimal::from_scientific(s))
             .map(|v| DecimalWrapper { item: v })
     }
 }
 
+impl DecimalWrapper {
+    /// Parses a number with a decimal comma, like `1.234,56`, the way `FromStr` parses `1234.56`
+    pub fn parse_decimal_comma(s: &str) -> Result<DecimalWrapper, rust_decimal::Error> {
+        from_decimal_comma(s).parse()
+    }
+}
+
+/// Rewrites a number with a decimal comma (and periods between groups of thousands), like `1.234,56`,
+/// with a decimal point, like `1234.56`, so it can be parsed as a `Decimal` or an `f64`.
+///
+/// Like Python's `locale.atof`, this drops every period without checking where it is, so `1.5` becomes `15`.
+pub fn from_decimal_comma(s: &str) -> Cow<'_, str> {
+    if !s.contains(['.', ',']) {
+        return Cow::Borrowed(s);
+    }
+    Cow::Owned(s.replace('.', "").replace(',', "."))
+}
+
+/// The number of significant digits every `Decimal` can hold. (Some 29-digit numbers fit, too.)
+const DECIMAL_DIGITS: usize = 28;
+
+/// Whether parsing a number as a `DecimalWrapper` changes its value, because it has more
+/// significant digits than a `Decimal` can hold. (`Decimal` quietly drops the extra digits.)
+///
+/// Numbers in scientific notation and strings that aren't numbers return `false`.
+pub fn loses_precision(s: &str) -> bool {
+    let s = s.trim();
+    // the common case: short numbers always fit
+    if s.bytes().filter(u8::is_ascii_digit).count() <= DECIMAL_DIGITS {
+        return false;
+    }
+    match (significant_digits(s), s.parse::<Decimal>()) {
+        (Some(digits), Ok(parsed)) => significant_digits(&parsed.to_string()) != Some(digits),
+        _ => false,
+    }
+}
+
+/// Returns the significant digits of a number (without leading or trailing zeros) along with
+/// the position of the decimal point relative to the first of them, so `0.0120` and `120`
+/// become `("12", -1)` and `("12", 3)`. Returns `None` for scientific notation.
+fn significant_digits(s: &str) -> Option<(String, i64)> {
+    let unsigned = s.trim_start_matches(['+', '-']);
+    if unsigned.contains(['e', 'E']) {
+        return None;
+    }
+    let (integer, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let digits = format!("{}{}", integer, fraction);
+    let leading_zeros = digits.len() - digits.trim_start_matches('0').len();
+    let exponent = integer.len() as i64 - leading_zeros as i64;
+    let digits = digits.trim_matches('0').to_string();
+    Some((digits, exponent))
+}
+
 impl fmt::Display for DecimalWrapper {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.item.to_string())
+        write!(f, "{}", self.item)
     }
 }
 
@@ -88,37 +341,184 @@ impl std::ops::Div for DecimalWrapper {
     }
 }
 
-/// A light wrapper over `chrono::NaiveDateTime`. Also implements `std::ops::Sub` to compute the total number of
-/// days between two dates. This is probably not smart, but it allows me to easily run `Range` on dates.
-#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+/// A light wrapper over `chrono::NaiveDateTime`. Also implements `std::ops::Sub` to compute the difference
+/// between two dates (as a `DateDifference`), which allows me to easily run `Range` on dates.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd)]
 pub struct CustomDateObject(NaiveDateTime);
 
 impl std::str::FromStr for CustomDateObject {
     type Err = chrono::format::ParseError;
 
+    /// Parses a date with the global formats from `set_date_format` and `set_assumed_offset`
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // need to borrow as mutable to avoid moving the value
-        // https://stackoverflow.com/questions/62248219/rust-accessing-option-from-mutex
-        let first_pass = NaiveDateTime::parse_from_str(s, &*INPUT_DATE_FORMAT.lock().unwrap());
-        let parsed_dt = if first_pass.is_err() {
-            NaiveDate::parse_from_str(s, &*INPUT_DATE_FORMAT.lock().unwrap())
-                .map(|v| v.and_hms(0, 0, 0))
-        } else {
-            first_pass
-        }?;
-        Ok(CustomDateObject(parsed_dt))
+        let formats = INPUT_DATE_FORMAT.lock().unwrap();
+        let assumed_offset = *ASSUMED_OFFSET.lock().unwrap();
+        parse_date(s, &formats, assumed_offset)
+    }
+}
+
+impl CustomDateObject {
+    /// Parses a date with `format` instead of the global formats `FromStr` uses
+    pub fn parse_with_format(
+        s: &str,
+        format: &DateFormat,
+    ) -> chrono::ParseResult<CustomDateObject> {
+        parse_date(s, &format.formats, format.assumed_offset)
     }
 }
 
+fn parse_date(
+    s: &str,
+    formats: &[String],
+    assumed_offset: Option<FixedOffset>,
+) -> chrono::ParseResult<CustomDateObject> {
+    // tries each format in order, returning the error from the last one if none of them match
+    let mut result = parse_single_format(s, &formats[0], assumed_offset);
+    for format in &formats[1..] {
+        if result.is_ok() {
+            break;
+        }
+        result = parse_single_format(s, format, assumed_offset);
+    }
+    // timestamps with offsets, like 2023-05-01T12:00:00+02:00, don't need a format
+    if result.is_err() {
+        if let Ok(date) = DateTime::parse_from_rfc3339(s.trim()) {
+            return Ok(CustomDateObject(date.naive_utc()));
+        }
+    }
+    Ok(CustomDateObject(result?))
+}
+
+/// Parses a date with a single format. Dates with UTC offsets (from `%z` and its variants)
+/// are converted to UTC, as are dates without them if there's an `assumed_offset`.
+fn parse_single_format(
+    s: &str,
+    format: &str,
+    assumed_offset: Option<FixedOffset>,
+) -> chrono::ParseResult<NaiveDateTime> {
+    if format.contains("%z") || format.contains(":z") || format.contains("%#z") {
+        return DateTime::parse_from_str(s, format).map(|date| date.naive_utc());
+    }
+    let date = NaiveDateTime::parse_from_str(s, format).or_else(|_| {
+        NaiveDate::parse_from_str(s, format).map(|v| v.and_hms_opt(0, 0, 0).unwrap())
+    })?;
+    Ok(match assumed_offset {
+        Some(offset) => date - offset,
+        None => date,
+    })
+}
+
 // necessary to get range to work
 impl std::ops::Sub for CustomDateObject {
-    type Output = f64;
+    type Output = DateDifference;
 
-    /// Returns the total number of days between two dates
-    #[allow(clippy::suspicious_arithmetic_impl)]
-    fn sub(self, other: CustomDateObject) -> f64 {
-        let duration = self.0.signed_duration_since(other.0);
-        duration.num_seconds() as f64 / 86400.
+    /// Returns the difference between two dates
+    fn sub(self, other: CustomDateObject) -> DateDifference {
+        DateDifference {
+            start: other.0,
+            end: self.0,
+        }
+    }
+}
+
+/// The difference between two dates, which is what `Range` returns for dates.
+///
+/// How this is displayed depends on `OUTPUT_DURATION_FORMAT`; by default, it's displayed
+/// as the total number of days between the two dates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DateDifference {
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+}
+
+impl DateDifference {
+    /// Returns the total number of days between the two dates, including fractional days.
+    /// Whole numbers of days (like the ranges of dates without times) display without decimals.
+    pub fn days(&self) -> f64 {
+        self.seconds() as f64 / 86400.
+    }
+
+    /// Returns the total number of hours between the two dates, including fractional hours.
+    pub fn hours(&self) -> f64 {
+        self.seconds() as f64 / 3600.
+    }
+
+    /// Returns the total number of seconds between the two dates.
+    pub fn seconds(&self) -> i64 {
+        self.end.signed_duration_since(self.start).num_seconds()
+    }
+
+    fn fmt_human(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total_seconds = self.end.signed_duration_since(self.start).num_seconds();
+        let units = [
+            (total_seconds / 86400, "day"),
+            (total_seconds % 86400 / 3600, "hour"),
+            (total_seconds % 3600 / 60, "minute"),
+            (total_seconds % 60, "second"),
+        ];
+        let parts: Vec<String> = units
+            .iter()
+            .filter(|(amount, _)| *amount != 0)
+            .map(|(amount, unit)| match amount {
+                1 => format!("1 {}", unit),
+                _ => format!("{} {}s", amount, unit),
+            })
+            .collect();
+        if parts.is_empty() {
+            write!(f, "0 days")
+        } else {
+            write!(f, "{}", parts.join(" "))
+        }
+    }
+
+    fn fmt_year_month_day(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut months = (self.end.year() - self.start.year()) * 12 + self.end.month() as i32
+            - self.start.month() as i32;
+        // don't count the last month if we haven't reached the same point in the month yet
+        if months > 0 && add_months(self.start, months) > self.end {
+            months -= 1;
+        }
+        let days = self
+            .end
+            .signed_duration_since(add_months(self.start, months))
+            .num_days();
+        write!(f, "{}y {}m {}d", months / 12, months % 12, days)
+    }
+}
+
+/// Adds calendar months to a date, moving to the end of the month if the day doesn't exist
+/// in the new month (so January 31 plus one month is February 28 or 29).
+fn add_months(date: NaiveDateTime, months: i32) -> NaiveDateTime {
+    let total_months = date.year() * 12 + date.month0() as i32 + months;
+    let (year, month) = (
+        total_months.div_euclid(12),
+        total_months.rem_euclid(12) as u32 + 1,
+    );
+    let mut day = date.day();
+    while NaiveDate::from_ymd_opt(year, month, day).is_none() {
+        day -= 1;
+    }
+    NaiveDate::from_ymd_opt(year, month, day)
+        .unwrap()
+        .and_time(date.time())
+}
+
+impl PartialEq<f64> for DateDifference {
+    fn eq(&self, other: &f64) -> bool {
+        self.days() == *other
+    }
+}
+
+impl fmt::Display for DateDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = *OUTPUT_DURATION_FORMAT.lock().unwrap();
+        match format {
+            DurationFormat::Days => write!(f, "{}", self.days()),
+            DurationFormat::Human => self.fmt_human(f),
+            DurationFormat::YearMonthDay => self.fmt_year_month_day(f),
+            DurationFormat::Hours => write!(f, "{}", self.hours()),
+            DurationFormat::Seconds => write!(f, "{}", self.seconds()),
+        }
     }
 }
 
@@ -136,11 +536,107 @@ mod tests {
 
     #[test]
     fn test_date_subtraction() {
-        let day_recent = CustomDateObject(NaiveDate::from_ymd(2019, 1, 1).and_hms(0, 0, 0));
-        let day_previous = CustomDateObject(NaiveDate::from_ymd(2018, 12, 31).and_hms(0, 0, 0));
+        let day_recent = CustomDateObject(
+            NaiveDate::from_ymd_opt(2019, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+        );
+        let day_previous = CustomDateObject(
+            NaiveDate::from_ymd_opt(2018, 12, 31)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+        );
         assert_eq!(day_recent - day_previous, 1.);
     }
 
+    #[test]
+    fn test_time_buckets() {
+        // Wednesday, March 11, 2020
+        let date = CustomDateObject(
+            NaiveDate::from_ymd_opt(2020, 3, 11)
+                .unwrap()
+                .and_hms_opt(14, 35, 0)
+                .unwrap(),
+        );
+        let labels: Vec<String> = ["year", "month", "week", "day", "hour"]
+            .iter()
+            .map(|bucket| bucket.parse::<TimeBucket>().unwrap().label(&date))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                "2020",
+                "2020-03",
+                "2020-03-09",
+                "2020-03-11",
+                "2020-03-11 14:00"
+            ]
+        );
+        // weeks can start in the previous year
+        let new_years = CustomDateObject(
+            NaiveDate::from_ymd_opt(2021, 1, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap(),
+        );
+        assert_eq!(TimeBucket::Week.label(&new_years), "2020-12-28");
+        assert!("fortnight".parse::<TimeBucket>().is_err());
+    }
+
+    #[test]
+    fn test_date_difference_formats() {
+        // these use the formatting functions directly to avoid changing the global duration format
+        let start = NaiveDate::from_ymd_opt(2018, 1, 31)
+            .unwrap()
+            .and_hms_opt(6, 0, 0)
+            .unwrap();
+        let end = NaiveDate::from_ymd_opt(2020, 2, 1)
+            .unwrap()
+            .and_hms_opt(12, 1, 0)
+            .unwrap();
+        let diff = CustomDateObject(end) - CustomDateObject(start);
+        assert_eq!(diff.days().to_string(), "731.2506944444444".to_string());
+        assert_eq!(diff.hours().to_string(), "17550.016666666666".to_string());
+        assert_eq!(diff.seconds(), 63180060);
+        assert_eq!(
+            HumanDisplay(&diff).to_string(),
+            "731 days 6 hours 1 minute".to_string()
+        );
+        assert_eq!(YmdDisplay(&diff).to_string(), "2y 0m 1d".to_string());
+        // January 31 plus one month is the end of February
+        let end_of_feb = NaiveDate::from_ymd_opt(2019, 3, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let start_of_year = NaiveDate::from_ymd_opt(2019, 1, 31)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let diff = CustomDateObject(end_of_feb) - CustomDateObject(start_of_year);
+        assert_eq!(YmdDisplay(&diff).to_string(), "0y 1m 1d".to_string());
+        // ranges between midnights are whole numbers of days, without a trailing `.0`
+        assert_eq!(diff.days().to_string(), "29".to_string());
+        let same_day = CustomDateObject(start) - CustomDateObject(start);
+        assert_eq!(HumanDisplay(&same_day).to_string(), "0 days".to_string());
+        assert_eq!(YmdDisplay(&same_day).to_string(), "0y 0m 0d".to_string());
+    }
+
+    struct HumanDisplay<'a>(&'a DateDifference);
+    impl fmt::Display for HumanDisplay<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt_human(f)
+        }
+    }
+
+    struct YmdDisplay<'a>(&'a DateDifference);
+    impl fmt::Display for YmdDisplay<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt_year_month_day(f)
+        }
+    }
+
     #[test]
     fn test_scientific_notation() {
         let scinot1: DecimalWrapper = "1e-4".parse().unwrap();
@@ -149,6 +645,60 @@ mod tests {
         assert_eq!(scinot2.to_string(), "13000".to_string());
     }
 
+    #[test]
+    fn test_signs_and_whitespace() {
+        for (input, expected) in &[
+            ("+5", "5"),
+            ("-3.2", "-3.2"),
+            ("  -3.2 ", "-3.2"),
+            ("+1e3", "1000"),
+            ("-1e3", "-1000"),
+            ("1e+3", "1000"),
+            ("+1.5E-2", "0.015"),
+            (" +5\t", "5"),
+        ] {
+            let parsed: DecimalWrapper = input.parse().unwrap();
+            assert_eq!(parsed.to_string(), expected.to_string());
+        }
+        for input in &["+", "++5", "+-5", "1e", "5 5"] {
+            assert!(input.parse::<DecimalWrapper>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_booleans() {
+        for input in &["true", "TRUE", "t", "Yes", "y", "1", " true "] {
+            assert_eq!(input.parse::<BooleanWrapper>(), Ok(BooleanWrapper(true)));
+        }
+        for input in &["false", "F", "no", "N", "0"] {
+            assert_eq!(input.parse::<BooleanWrapper>(), Ok(BooleanWrapper(false)));
+        }
+        for input in &["", "2", "maybe", "truth"] {
+            assert!(input.parse::<BooleanWrapper>().is_err());
+        }
+    }
+
+    #[test]
+    fn test_loses_precision() {
+        assert!(!loses_precision("1.5"));
+        assert!(!loses_precision("79228162514264337593543950335"));
+        assert!(!loses_precision("0.0000000000000000000000000001"));
+        assert!(!loses_precision("1000000000000000000000000000.000000"));
+        assert!(loses_precision("123456789012345678901234567890123"));
+        assert!(loses_precision("1.12345678901234567890123456789012"));
+        assert!(loses_precision("-0.000000000000000000000000000012345"));
+    }
+
+    #[test]
+    fn test_decimal_hash_ignores_scale() {
+        use std::collections::HashSet;
+        let values: HashSet<DecimalWrapper> = ["1", "1.0", "1.00", "1e0", "2.50", "2.5"]
+            .iter()
+            .map(|v| v.parse().unwrap())
+            .collect();
+        assert_eq!(values.len(), 2);
+    }
+
     #[test]
     fn test_parse_multiple_dates() {
         // using panic because a failure on this text could impact other tests
@@ -166,6 +716,15 @@ mod tests {
             let mdy_parse: Result<CustomDateObject, chrono::format::ParseError> =
                 "12-23-2019".parse();
             assert!(mdy_parse.is_ok());
+            set_date_format("%Y-%m-%d;%m/%d/%Y".to_string());
+            let first_format: CustomDateObject = "2019-12-23".parse().unwrap();
+            let second_format: CustomDateObject = "12/23/2019".parse().unwrap();
+            assert_eq!(first_format, second_format);
+            assert!("23.12.2019".parse::<CustomDateObject>().is_err());
+            assert_eq!(
+                describe_date_formats(),
+                "any of the `%Y-%m-%d`, `%m/%d/%Y` formats"
+            );
         });
         set_date_format(OUTPUT_DATE_FORMAT.to_string());
         if let Err(err) = result {
@@ -173,11 +732,77 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_offsets() {
+        let utc = |s: &str| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap();
+        // these don't depend on the date format, so they don't need to set it
+        let with_offset: CustomDateObject = "2023-05-01T12:00:00+02:00".parse().unwrap();
+        assert_eq!(with_offset.0, utc("2023-05-01 10:00:00"));
+        let zulu: CustomDateObject = "2023-05-01T11:00:00Z".parse().unwrap();
+        assert!(with_offset < zulu);
+        assert_eq!(
+            parse_single_format("01/05/2023 08:30 -0200", "%d/%m/%Y %H:%M %z", None),
+            Ok(utc("2023-05-01 10:30:00"))
+        );
+        let cst = parse_offset("-06:00").unwrap();
+        assert_eq!(
+            parse_single_format("2023-05-01 04:00:00", "%Y-%m-%d %H:%M:%S", Some(cst)),
+            Ok(utc("2023-05-01 10:00:00"))
+        );
+        assert_eq!(parse_offset("+0530").unwrap().local_minus_utc(), 19800);
+        assert_eq!(parse_offset("UTC").unwrap().local_minus_utc(), 0);
+        assert_eq!(parse_offset("-05").unwrap().local_minus_utc(), -18000);
+        for invalid in &["0200", "+2:00", "+02:75", "EST", "+"] {
+            assert!(parse_offset(invalid).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decimal_comma() {
+        assert_eq!(from_decimal_comma("1.234,56"), "1234.56");
+        assert_eq!(from_decimal_comma("-1.234.567"), "-1234567");
+        assert_eq!(from_decimal_comma("0,5e3"), "0.5e3");
+        assert!(matches!(from_decimal_comma("12"), Cow::Borrowed("12")));
+        assert_eq!(
+            DecimalWrapper::parse_decimal_comma("1.234,56")
+                .unwrap()
+                .item,
+            Decimal::new(123456, 2)
+        );
+        assert!(DecimalWrapper::parse_decimal_comma("1,2,3").is_err());
+    }
+
+    #[test]
+    fn test_parse_with_format() {
+        // unlike `set_date_format`, this doesn't touch the global formats, so it can run alongside other tests
+        let dmy = DateFormat::new("%d/%m/%Y");
+        let mdy = DateFormat::new("%m/%d/%Y;%Y-%m-%d");
+        let first = CustomDateObject::parse_with_format("05/01/2023", &dmy).unwrap();
+        let second = CustomDateObject::parse_with_format("05/01/2023", &mdy).unwrap();
+        assert_eq!(first.0.month(), 1);
+        assert_eq!(second.0.month(), 5);
+        assert!(CustomDateObject::parse_with_format("2023-01-05", &dmy).is_err());
+        assert_eq!(
+            CustomDateObject::parse_with_format("2023-01-05", &mdy),
+            Ok(first)
+        );
+        assert_eq!(dmy.describe(), "the `%d/%m/%Y` format");
+        assert_eq!(mdy.formats(), ["%m/%d/%Y", "%Y-%m-%d"]);
+        let cst = DateFormat::new("%Y-%m-%d %H:%M:%S")
+            .with_assumed_offset(parse_offset("-06:00").unwrap());
+        assert_eq!(
+            CustomDateObject::parse_with_format("2023-05-01 04:00:00", &cst)
+                .unwrap()
+                .to_string(),
+            "2023-05-01 10:00:00"
+        );
+    }
+
     proptest! {
         #[test]
         fn test_date_parsing(year in 1900..=2020i32, month in 1..=12u32, day in 1..=28u32, hour in 0..=23u32, minute in 0..=59u32, second in 0..=59u32) {
-            let dt = CustomDateObject(NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, second));
-            let _ex = set_date_format("%Y-%m-%d %H:%M:%S".to_string());
+            let dt = CustomDateObject(NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(hour, minute, second).unwrap());
+            set_date_format("%Y-%m-%d %H:%M:%S".to_string());
             let deser_ser : CustomDateObject = dt.to_string().parse().unwrap();
             assert_eq!(dt, deser_ser);
         }
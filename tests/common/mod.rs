@@ -17,7 +17,6 @@
 /// [Clap](https://github.com/clap-rs/clap/blob/master/src/macros.rs#L147))
 /// and from
 /// [this helpful guide to implementing macros](https://medium.com/@phoomparin/a-beginners-guide-to-rust-macros-5c75594498f1).
-#[cfg(not(feature = "no_cargo"))]
 #[macro_export]
 macro_rules! program_path {
     () => {{
@@ -28,3 +27,37 @@ macro_rules! program_path {
         }
     }};
 }
+
+/// A directory for the files a test writes, which is removed when it's dropped,
+/// even if the test fails.
+///
+/// Each test names its own directory, and the process id keeps separate runs
+/// from sharing one.
+#[allow(dead_code)]
+pub struct TempDir(std::path::PathBuf);
+
+#[allow(dead_code)]
+impl TempDir {
+    /// Creates an empty directory for the test `name`
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("clipivot_{}_{}", name, std::process::id()));
+        // a run that was killed before it could clean up can leave files behind
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+
+    pub fn join<P: AsRef<std::path::Path>>(&self, name: P) -> std::path::PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
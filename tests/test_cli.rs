@@ -1,4 +1,5 @@
 use assert_cmd::Command;
+use std::fs;
 use std::process::Output;
 /// This module holds most of the integration tests (basically everything but numerical accuracy tests)
 use std::str;
@@ -26,6 +27,15 @@ fn setup_results(query: &[&str]) -> Vec<Vec<String>> {
     results
 }
 
+/// Like `setup_results`, but for pivot tables without rows or columns, which don't have a header.
+fn setup_scalar_results(query: &[&str]) -> Vec<Vec<String>> {
+    let output = setup_cmd(query).stdout;
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(output.as_slice());
+    rdr.deserialize().map(|result| result.unwrap()).collect()
+}
+
 fn setup_sorting_columns(query: &[&str]) -> Vec<String> {
     let output = setup_cmd(query).stdout;
     let stroutput = str::from_utf8(&output).unwrap();
@@ -115,8 +125,9 @@ fn test_index_sorting_works() {
 fn test_flag_ignores_empty_vals() {
     let query = ["count", "test_csvs/empty_count.csv", "-v", "2", "-e"];
     let output = setup_cmd(&query).stdout;
-    let stroutput = str::from_utf8(&output).unwrap();
-    let mut rdr = csv::Reader::from_reader(stroutput.as_bytes());
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(output.as_slice());
     let mut iter = rdr.deserialize();
     let item: (String, usize) = iter.next().unwrap().unwrap();
     assert_eq!(item.1, 1);
@@ -125,8 +136,9 @@ fn test_flag_ignores_empty_vals() {
 fn test_wo_e_flag_parses_empty_vals() {
     let query = ["count", "test_csvs/empty_count.csv", "-v", "2"];
     let output = setup_cmd(&query).stdout;
-    let stroutput = str::from_utf8(&output).unwrap();
-    let mut rdr = csv::Reader::from_reader(stroutput.as_bytes());
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(output.as_slice());
     let mut iter = rdr.deserialize();
     let item: (String, usize) = iter.next().unwrap().unwrap();
     assert_eq!(item.1, 2);
@@ -138,7 +150,7 @@ fn test_noheader() {
     let output = setup_cmd(&query);
     assert!(!output.status.success());
     query.push("--no-header");
-    let new_output = setup_results(&query);
+    let new_output = setup_scalar_results(&query);
     assert_eq!(new_output, vec![vec!["total".to_string(), "1".to_string()]]);
 }
 
@@ -148,7 +160,7 @@ fn test_mode() {
     let output: Output = setup_cmd(&file_query);
     assert!(output.status.success());
     assert_eq!(
-        setup_results(&file_query),
+        setup_scalar_results(&file_query),
         vec![vec!["total".to_string(), "1".to_string()]]
     );
 }
@@ -159,7 +171,7 @@ fn test_tab_delimiter() {
     let output = setup_cmd(&file_query);
     assert!(output.status.success());
     assert_eq!(
-        setup_results(&file_query),
+        setup_scalar_results(&file_query),
         vec![vec!["total".to_string(), "2".to_string()]]
     );
     let stdin_contents = "foo, bar	bar	baz
@@ -169,8 +181,9 @@ fn test_tab_delimiter() {
         .args(vec!["count", "-v", "0"])
         .write_stdin(stdin_contents)
         .assert()
-        // should fail because of the comma on the header
-        .failure();
+        // the tabs split every line into 3 fields, while the comma only splits the header
+        .success()
+        .stdout("total,2\n");
     let _cmd_with_t = Command::new(program_path!())
         .args(vec!["count", "-v", "0", "-t"])
         .write_stdin(stdin_contents)
@@ -198,3 +211,3073 @@ fn test_custom_delim() {
         .assert()
         .success();
 }
+
+#[test]
+fn test_sum_squares_and_rms() {
+    let stdin_contents = "val\n3\n-4\n";
+    Command::new(program_path!())
+        .args(vec!["sumsq", "-v", "val"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,25\n");
+    Command::new(program_path!())
+        .args(vec!["rms", "-v", "val"])
+        .write_stdin("val\n2\n-2\n")
+        .assert()
+        .success()
+        .stdout("total,2\n");
+}
+
+#[test]
+fn test_minmax_separator() {
+    let stdin_contents = "val\n3\n-4\n10\n";
+    Command::new(program_path!())
+        .args(vec!["minmax", "-v", "val", "-N"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,-4 - 10\n");
+    Command::new(program_path!())
+        .args(vec!["minmax", "-v", "val", "-N", "--minmax-sep", ".."])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,-4..10\n");
+    // a separator containing the delimiter still produces a single, quoted cell
+    Command::new(program_path!())
+        .args(vec!["minmax", "-v", "val", "-N", "--minmax-sep", ","])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,\"-4,10\"\n");
+}
+
+#[test]
+fn test_duration_format() {
+    let stdin_contents = "date\n2018-01-02\n2020-01-03\n2019-02-03\n";
+    Command::new(program_path!())
+        .args(vec!["range", "-v", "date", "-F", "%Y-%m-%d"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,731\n");
+    Command::new(program_path!())
+        .args(vec![
+            "range",
+            "-v",
+            "date",
+            "-F",
+            "%Y-%m-%d",
+            "--duration-format",
+            "ymd",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,2y 0m 1d\n");
+    Command::new(program_path!())
+        .args(vec![
+            "range",
+            "-v",
+            "date",
+            "-F",
+            "%Y-%m-%d",
+            "--duration-format",
+            "human",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,731 days\n");
+    for (unit, expected) in &[
+        ("days", "total,731\n"),
+        ("hours", "total,17544\n"),
+        ("seconds", "total,63158400\n"),
+    ] {
+        Command::new(program_path!())
+            .args(vec![
+                "range",
+                "-v",
+                "date",
+                "-F",
+                "%Y-%m-%d",
+                "--date-range-unit",
+                unit,
+            ])
+            .write_stdin(stdin_contents)
+            .assert()
+            .success()
+            .stdout(*expected);
+    }
+}
+
+#[test]
+fn test_file_and_stdin_output_match() {
+    let contents = fs::read("test_csvs/layoffs.csv").unwrap();
+    let queries = vec![
+        vec!["-r", "department", "-c", "was_fired"],
+        vec!["-r", "was_fired", "-c", "department", "-v", "salary"],
+        vec!["-r", "department,was_fired", "-c", "salary", "-v", "id"],
+    ];
+    for (aggfunc, query) in ["count", "sum", "mode"].iter().zip(queries) {
+        let from_file = Command::new(program_path!())
+            .args([aggfunc, "test_csvs/layoffs.csv"])
+            .args(&query)
+            .output()
+            .unwrap();
+        let from_stdin = Command::new(program_path!())
+            .arg(aggfunc)
+            .args(&query)
+            .write_stdin(contents.clone())
+            .output()
+            .unwrap();
+        assert!(from_file.status.success());
+        assert_eq!(from_file.stdout, from_stdin.stdout);
+    }
+}
+
+#[test]
+fn test_quote_options() {
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "test_csvs/single_quotes.csv",
+            "--quote",
+            "'",
+            "-v",
+            "amount",
+            "-r",
+            "name",
+        ])
+        .assert()
+        .success()
+        .stdout(",total\n\"Lee, Max\",1\n\"O'Brien, Sam\",2\nAnn,3\n");
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "test_csvs/single_quotes.csv",
+            "--quote",
+            "'",
+            "-r",
+            "city",
+        ])
+        .assert()
+        .success()
+        .stdout(",total\nDenver,2\nBoulder,1\n");
+    // without the quote character, the commas in the names split the rows into the wrong fields
+    Command::new(program_path!())
+        .args(vec!["count", "test_csvs/single_quotes.csv", "-r", "city"])
+        .assert()
+        .code(4);
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "name", "--no-quoting"])
+        .write_stdin("name\n\"Max\n\"Max\n")
+        .assert()
+        .success()
+        .stdout(",total\n\"\"\"Max\",2\n");
+}
+
+#[test]
+fn test_infer() {
+    // as text, 9 would be the maximum
+    Command::new(program_path!())
+        .args(vec!["max", "-v", "amount", "--infer"])
+        .write_stdin("amount\n9\n10\n\n")
+        .assert()
+        .success()
+        .stdout("total,10\n");
+    Command::new(program_path!())
+        .args(vec!["range", "-v", "date", "--infer"])
+        .write_stdin("date\n2019-01-01 00:00:00\n2019-01-03 12:00:00\n")
+        .assert()
+        .success()
+        .stdout("total,2.5\n");
+    // a mixed column falls back to text
+    Command::new(program_path!())
+        .args(vec!["max", "-v", "amount", "--infer"])
+        .write_stdin("amount\n9\n10\nten\n")
+        .assert()
+        .success()
+        .stdout("total,ten\n");
+    Command::new(program_path!())
+        .args(vec!["range", "-v", "amount", "--infer"])
+        .write_stdin("amount\n9\n10\nten\n")
+        .assert()
+        .code(2);
+    // -N takes precedence
+    Command::new(program_path!())
+        .args(vec!["max", "-v", "amount", "--infer", "-N"])
+        .write_stdin("amount\n9\n10\nten\n")
+        .assert()
+        .code(4);
+    Command::new(program_path!())
+        .args(vec![
+            "min",
+            "test_csvs/layoffs.csv",
+            "-v",
+            "salary",
+            "--infer",
+            "--check",
+        ])
+        .assert()
+        .success()
+        .stdout("rows: \ncolumns: \nvalues: 2\nparsing strategy: numeric\n");
+}
+
+#[test]
+fn test_infer_past_delimiter_sample() {
+    // the first values are far past the start of the input read to guess the delimiter,
+    // and everything --infer reads has to be read again
+    let stdin_contents = format!("name;amount\n{}a;9\na;10\n", "x;\n".repeat(10_000));
+    Command::new(program_path!())
+        .args(vec!["max", "-r", "name", "-v", "amount", "--infer", "-e"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,10\n");
+}
+
+#[test]
+fn test_no_trim() {
+    let stdin_contents = "state,amount\nCO,1\nCO ,2\n ,3\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "state", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nCO,3\n,3\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "state", "-v", "amount", "--no-trim"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nCO,1\nCO ,2\n ,3\n");
+}
+
+#[test]
+fn test_duplicate_value_header() {
+    let stdin_contents = "name,amount,amount\nMax,1,10\nSam,2,20\nMax,3,30\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "name", "-v", "amount[1]"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,40\nSam,20\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "name", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,4\nSam,2\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "name", "-v", "amount[2]"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2)
+        .stderr(
+            "Could not properly configure the aggregator: `amount` only appears 2 time(s) \
+             in the header row, so there isn't a `amount[2]` (the numbers in brackets start at 0)\n",
+        );
+}
+
+#[test]
+fn test_range_needs_numeric_or_date() {
+    let stdin_contents = "amount\n1.5\n4\n-2\n";
+    Command::new(program_path!())
+        .args(vec!["range", "-v", "amount", "-N"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,6\n");
+    Command::new(program_path!())
+        .args(vec!["range", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_multiple_date_formats() {
+    let stdin_contents = "date\n2018-01-02\n01/03/2020\n2019-02-03\n";
+    Command::new(program_path!())
+        .args(vec!["range", "-v", "date", "--format", "%Y-%m-%d;%m/%d/%Y"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,731\n");
+    Command::new(program_path!())
+        .args(vec!["max", "-v", "date", "-F", "%Y-%m-%d;%d.%m.%Y"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(4)
+        .stderr(
+            "Could not parse record `01/03/2020` with index 1: Could not parse as a datetime \
+             or as a date (without a time) with any of the `%Y-%m-%d`, `%d.%m.%Y` formats. \
+             Hint: you can set the date formats with the -F option\n",
+        );
+}
+
+#[test]
+fn test_date_errors_name_first_bad_line() {
+    let stdin_contents = "date\n2018-01-02\n01/03/2020\n02/03/2020\n";
+    Command::new(program_path!())
+        .args(vec!["max", "-v", "date", "-F", "%Y-%m-%d"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .stdout("")
+        .stderr(
+            "Could not parse record `01/03/2020` with index 1: Could not parse as a datetime \
+             with the `%Y-%m-%d` format, or as a date (without a time) with the `%Y-%m-%d` format. \
+             Hint: you can set the date format with the -F option\n",
+        );
+}
+
+#[test]
+fn test_check_validates_without_aggregating() {
+    let stdin_contents = "name,state,amount\nMax,CO,not a number\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum", "-r", "state", "-c", "name", "-v", "amount", "--check",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("rows: 1\ncolumns: 0\nvalues: 2\nparsing strategy: numeric\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "city", "-v", "amount", "--check"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .stdout("");
+}
+
+#[test]
+fn test_stats_go_to_stderr() {
+    let stdin_contents = "name,state,amount\nMax,CO,1\nSam,NY,\nAlex,CO,2\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "state", "-v", "amount", "-e", "--stats"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nCO,3\n")
+        .stderr(
+            "distinct rows: 1\ndistinct columns: 1\nrecords processed: 3\nrecords skipped as empty: 1\n",
+        );
+}
+
+#[test]
+fn test_latin1_encoding() {
+    let stdin_contents = b"name,amount\nJos\xe9,1\nJos\xe9,2\n".to_vec();
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "name",
+            "-v",
+            "amount",
+            "--encoding",
+            "latin1",
+        ])
+        .write_stdin(stdin_contents.clone())
+        .assert()
+        .success()
+        .stdout(",total\nJosé,3\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "name", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .stdout("");
+}
+
+#[test]
+fn test_zero_dimension_pivot_is_scalar() {
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "0", "test_csvs/NumAcc1.csv"])
+        .assert()
+        .success()
+        .stdout("total,30000006\n");
+    let stdin_contents = "a,v\nx,1\ny,2\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "v", "--with-col-count"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,3\ncount,2\n");
+    // with more than one value, the header says which is which
+    Command::new(program_path!())
+        .args(vec!["agg", "--funcs", "sum,mean", "-v", "v"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",sum,mean\ntotal,3,1.5\n");
+}
+
+#[test]
+fn test_crlf_line_endings() {
+    let stdin_contents = "name,state\r\nMax,CO\nSam,NY\r\n";
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "state", "-v", "name"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nCO,1\nNY,1\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "state", "-v", "name", "--crlf"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\r\nCO,1\r\nNY,1\r\n");
+}
+
+#[test]
+fn test_progress_keeps_stdout_clean() {
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-v",
+            "0",
+            "test_csvs/NumAcc1.csv",
+            "--progress",
+        ])
+        .assert()
+        .success()
+        .stdout("total,30000006\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-v", "0", "--progress"])
+        .write_stdin("letter\na\nb\nc\n")
+        .assert()
+        .success()
+        .stdout("total,3\n");
+}
+
+#[test]
+fn test_cumulative() {
+    let stdin_contents = "year,state,amount\n2018,CO,1\n2019,CO,2.5\n2019,NY,4\n2020,CO,3\n";
+    let args = vec![
+        "sum",
+        "-r",
+        "year",
+        "-c",
+        "state",
+        "-v",
+        "amount",
+        "-A",
+        "--cumulative",
+    ];
+    let output = Command::new(program_path!())
+        .args(&args)
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    let mut rdr = csv::Reader::from_reader(output.stdout.as_slice());
+    let results: Vec<Vec<String>> = rdr.deserialize().map(|r| r.unwrap()).collect();
+    assert_eq!(
+        results,
+        vec![
+            vec!["2018", "1", ""],
+            vec!["2019", "3.5", "4"],
+            vec!["2020", "6.5", "4"],
+        ]
+    );
+    // the last row's running total is the total of the whole column
+    let totals = Command::new(program_path!())
+        .args(vec!["sum", "-c", "state", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    assert_eq!(
+        str::from_utf8(&totals.stdout).unwrap(),
+        ",CO,NY\ntotal,6.5,4\n"
+    );
+    Command::new(program_path!())
+        .args(vec!["mean", "-r", "year", "-v", "amount", "--cumulative"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_sample_check() {
+    let stdin_contents = "name,amount\nMax,1\nSam,\nAlex,2\n";
+    Command::new(program_path!())
+        .args(vec!["mean", "-v", "name", "--sample-check"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .stderr(
+            "Could not properly configure the aggregator: None of the first 3 non-empty values \
+             in the values column could be parsed (Failed to parse as numeric). \
+             Did you choose the right column and function?\n",
+        );
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "name",
+            "-v",
+            "amount",
+            "-e",
+            "--sample-check",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,1\nAlex,2\n");
+}
+
+#[test]
+fn test_na_rep() {
+    let stdin_contents = "name,state,amount\nMax,CO,1\nSam,NY,2\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum", "-r", "name", "-c", "state", "-v", "amount", "--na-rep", "-",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",CO,NY\nMax,1,-\nSam,-,2\n");
+}
+
+#[test]
+fn test_na_number() {
+    let stdin_contents = "name,state,amount\nMax,CO,1\nSam,CO,-999\nAnn,CO,3\nSam,NY,9999.0\n";
+    Command::new(program_path!())
+        .args(vec![
+            "mean",
+            "-r",
+            "state",
+            "-v",
+            "amount",
+            "--na-number",
+            "-999",
+            "--na-number",
+            "9999",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nCO,2\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount", "--na-number", "-999"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,10003.0\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-v", "amount", "--na-number", "-999"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_count_empty_as_zero() {
+    let stdin_contents = "name,state,amount\nMax,CO,1\nSam,NY,\nAnn,CO,2\nSam,CO,NA\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum", "-r", "name", "-c", "state", "-v", "amount", "-e",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",CO\nMax,1\nAnn,2\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "name",
+            "-c",
+            "state",
+            "-v",
+            "amount",
+            "-e",
+            "--count-empty-as-zero",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",CO,NY\nMax,1,0\nSam,0,0\nAnn,2,0\n");
+    Command::new(program_path!())
+        .args(vec!["mean", "-v", "amount", "-e", "--count-empty-as-zero"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_skip_errors() {
+    let stdin_contents = "name,state,amount\nMax,CO,1\nSam,NY,one\nAnn,CO,2\nSam,CO,$3\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "name", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(4);
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "name", "-v", "amount", "--skip-errors"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,1\nAnn,2\n")
+        .stderr(
+            "Skipped 2 value(s) that could not be parsed:\n  \
+             Could not parse record `one` with index 1: Failed to parse as numeric\n  \
+             Could not parse record `$3` with index 3: Failed to parse as numeric\n",
+        );
+}
+
+#[test]
+fn test_key_separator() {
+    let stdin_contents = "city,state,team,sport\nColumbus,OH,Crew,Soccer\nColumbus,OH,Blue Jackets,Hockey\nDenver,CO,Avalanche,Hockey\n";
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "city,state", "-c", "sport"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",Hockey,Soccer\nColumbus_<sep>_OH,1,1\nDenver_<sep>_CO,1,\n");
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "city,state",
+            "-c",
+            "state,sport",
+            "--key-sep",
+            " / ",
+            "--cols-drop",
+            "CO / Hockey",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",OH / Hockey,OH / Soccer\nColumbus / OH,1,1\nDenver / CO,,\n");
+}
+
+#[test]
+fn test_carry() {
+    let stdin_contents =
+        "code,state,year,amount\nCO,Colorado,2019,1\nNY,New York,2019,2\nCO,Colo.,2020,3\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum", "-r", "code", "-c", "year", "-v", "amount", "--carry", "state",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",state,2019,2020\nCO,Colorado,1,3\nNY,New York,2,\n")
+        .stderr(
+            "Warning: `state` has more than one value in the `CO` row, so only its first value is displayed\n",
+        );
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount", "--carry", "state"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_cardinality() {
+    Command::new(program_path!())
+        .args(vec!["cardinality", "-r", "state", "-v", "name"])
+        .write_stdin("state,name\nCO,Max\nCO,Sam\nCO,Max\nNY,Lee\n")
+        .assert()
+        .success()
+        .stdout(",total\nCO,2 (0.6667)\nNY,1 (1)\n");
+}
+
+#[test]
+fn test_sample() {
+    let stdin_contents = "state,name\nCO,Max\nCO,Sam\nCO,Ann\nNY,Lee\n";
+    let args = vec![
+        "sample", "-r", "state", "-v", "name", "--k", "2", "--seed", "7",
+    ];
+    let first = Command::new(program_path!())
+        .args(&args)
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    let second = Command::new(program_path!())
+        .args(&args)
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    assert!(first.status.success());
+    assert_eq!(first.stdout, second.stdout);
+    let mut rdr = csv::Reader::from_reader(first.stdout.as_slice());
+    let results: Vec<Vec<String>> = rdr.deserialize().map(|r| r.unwrap()).collect();
+    assert_eq!(results[0][1].split("; ").count(), 2);
+    assert_eq!(results[1], vec!["NY", "Lee"]);
+    Command::new(program_path!())
+        .args(vec!["sample", "-v", "name", "--k", "0"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_with_count() {
+    let stdin_contents = "name,state,amount\nMax,CO,1\nSam,CO,2\nSam,NY,2\n";
+    Command::new(program_path!())
+        .args(vec!["mean", "-c", "state", "-v", "amount", "--with-count"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",CO,NY\ntotal,1.5 (2),2 (1)\n");
+    // annotations with the delimiter in them get quoted
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "state",
+            "-v",
+            "amount",
+            "--with-count",
+            "--count-format",
+            "{value}, n={count}",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nCO,\"3, n=2\"\nNY,\"2, n=1\"\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "state", "--with-count"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_exit_codes() {
+    let stdin_contents = "name,amount\nMax,1\nSam,abc\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "city"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount", "-d", "ab"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["sum", "test_csvs/does_not_exist.csv", "-v", "amount"])
+        .assert()
+        .code(3);
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_xlsx_input() {
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "test_csvs/sample.xlsx",
+            "-r",
+            "department",
+            "-v",
+            "salary",
+        ])
+        .assert()
+        .success()
+        .stdout(",total\nsales,110000\nengineering,80000\n");
+    // without -F, dates are left as Excel serial numbers
+    Command::new(program_path!())
+        .args(vec![
+            "max",
+            "test_csvs/sample.xlsx",
+            "--sheet",
+            "hires",
+            "-v",
+            "hired",
+            "-N",
+        ])
+        .assert()
+        .success()
+        .stdout("total,43831\n");
+    Command::new(program_path!())
+        .args(vec![
+            "range",
+            "test_csvs/sample.xlsx",
+            "--sheet",
+            "1",
+            "-v",
+            "hired",
+            "-F",
+            "%Y-%m-%d",
+        ])
+        .assert()
+        .success()
+        .stdout("total,365\n");
+    Command::new(program_path!())
+        .args(vec![
+            "range",
+            "test_csvs/sample.xlsx",
+            "--sheet",
+            "1",
+            "-v",
+            "hired",
+            "-F",
+            "%Q",
+        ])
+        .assert()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "test_csvs/sample.xlsx",
+            "--sheet",
+            "nope",
+            "-v",
+            "0",
+        ])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_fast_median() {
+    Command::new(program_path!())
+        .args(vec!["median", "-v", "0", "--fast-median"])
+        .write_stdin("val\n3\n1\n2\n10\n")
+        .assert()
+        .success()
+        .stdout("total,2.5\n");
+}
+
+#[test]
+fn test_count_without_values_column() {
+    let stdin_contents = "name,state\nMax,CO\n,CO\nSam,NY\n";
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "state"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nCO,2\nNY,1\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "state", "-e"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "state"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2)
+        .stderr(
+            "Could not properly configure the aggregator: \
+             You need to choose a values column with -v (it's only optional for count, crosstab and shape)\n",
+        );
+}
+
+#[test]
+fn test_cols_keep_and_drop() {
+    let stdin_contents = "state,year\nCO,2021\nCO,2022\nNY,2023\n";
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "state",
+            "-c",
+            "year",
+            "--cols-keep",
+            "2021,2023,2030",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",2021,2023\nCO,1,\nNY,,1\n")
+        .stderr("Warning: `2030` is not a column in the pivot table\n");
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "state",
+            "-c",
+            "year",
+            "--cols-drop",
+            "2022",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",2021,2023\nCO,1,\nNY,,1\n")
+        .stderr("");
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "state",
+            "-c",
+            "year",
+            "--cols-keep",
+            "2030",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_header_names() {
+    let stdin_contents = "west,2020,1\neast,2020,2\nwest,2021,3\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "--no-header",
+            "--header-names",
+            "region,year,amount",
+            "-r",
+            "region",
+            "-c",
+            "year",
+            "-v",
+            "amount",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("region,2020,2021\nwest,1,3\neast,2,\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "--no-header",
+            "--header-names",
+            "region,year",
+            "-v",
+            "0",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+    // without --header-names, columns have to be numeric
+    Command::new(program_path!())
+        .args(vec!["sum", "--no-header", "-r", "region", "-v", "2"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn test_list_functions() {
+    let output = Command::new(program_path!())
+        .arg("--list-functions")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = str::from_utf8(&output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "function\tparsing\trows_and_columns");
+    assert_eq!(lines.len(), 31);
+    assert!(lines.contains(&"range\tnumeric,date\ttrue"));
+    // pivot-longer doesn't accept -c
+    assert!(lines.contains(&"pivot-longer\ttext\tfalse"));
+    let json_output = Command::new(program_path!())
+        .args(vec!["--list-functions", "--json"])
+        .output()
+        .unwrap();
+    assert!(str::from_utf8(&json_output.stdout).unwrap().starts_with(
+        "[{\"function\":\"agg\",\"parsing\":[\"numeric\"],\"rows_and_columns\":true},"
+    ));
+}
+
+#[test]
+fn test_contradictory_sort_flags() {
+    let base = vec![
+        "count",
+        "test_csvs/sorting_csv.csv",
+        "-v",
+        "0",
+        "-c",
+        "year",
+    ];
+    for flags in [
+        vec!["--asc-cols", "--desc-cols"],
+        vec!["--asc-cols", "--index-cols"],
+        vec!["--index-cols", "--desc-cols"],
+        vec!["--asc-rows", "--index-rows"],
+        vec!["--desc-rows", "--index-rows"],
+    ] {
+        let mut args = base.clone();
+        args.extend(flags);
+        Command::new(program_path!()).args(&args).assert().code(2);
+    }
+    let mut args = base.clone();
+    args.extend(vec!["--asc-cols", "--index-rows"]);
+    assert_eq!(
+        setup_sorting_columns(&args),
+        vec!["2016", "2017", "2018", "2019"]
+    );
+}
+
+#[test]
+fn test_histogram() {
+    let stdin_contents = "state,amount\nCO,5\nCO,50\nNY,-3\nNY,20\nCO,1000\nCO,9.99\n";
+    Command::new(program_path!())
+        .args(vec![
+            "histogram",
+            "-r",
+            "state",
+            "-v",
+            "amount",
+            "--bins",
+            "0,10,100,1000",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",..0,0..10,10..100,1000..\nCO,,2,1,1\nNY,1,,1,\n");
+    Command::new(program_path!())
+        .args(vec!["histogram", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["histogram", "-v", "state", "--bins", "0,10"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_row_bucket() {
+    let stdin_contents =
+        "date,amount\n2020-01-05 08:00:00,1\n2020-01-20 10:30:00,2\n2020-02-01 23:59:59,3\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "date",
+            "-v",
+            "amount",
+            "--row-bucket",
+            "month",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\n2020-01,3\n2020-02,3\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "date",
+            "-v",
+            "amount",
+            "--row-bucket",
+            "week",
+            "-A",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\n2019-12-30,1\n2020-01-20,2\n2020-01-27,3\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "amount",
+            "-v",
+            "amount",
+            "--row-bucket",
+            "day",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(4);
+}
+
+#[test]
+fn test_header_comment() {
+    let stdin_contents = "state,year,amount\nOH,2019,1\nOH,2020,2\nPA,2020,3\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "state",
+            "-c",
+            "year",
+            "-v",
+            "amount",
+            "--header-comment",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("# values: sum(amount); rows: state; columns: year\n,2019,2020\nOH,1,2\nPA,,3\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "state", "--multi-header"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\n,count(*)\nOH,2\nPA,1\n");
+    // the label names the values field, not the --order-by field
+    Command::new(program_path!())
+        .args(vec![
+            "last",
+            "-r",
+            "state",
+            "-v",
+            "amount",
+            "--order-by",
+            "year",
+            "-N",
+            "--header-comment",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("# values: last(amount); rows: state; columns: \n,total\nOH,2\nPA,3\n");
+}
+
+#[test]
+fn test_crosstab() {
+    let stdin_contents = "smoker,sick\nyes,yes\nyes,no\nno,no\nno,no\nyes,yes\n";
+    Command::new(program_path!())
+        .args(vec![
+            "crosstab",
+            "-r",
+            "smoker",
+            "-c",
+            "sick",
+            "-A",
+            "--asc-cols",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",no,yes\nno,2,\nyes,1,2\n");
+    Command::new(program_path!())
+        .args(vec!["crosstab", "-r", "smoker", "-c", "sick", "-v", "sick"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_selectors_from_files() {
+    let dir = common::TempDir::new("selectors");
+    let rows_file = dir.join("rows.txt");
+    let cols_file = dir.join("cols.txt");
+    let val_file = dir.join("val.txt");
+    fs::write(&rows_file, "state\n\n").unwrap();
+    fs::write(&cols_file, "year,'kind'\n").unwrap();
+    fs::write(&val_file, "amount[0]\n").unwrap();
+    let stdin_contents = "state,year,kind,amount\nOH,2019,a,1\nOH,2020,a,2\nPA,2020,b,3\n";
+    let from_files = Command::new(program_path!())
+        .arg("sum")
+        .arg("--rows-file")
+        .arg(&rows_file)
+        .arg("--cols-file")
+        .arg(&cols_file)
+        .arg("--val-file")
+        .arg(&val_file)
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    let from_args = Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "state",
+            "-c",
+            "year,kind",
+            "-v",
+            "amount",
+        ])
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    assert!(from_files.status.success());
+    assert_eq!(from_files.stdout, from_args.stdout);
+    fs::write(&val_file, "amount\nyear\n").unwrap();
+    Command::new(program_path!())
+        .arg("sum")
+        .arg("-r")
+        .arg("state")
+        .arg("--val-file")
+        .arg(&val_file)
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_empty_input() {
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "a"])
+        .write_stdin("")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(
+            "Could not properly configure the aggregator: \
+             The input is empty, so there's no header row or records to aggregate\n",
+        );
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "a"])
+        .write_stdin("a,b\n")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(
+            "Could not properly configure the aggregator: \
+             The input has a header row but no records to aggregate\n",
+        );
+    // a file without records is fine as long as another file has some
+    let dir = common::TempDir::new("empty");
+    let empty = dir.join("empty.csv");
+    let full = dir.join("full.csv");
+    fs::write(&empty, "a,b\n").unwrap();
+    fs::write(&full, "a,b\nx,1\n").unwrap();
+    for extra_args in [vec![], vec!["--merge-files"]] {
+        Command::new(program_path!())
+            .arg("count")
+            .args([&empty, &full])
+            .args(vec!["-r", "a"])
+            .args(extra_args)
+            .assert()
+            .success()
+            .stdout(",total\nx,1\n");
+    }
+    Command::new(program_path!())
+        .arg("count")
+        .args([&empty, &empty])
+        .args(vec!["-r", "a", "--merge-files"])
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_precision() {
+    let stdin_contents = "g,v\na,1\na,2\na,4\nb,1000000\nb,3000000.5\n";
+    Command::new(program_path!())
+        .args(vec!["stddev", "-r", "g", "-v", "v", "--precision", "2"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,1.53\nb,1414213.92\n");
+    Command::new(program_path!())
+        .args(vec![
+            "stddev",
+            "-r",
+            "g",
+            "-v",
+            "v",
+            "--precision",
+            "3",
+            "--notation",
+            "general",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,1.53\nb,1.41e6\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "g", "--precision", "2"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    // more digits than a Decimal keeps
+    Command::new(program_path!())
+        .args(vec!["stddev", "-r", "g", "-v", "v", "--precision", "99999"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_named_pipe_input() {
+    let dir = common::TempDir::new("fifo");
+    let fifo = dir.join("input.csv");
+    let stdin_contents = "name,amount\nMax,1\nSam,2\nMax,3\n";
+    for extra_args in [vec![], vec!["--infer"], vec!["--encoding", "utf-8"]] {
+        let _ = fs::remove_file(&fifo);
+        assert!(std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap()
+            .success());
+        let writer_path = fifo.clone();
+        let writer = std::thread::spawn(move || fs::write(writer_path, stdin_contents).unwrap());
+        Command::new(program_path!())
+            .args(vec!["max", "-r", "name", "-v", "amount"])
+            .arg(&fifo)
+            .args(extra_args)
+            .assert()
+            .success()
+            .stdout(",total\nMax,3\nSam,2\n");
+        writer.join().unwrap();
+    }
+}
+
+#[test]
+fn test_max_by_and_min_by() {
+    let stdin_contents = "region,product,sale\nE,a,5\nE,b,9\nE,c,9\nW,d,10\nW,e,2\n";
+    Command::new(program_path!())
+        .args(vec![
+            "maxby", "-r", "region", "-v", "sale", "--pick", "product", "-N",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nE,b\nW,d\n");
+    Command::new(program_path!())
+        .args(vec![
+            "minby", "-r", "region", "-v", "sale", "--pick", "product", "-N",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nE,a\nW,e\n");
+    Command::new(program_path!())
+        .args(vec![
+            "maxby",
+            "-r",
+            "region",
+            "-v",
+            "sale",
+            "--pick",
+            "product",
+            "-N",
+            "--sample-check",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nE,b\nW,d\n");
+    Command::new(program_path!())
+        .args(vec!["maxby", "-r", "region", "-v", "sale"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["max", "-v", "sale", "--pick", "product"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_row_and_col_counts() {
+    let stdin_contents = "state,year,amount\nOH,2019,1\nOH,2020,2\nOH,2020,\nPA,2020,3\n";
+    Command::new(program_path!())
+        .args(vec![
+            "mean",
+            "-r",
+            "state",
+            "-c",
+            "year",
+            "-v",
+            "amount",
+            "-e",
+            "--with-row-count",
+            "--with-col-count",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",2019,2020,count\nOH,1,2,2\nPA,,3,1\ncount,1,2,3\n");
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "state",
+            "-c",
+            "year",
+            "--with-col-count",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",2019,2020\nOH,1,2\nPA,,1\ncount,1,3\n");
+}
+
+#[test]
+fn test_labels_containing_separator() {
+    // a single value that looks like two joined values stays a single value
+    let stdin_contents = "name,kind\nA_<sep>_B,x\nA,y\n";
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "name",
+            "-c",
+            "kind",
+            "--key-sep",
+            " / ",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",x,y\nA_<sep>_B,1,\nA,,1\n");
+}
+
+#[test]
+fn test_group_digits() {
+    let stdin_contents = "g,v\na,1234567.891\na,-1000\nb,999\nb,12\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "g",
+            "-v",
+            "v",
+            "--group-digits",
+            "--precision",
+            "1",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,\"1,233,567.9\"\nb,\"1,011.0\"\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "g",
+            "-v",
+            "v",
+            "--group-digits",
+            "--group-sep",
+            ".",
+            "--decimal-sep",
+            ",",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,\"1.233.567,891\"\nb,1.011\n");
+    Command::new(program_path!())
+        .args(vec!["mode", "-r", "g", "-v", "v", "--group-digits"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_limit_input() {
+    let stdin_contents = "name\nMax\nSam\nMax\nMax\nSam\n";
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "name", "--limit-input", "3"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,2\nSam,1\n");
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "name",
+            "-v",
+            "name",
+            "--limit-input",
+            "2",
+            "--sample-check",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,1\nSam,1\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "name", "--limit-input", "0"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_signed_numbers_with_whitespace() {
+    let stdin_contents = "g,v\na, -3.2 \na,+1e1\na,+4\n";
+    for aggfunc in &["sum", "max"] {
+        Command::new(program_path!())
+            .args(vec![aggfunc, "-r", "g", "-v", "v", "-N", "--no-trim"])
+            .write_stdin(stdin_contents)
+            .assert()
+            .success()
+            .stdout(match *aggfunc {
+                "sum" => ",total\na,10.8\n",
+                _ => ",total\na,10\n",
+            });
+    }
+}
+
+#[test]
+fn test_explode() {
+    let stdin_contents = "id,tags,amount\n1,a;b,10\n2,b; ;c,5\n3,,1\n";
+    // the value is shared by each piece, and records without any pieces are kept as they are
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "tags",
+            "-v",
+            "amount",
+            "--explode",
+            "tags",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,10\nb,15\nc,5\n,1\n");
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-v",
+            "tags",
+            "-e",
+            "--explode",
+            "tags",
+            "--explode-sep",
+            "b",
+        ])
+        .write_stdin("tags\nabc\nb\n")
+        .assert()
+        .success()
+        .stdout("total,2\n");
+}
+
+#[test]
+fn test_lexical_sort() {
+    let stdin_contents =
+        "city,state,year\nColumbus Heights,MN,2020\nColumbus,OH,2019\nAkron,OH,2020\n";
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "city,state",
+            "-c",
+            "year",
+            "-I",
+            "--key-sep",
+            " ",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",2020,2019\nColumbus Heights MN,1,\nColumbus OH,,1\nAkron OH,1,\n");
+    // labels sort field by field, so `Columbus` comes before `Columbus Heights`
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "city,state",
+            "-c",
+            "year",
+            "--sort",
+            "lexical",
+            "--key-sep",
+            " ",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",2019,2020\nAkron OH,,1\nColumbus OH,1,\nColumbus Heights MN,,1\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "city", "--sort", "lexical", "-D"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_countif() {
+    let stdin_contents = "service,status\napi,error: timeout\napi,ok\napi,err\nweb,ok\n";
+    Command::new(program_path!())
+        .args(vec![
+            "countif", "-r", "service", "-v", "status", "--match", "^err",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\napi,2\nweb,0\n");
+    Command::new(program_path!())
+        .args(vec!["countif", "-r", "service", "-v", "status"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["countif", "-v", "status", "--match", "(err"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["count", "-v", "status", "--match", "^err"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_expr() {
+    let stdin_contents = "region,qty,unit price\nN,2,1.5\nS,1,4\nN,3,2\nS,,5\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "--expr",
+            "qty * \"unit price\"",
+            "-r",
+            "region",
+            "-e",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nN,9.0\nS,4\n");
+    Command::new(program_path!())
+        .args(vec!["max", "--expr", "(qty + 1) / 2", "-e"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,2\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "--expr", "qty * price", "-e"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["sum", "--expr", "qty *", "-e"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_closed_output_pipe() {
+    use std::io::Read;
+    use std::process::Stdio;
+    let dir = common::TempDir::new("pipe");
+    let input = dir.join("input.csv");
+    // enough output to fill the pipe's buffer after the reader closes it
+    let mut contents = String::from("id,amount\n");
+    for i in 0..100_000 {
+        contents.push_str(&format!("{},1\n", i));
+    }
+    fs::write(&input, contents).unwrap();
+    let mut child = std::process::Command::new(program_path!())
+        .args(vec!["sum", "-r", "id", "-v", "amount"])
+        .arg(&input)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut start = [0; 16];
+    child.stdout.take().unwrap().read_exact(&mut start).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(str::from_utf8(&output.stderr).unwrap(), "");
+}
+
+#[test]
+fn test_pretty() {
+    let stdin_contents = "region,product,sale\nEast,a very long name,5\nEast,b,19.5\nWest,c,1000\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum", "-r", "product", "-c", "region", "-v", "sale", "--pretty",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(
+            "                  East  West\na very long name     5\nb                 19.5\nc                       1000\n",
+        );
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "product",
+            "-c",
+            "region",
+            "-v",
+            "sale",
+            "--pretty",
+            "--max-width",
+            "6",
+            "--na-rep",
+            "-",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("        East  West\na ver…     5     -\nb       19.5     -\nc          -  1000\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "sale", "--pretty", "--max-width", "0"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_dash_reads_stdin() {
+    let stdin_contents = "name,amount\nMax,1\nSam,2\nMax,3\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-", "-r", "name", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,4\nSam,2\n");
+    // `-` isn't a .tsv file, so the input is comma-delimited unless you say otherwise
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount", "-t", "-"])
+        .write_stdin(stdin_contents.replace(',', "\t"))
+        .assert()
+        .success()
+        .stdout("total,6\n");
+}
+
+#[test]
+fn test_skip_repeated_header() {
+    let stdin_contents = "name,amount\nMax,1\nname,amount\nSam,2\nMax,3\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "name",
+            "-v",
+            "amount",
+            "--skip-repeated-header",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,4\nSam,2\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "name", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(4);
+}
+
+#[test]
+fn test_no_output_header() {
+    let stdin_contents = "name,amount\nMax,1\nSam,2\nMax,3\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "name",
+            "-v",
+            "amount",
+            "--no-output-header",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("Max,4\nSam,2\n");
+}
+
+#[test]
+fn test_length() {
+    let stdin_contents = "lang,word\nfr,café\nfr,naïve\nja,日本\nja,東京都\n";
+    Command::new(program_path!())
+        .args(vec!["length", "-r", "lang", "-v", "word"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nfr,4 - 5 (4.5)\nja,2 - 3 (2.5)\n");
+}
+
+#[test]
+fn test_label_replace() {
+    let stdin_contents =
+        "company,year,amount\nAcme Inc (123),2019,1\nAcme Inc (456),2020,2\nBeta (1),2019,5\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "company",
+            "-c",
+            "year",
+            "-v",
+            "amount",
+            "--row-replace",
+            r" \(.*\)$",
+            "",
+            "--col-replace",
+            "^20(..)",
+            "'$1",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",'19,'20\nAcme Inc,1,2\nBeta,5,\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "company",
+            "-v",
+            "amount",
+            "--row-replace",
+            "(",
+            "",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_countunique_parsed_values() {
+    let stdin_contents = "group,value\na,1.0\na,1.00\na,1\nb,2\nb,2.5\n";
+    Command::new(program_path!())
+        .args(vec!["countunique", "-r", "group", "-v", "value"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,3\nb,2\n");
+    Command::new(program_path!())
+        .args(vec!["countunique", "-r", "group", "-v", "value", "-N"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,1\nb,2\n");
+    Command::new(program_path!())
+        .args(vec!["countunique", "-v", "date", "-F", "%Y-%m-%d;%m/%d/%Y"])
+        .write_stdin("date\n2020-01-01\n01/01/2020\n2020-01-02\n")
+        .assert()
+        .success()
+        .stdout("total,2\n");
+}
+
+#[test]
+fn test_zero_pad() {
+    let stdin_contents = "id,year,amount\n10,2,1\n9,10,1\n100,2,1\n9,1,1\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "id",
+            "-c",
+            "year",
+            "-v",
+            "amount",
+            "-A",
+            "--zero-pad",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",1,2,10\n9,1,,1\n10,,1,\n100,,1,\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "id",
+            "-c",
+            "year",
+            "-v",
+            "amount",
+            "-D",
+            "--zero-pad",
+            "--keep-padding",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",01,02,10\n100,,1,\n010,,1,\n009,1,,1\n");
+}
+
+#[test]
+fn test_agg_option() {
+    let stdin_contents = "name,amount\nMax,1\nSam,2\nMax,3\n";
+    Command::new(program_path!())
+        .args(vec!["--agg", "sum", "-r", "name", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nMax,4\nSam,2\n");
+    Command::new(program_path!())
+        .args(vec![
+            "--agg=max",
+            "test_csvs/layoffs.csv",
+            "-v",
+            "salary",
+            "-N",
+        ])
+        .assert()
+        .success();
+    Command::new(program_path!())
+        .args(vec!["mean", "--agg", "sum", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["summ", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_one_indexed() {
+    let zero_indexed = setup_cmd(&["sum", "test_csvs/layoffs.csv", "-r", "1", "-v", "0"]);
+    let one_indexed = setup_cmd(&[
+        "sum",
+        "test_csvs/layoffs.csv",
+        "-r",
+        "2",
+        "-v",
+        "1",
+        "--one-indexed",
+    ]);
+    assert!(zero_indexed.status.success());
+    assert_eq!(zero_indexed.stdout, one_indexed.stdout);
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "test_csvs/layoffs.csv",
+            "-v",
+            "0",
+            "--one-indexed",
+        ])
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_precision_loss_warning() {
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount"])
+        .write_stdin("amount\n1\n123456789012345678901234567890123\n1.12345678901234567890123456789012\n")
+        .assert()
+        .success()
+        .stderr("Warning: `123456789012345678901234567890123` in record 1 has more digits than clipivot \
+        can represent exactly, so the results may be wrong\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount"])
+        .write_stdin("amount\n0.5\n12345678901234567890123456789\n")
+        .assert()
+        .success()
+        .stderr("");
+}
+
+#[test]
+fn test_rate() {
+    let stdin_contents = "group,converted\na,yes\na,no\nb,TRUE\na,1\nb,f\nb,0\n";
+    Command::new(program_path!())
+        .args(vec!["rate", "-r", "group", "-v", "converted"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,0.6667\nb,0.3333\n");
+    Command::new(program_path!())
+        .args(vec!["rate", "-r", "group", "-v", "converted", "--percent"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,66.67%\nb,33.33%\n");
+    Command::new(program_path!())
+        .args(vec!["rate", "-v", "converted"])
+        .write_stdin("converted\nyes\nmaybe\n")
+        .assert()
+        .failure()
+        .code(4);
+}
+
+#[test]
+fn test_mode_tiebreak() {
+    // 10 and 9 tie with two records each, and 10 reaches two records first
+    let stdin_contents = "value\n10\n9\n10\n9.0\n8\n";
+    let expected = vec![
+        ("first", "10"),
+        ("last", "9.0"),
+        ("min", "9"),
+        ("max", "10"),
+    ];
+    for (tiebreak, mode) in expected {
+        Command::new(program_path!())
+            .args(vec!["mode", "-v", "value", "-N", "--tiebreak", tiebreak])
+            .write_stdin(stdin_contents)
+            .assert()
+            .success()
+            .stdout(format!("total,{}\n", mode));
+    }
+    // without -N, values are compared as text
+    Command::new(program_path!())
+        .args(vec!["mode", "-v", "value", "--tiebreak", "min"])
+        .write_stdin("value\n10\n9\n10\n9\n")
+        .assert()
+        .success()
+        .stdout("total,10\n");
+}
+
+#[test]
+fn test_row_percent() {
+    let output = setup_cmd(&[
+        "sum",
+        "test_csvs/layoffs.csv",
+        "-r",
+        "department",
+        "-c",
+        "was_fired",
+        "-v",
+        "salary",
+        "--percent",
+    ]);
+    assert!(output.status.success());
+    let stdout = str::from_utf8(&output.stdout).unwrap();
+    for line in stdout.lines().skip(1) {
+        let total: f64 = line
+            .split(',')
+            .skip(1)
+            .filter(|cell| !cell.is_empty())
+            .map(|cell| cell.trim_end_matches('%').parse::<f64>().unwrap())
+            .sum();
+        assert!((total - 100.0).abs() < 0.1);
+    }
+    let stdin_contents = "team,result\na,win\na,win\na,loss\nb,loss\n";
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "team", "-c", "result", "--percent"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",loss,win\na,33.33%,66.67%\nb,100%,\n");
+    Command::new(program_path!())
+        .args(vec![
+            "count",
+            "-r",
+            "team",
+            "-c",
+            "result",
+            "--percent",
+            "--keep-raw",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",loss,win\na,1 (33.33%),2 (66.67%)\nb,1 (100%),\n");
+    // a row that sums to zero has no percentages to add to its raw values
+    let stdin_contents = "g,c,v\na,x,1\na,y,-1\nb,x,2\nb,y,2\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "g",
+            "-c",
+            "c",
+            "-v",
+            "v",
+            "--percent",
+            "--keep-raw",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",x,y\na,1,-1\nb,2 (50%),2 (50%)\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "g",
+            "-c",
+            "c",
+            "-v",
+            "v",
+            "--percent",
+            "--keep-raw",
+            "--na-rep",
+            "NA",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",x,y\na,1 (NA),-1 (NA)\nb,2 (50%),2 (50%)\n");
+    Command::new(program_path!())
+        .args(vec!["mean", "-r", "team", "-v", "result", "--percent"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+/// A benchmark for wide, sparse pivot tables, where every record has its own column.
+/// Run it with `cargo test -- --ignored bench_sparse_pivot --nocapture`.
+#[test]
+#[ignore]
+fn bench_sparse_pivot() {
+    let num_records = 3000;
+    let mut stdin_contents = "row,col\n".to_string();
+    for i in 0..num_records {
+        stdin_contents.push_str(&format!("r{},c{}\n", i, i));
+    }
+    let start = std::time::Instant::now();
+    let output = Command::new(program_path!())
+        .args(vec!["count", "-r", "row", "-c", "col"])
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    eprintln!(
+        "{} x {} sparse pivot table: {:?}",
+        num_records,
+        num_records,
+        start.elapsed()
+    );
+    assert!(output.status.success());
+    let stdout = str::from_utf8(&output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), num_records + 1);
+    assert!(stdout
+        .lines()
+        .skip(1)
+        .all(|line| line.matches(",1").count() == 1));
+}
+
+#[test]
+fn test_shape() {
+    Command::new(program_path!())
+        .args(vec![
+            "shape",
+            "test_csvs/layoffs.csv",
+            "-r",
+            "department",
+            "-c",
+            "was_fired",
+        ])
+        .assert()
+        .success()
+        .stdout("rows,columns,cells\n2,2,4\n");
+    Command::new(program_path!())
+        .args(vec!["shape", "-r", "team", "-c", "result"])
+        .write_stdin("team,result\na,win\na,win\nb,loss\n")
+        .assert()
+        .success()
+        .stdout("rows,columns,cells\n2,2,2\n");
+    Command::new(program_path!())
+        .args(vec!["shape", "-r", "team", "-v", "result"])
+        .write_stdin("team,result\na,win\n")
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_utc_offsets() {
+    // 10:00, 11:00 and 10:30 in UTC
+    let stdin_contents =
+        "time\n2023-05-01T12:00:00+02:00\n2023-05-01T11:00:00Z\n2023-05-01T08:30:00-02:00\n";
+    Command::new(program_path!())
+        .args(vec!["minmax", "-v", "time", "--infer"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,2023-05-01 10:00:00 - 2023-05-01 11:00:00\n");
+    Command::new(program_path!())
+        .args(vec![
+            "range",
+            "-v",
+            "time",
+            "-F",
+            "%Y-%m-%d %H:%M:%S",
+            "--date-range-unit",
+            "hours",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,1\n");
+    // naive dates are converted from --assume-tz, so they compare with the others
+    Command::new(program_path!())
+        .args(vec![
+            "max",
+            "-v",
+            "time",
+            "-F",
+            "%Y-%m-%d %H:%M:%S",
+            "--assume-tz",
+            "-01:00",
+        ])
+        .write_stdin(format!("{}2023-05-01 10:15:00\n", stdin_contents))
+        .assert()
+        .success()
+        .stdout("total,2023-05-01 11:15:00\n");
+    Command::new(program_path!())
+        .args(vec!["max", "-v", "time", "-F", "%Y", "--assume-tz", "EST"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_first_and_last() {
+    let stdin_contents = "team,player,joined\n\
+                          a,ann,2021-03-01\n\
+                          a,bob,2020-07-15\n\
+                          b,cat,2022-01-01\n\
+                          a,dan,2020-07-15\n\
+                          a,eve,2019-12-31\n";
+    Command::new(program_path!())
+        .args(vec!["first", "-r", "team", "-v", "player"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,ann\nb,cat\n");
+    Command::new(program_path!())
+        .args(vec!["last", "-r", "team", "-v", "player"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,eve\nb,cat\n");
+    Command::new(program_path!())
+        .args(vec![
+            "first",
+            "-r",
+            "team",
+            "-v",
+            "player",
+            "--order-by",
+            "joined",
+            "-F",
+            "%Y-%m-%d",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,eve\nb,cat\n");
+    // bob and dan tie for the latest date, so the last of them wins
+    Command::new(program_path!())
+        .args(vec![
+            "last",
+            "-r",
+            "team",
+            "-v",
+            "player",
+            "--order-by",
+            "joined",
+            "-F",
+            "%Y-%m-%d",
+        ])
+        .write_stdin(stdin_contents.replace("2021-03-01", "2018-01-01"))
+        .assert()
+        .success()
+        .stdout(",total\na,dan\nb,cat\n");
+    Command::new(program_path!())
+        .args(vec!["first", "-v", "player", "-N"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["max", "-v", "player", "--order-by", "joined"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_sniff_delimiter() {
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "team", "-c", "result"])
+        .write_stdin("team;result;notes\na;win;close\nb;loss;\"x,y\"\na;win;\n")
+        .assert()
+        .success()
+        .stdout(",loss,win\na,,2\nb,1,\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "team", "-v", "points"])
+        .write_stdin("team|points\na|1\nb|2\na|3\n")
+        .assert()
+        .success()
+        .stdout(",total\na,4\nb,2\n");
+    // -d overrides the guess
+    Command::new(program_path!())
+        .args(vec!["count", "-v", "team|points", "-d", ","])
+        .write_stdin("team|points\na|1\nb|2\n")
+        .assert()
+        .success()
+        .stdout("total,2\n");
+}
+
+#[test]
+fn test_merge_files() {
+    let dir = common::TempDir::new("merge");
+    let parts = [
+        "state,year,amount\nOH,2019,1.5\nOH,2020,2\nPA,2020,3\n",
+        "state,year,amount\nPA,2019,4\nOH,2019,7.25\nNY,2021,\n",
+        "state,year,amount\nNY,2020,10\nOH,2019,-2\nPA,2020,3\n",
+    ];
+    let mut paths = Vec::new();
+    let mut combined = String::from("state,year,amount\n");
+    for (i, part) in parts.iter().enumerate() {
+        let path = dir.join(format!("part{}.csv", i));
+        fs::write(&path, part).unwrap();
+        paths.push(path);
+        combined.push_str(part.split_once('\n').unwrap().1);
+    }
+    for aggfunc in &[
+        "count", "sum", "mean", "stddev", "median", "minmax", "first", "last",
+    ] {
+        let args = vec![*aggfunc, "-r", "state", "-c", "year", "-v", "amount", "-e"];
+        let single = Command::new(program_path!())
+            .args(&args)
+            .write_stdin(combined.clone())
+            .output()
+            .unwrap();
+        assert!(single.status.success());
+        for merge in &[false, true] {
+            let mut cmd = Command::new(program_path!());
+            cmd.arg(aggfunc).args(&paths).args(&args[1..]);
+            if *merge {
+                cmd.arg("--merge-files");
+            }
+            cmd.assert()
+                .success()
+                .stdout(String::from_utf8(single.stdout.clone()).unwrap());
+        }
+    }
+    let other = dir.join("other.csv");
+    fs::write(&other, "state,amount\nOH,1\n").unwrap();
+    Command::new(program_path!())
+        .arg("sum")
+        .arg(&paths[0])
+        .arg(&other)
+        .args(vec!["-r", "state", "-v", "amount"])
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .arg("sum")
+        .arg(&paths[0])
+        .arg("-")
+        .args(vec!["-r", "state", "-v", "amount"])
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_late_columns() {
+    let stdin_contents = "r,c\nb,x\na,x\nb,y\nc,z\n";
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "r", "-c", "c"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",x,y,z\nb,1,1,\na,1,,\nc,,,1\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "r", "-c", "c", "-R", "-D"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",z,y,x\nc,1,,\nb,,1,1\na,,,1\n");
+}
+
+#[test]
+fn test_explain() {
+    let stdin_contents =
+        "month,region,amount\n2023-05,north,10\n2023-05,south,3\n2023-06,north,1\n2023-05,north,-4\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "month", "-c", "region", "-v", "amount"])
+        .args(vec!["--explain", "2023-05|north"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("month,region,amount\n2023-05,north,10\n2023-05,north,-4\n");
+    // without a column, the column is `total`
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "month",
+            "-v",
+            "amount",
+            "--explain",
+            "2023-06",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("month,region,amount\n2023-06,north,1\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "month", "-c", "region", "-v", "amount"])
+        .args(vec!["--explain", "2023-06|south"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("month,region,amount\n")
+        .stderr("Warning: no records went into the `2023-06|south` cell\n");
+}
+
+#[test]
+fn test_everything_skipped() {
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "a", "-v", "b", "-e"])
+        .write_stdin("a,b\nx,\ny,NA\n")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(
+            "Could not properly configure the aggregator: \
+             All 2 record(s) had empty values in the values column, so they were all skipped. \
+             Did you choose the right column? To aggregate them anyway, remove -e\n",
+        );
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "a", "-v", "b", "--skip-errors"])
+        .write_stdin("a,b\nx,q\n")
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(
+            "Skipped 1 value(s) that could not be parsed:\n  \
+             Could not parse record `q` with index 0: Failed to parse as numeric\n\
+             Could not properly configure the aggregator: \
+             None of the 1 value(s) could be parsed, so --skip-errors skipped all of them. \
+             Did you choose the right column and function?\n",
+        );
+}
+
+#[test]
+fn test_add_column() {
+    let stdin_contents = "a,b,v\nx,1,2\ny,2,3\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "a",
+            "-c",
+            "b",
+            "-v",
+            "v",
+            "--with-col-count",
+        ])
+        .args(vec![
+            "--add-column",
+            "src=s1",
+            "--add-column",
+            "run=2024-01-01",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",src,run,1,2\nx,s1,2024-01-01,2,\ny,s1,2024-01-01,,3\ncount,s1,2024-01-01,1,1\n");
+    for column in ["src", "=s1"] {
+        Command::new(program_path!())
+            .args(vec!["sum", "-r", "a", "-v", "v", "--add-column", column])
+            .write_stdin(stdin_contents)
+            .assert()
+            .failure()
+            .code(2);
+    }
+}
+
+#[test]
+fn test_strict_headers() {
+    let stdin_contents = "amount,region,amount\n1,west,2\n3,east,4\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount[1]"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,6\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "region", "--strict-headers"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(
+            "Could not properly configure the aggregator: \
+             The header row has more than one column named `amount` (columns 0 and 2)\n",
+        );
+}
+
+#[test]
+fn test_jsonl_input() {
+    let stdin_contents = "{\"state\": \"OH\", \"year\": 2020, \"amount\": 1.5}\n\
+                          {\"state\": \"PA\", \"amount\": 2}\n\
+                          {\"state\": \"OH\", \"year\": 2020, \"amount\": 3}\n";
+    // PA doesn't have a year, so its year is empty
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "--format-in",
+            "jsonl",
+            "-r",
+            "state",
+            "-c",
+            "year",
+            "-v",
+            "amount",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",,2020\nOH,,4.5\nPA,2,\n");
+    Command::new(program_path!())
+        .args(vec!["count", "--format-in", "jsonl", "-r", "state"])
+        .write_stdin("{\"state\": \"OH\"}\nnot json\n")
+        .assert()
+        .failure()
+        .code(4);
+}
+
+#[test]
+fn test_sort_cols_by_total() {
+    let stdin_contents = "r,c,v\na,x,1\na,y,5\nb,x,1\nb,z,3\nc,z,1\nc,w,4\n";
+    // z and w both add up to 4, so they stay in the order they were first seen
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "r",
+            "-c",
+            "c",
+            "-v",
+            "v",
+            "--sort-cols-by-total",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",y,z,w,x\na,5,,,1\nb,,3,,1\nc,,1,4,\n");
+    Command::new(program_path!())
+        .args(vec!["count", "-c", "c", "--sort-cols-by-total", "-A"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",x,z,y,w\ntotal,2,2,1,1\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-c",
+            "c",
+            "-v",
+            "v",
+            "--sort-cols-by-total",
+            "-R",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["mean", "-c", "c", "-v", "v", "--sort-cols-by-total"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_coalesce() {
+    // a has both fields, b only the second, c neither (and d an NA that -e skips)
+    let stdin_contents = "id,usd,eur\na,1,2\na,,3\nb, ,4\nc,,\nd,NA,5\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "id", "--coalesce", "usd,eur", "-e"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,4\nb,4\nd,5\n");
+    // the order of the fields decides which one wins
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "id", "--coalesce", "eur,usd", "-e"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,5\nb,4\nd,5\n");
+    // without -e, a record where every field is empty can't be parsed as a number
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "id", "--coalesce", "usd,eur"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(4);
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "id", "--coalesce", "usd,eur", "-e"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,2\nb,1\nd,1\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "id",
+            "--coalesce",
+            "usd,eur",
+            "-v",
+            "usd",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure();
+    Command::new(program_path!())
+        .args(vec!["crosstab", "-r", "id", "--coalesce", "usd,eur"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_percentile_rank() {
+    let stdin_contents = "team,score\na,1\na,2\na,3\na,4\nb,5\nb,10\nb,-1\n";
+    Command::new(program_path!())
+        .args(vec![
+            "percentilerank",
+            "-r",
+            "team",
+            "-v",
+            "score",
+            "--at",
+            "3",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\na,0.75\nb,0.3333333333333333333333333333\n");
+    Command::new(program_path!())
+        .args(vec!["percentilerank", "-v", "score", "--at", "-1"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,0.1428571428571428571428571429\n");
+    Command::new(program_path!())
+        .args(vec!["percentilerank", "-r", "team", "-v", "score"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["percentilerank", "-v", "score", "--at", "high"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+    Command::new(program_path!())
+        .args(vec!["median", "-v", "score", "--at", "3"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_decimal_comma() {
+    // semicolons separate the fields, which the delimiter sniffing picks up on its own
+    let stdin_contents = "city;qty;amount\nBerlin;2;1.234,56\nBerlin;1;10,5\nParis;4;0,25\n";
+    let output = Command::new(program_path!())
+        .args(vec!["sum", "-r", "city", "-v", "amount", "--decimal-comma"])
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    let output = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(output, ",total\nBerlin,\"1245,06\"\nParis,\"0,25\"\n");
+    // the output parses the same way it was written
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "total", "--decimal-comma"])
+        .write_stdin(output)
+        .assert()
+        .success()
+        .stdout("total,\"1245,31\"\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "city",
+            "-v",
+            "amount",
+            "--decimal-comma",
+            "--group-digits",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nBerlin,\"1.245,06\"\nParis,\"0,25\"\n");
+    Command::new(program_path!())
+        .args(vec!["max", "-N", "-v", "amount", "--decimal-comma"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("total,\"1234,56\"\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "city",
+            "--expr",
+            "qty * amount",
+            "--decimal-comma",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nBerlin,\"2479,62\"\nParis,\"1,00\"\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(4);
+    Command::new(program_path!())
+        .args(vec!["count", "-v", "amount", "--decimal-comma"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_clean_headers() {
+    let stdin_contents =
+        "\"  Order Date \",Sales Region,AMOUNT\n2020-01-01,west,1\n2020-01-01,east,2\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "order_date",
+            "-c",
+            "sales_region",
+            "-v",
+            "amount",
+            "--clean-headers",
+            "--header-comment",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(
+            "# values: sum(amount); rows: order_date; columns: sales_region\n\
+             ,east,west\n2020-01-01,2,1\n",
+        );
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "order_date", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_multiple_functions() {
+    let stdin_contents = "region,kind,amount\nN,a,1\nN,b,2\nS,a,3\nN,a,6\n";
+    Command::new(program_path!())
+        .args(vec![
+            "agg",
+            "-r",
+            "region",
+            "-v",
+            "amount",
+            "--funcs",
+            "sum, mean,count",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",sum,mean,count\nN,9,3,3\nS,3,3,1\n");
+    Command::new(program_path!())
+        .args(vec![
+            "agg", "-r", "region", "-c", "kind", "-v", "amount", "--funcs", "max,min",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",a_<sep>_max,a_<sep>_min,b_<sep>_max,b_<sep>_min\nN,6,1,2,2\nS,3,3,,\n");
+    for args in [
+        vec!["agg", "-v", "amount"],
+        vec!["agg", "-v", "amount", "--funcs", "sum,mode"],
+        vec!["agg", "-v", "amount", "--funcs", "sum,sum"],
+        vec!["sum", "-v", "amount", "--funcs", "sum"],
+    ] {
+        Command::new(program_path!())
+            .args(args)
+            .write_stdin(stdin_contents)
+            .assert()
+            .failure()
+            .code(2);
+    }
+}
+
+#[test]
+fn test_spread() {
+    let stdin_contents = "id,metric,value\n1,h,10\n1,w,3\n2,h,12\n2,w,4\n";
+    let base = vec!["spread", "-r", "id", "-c", "metric", "-v", "value"];
+    Command::new(program_path!())
+        .args(&base)
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",h,w\n1,10,3\n2,12,4\n");
+    let conflicting = format!("{}2,h,1\n", stdin_contents);
+    Command::new(program_path!())
+        .args(&base)
+        .write_stdin(conflicting.as_str())
+        .assert()
+        .failure()
+        .code(4);
+    for (on_conflict, expected) in [
+        ("first", ",h,w\n1,10,3\n2,12,4\n"),
+        ("last", ",h,w\n1,10,3\n2,1,4\n"),
+        ("sum", ",h,w\n1,10,3\n2,13,4\n"),
+    ] {
+        Command::new(program_path!())
+            .args(&base)
+            .args(vec!["--on-conflict", on_conflict])
+            .write_stdin(conflicting.as_str())
+            .assert()
+            .success()
+            .stdout(expected);
+    }
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "value", "--on-conflict", "sum"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_ragged_rows() {
+    let stdin_contents = "a,b,c\n1,x,3\n2,y\n3,z,4,5\n4,x,1\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "b", "-v", "c"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .code(4)
+        .stdout("")
+        .stderr("Line 3 has 2 field(s), but the first line has 3\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "b", "-v", "c", "--lenient"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nx,4\n")
+        .stderr(
+            "Skipped 2 record(s) with the wrong number of fields:\n  \
+             Line 3 has 2 field(s), but the first line has 3\n  \
+             Line 4 has 4 field(s), but the first line has 3\n",
+        );
+}
+
+#[test]
+fn test_tidyr_pivots() {
+    let wide = "id,height,weight\n1,10,3\n2,12,\n";
+    let long = "id,name,value\n1,height,10\n1,weight,3\n2,height,12\n2,weight,\n";
+    Command::new(program_path!())
+        .args(vec!["pivot-longer", "-r", "id"])
+        .write_stdin(wide)
+        .assert()
+        .success()
+        .stdout(long);
+    Command::new(program_path!())
+        .args(vec![
+            "pivot-longer",
+            "-r",
+            "id",
+            "--names-to",
+            "metric",
+            "--values-to",
+            "amount",
+            "-e",
+        ])
+        .write_stdin(wide)
+        .assert()
+        .success()
+        .stdout("id,metric,amount\n1,height,10\n1,weight,3\n2,height,12\n");
+    Command::new(program_path!())
+        .args(vec![
+            "pivot-wider",
+            "-r",
+            "id",
+            "--names-from",
+            "name",
+            "--values-from",
+            "value",
+        ])
+        .write_stdin(long)
+        .assert()
+        .success()
+        .stdout(",height,weight\n1,10,3\n2,12,\n");
+    for args in [
+        vec!["pivot-longer"],
+        vec!["pivot-longer", "-r", "id", "-v", "height"],
+        vec![
+            "spread",
+            "-r",
+            "id",
+            "-c",
+            "height",
+            "-v",
+            "weight",
+            "--names-to",
+            "x",
+        ],
+    ] {
+        Command::new(program_path!())
+            .args(args)
+            .write_stdin(wide)
+            .assert()
+            .failure()
+            .code(2);
+    }
+}
+
+#[test]
+fn test_weighted_count() {
+    let stdin_contents = "region,kind,freq\nN,a,3\nN,b,2\nS,a,1.5\nN,a,4\n";
+    let weighted = Command::new(program_path!())
+        .args(vec!["count", "-r", "region", "--weight", "freq"])
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    let summed = Command::new(program_path!())
+        .args(vec!["sum", "-r", "region", "-v", "freq"])
+        .write_stdin(stdin_contents)
+        .output()
+        .unwrap();
+    assert!(weighted.status.success());
+    assert_eq!(weighted.stdout, summed.stdout);
+    assert_eq!(
+        str::from_utf8(&weighted.stdout).unwrap(),
+        ",total\nN,9\nS,1.5\n"
+    );
+    Command::new(program_path!())
+        .args(vec!["count", "-r", "region", "--weight", "kind"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(4)
+        .stderr("Could not parse record `a` with index 0: Failed to parse the weight as numeric\n");
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "freq", "--weight", "freq"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_split_by() {
+    let dir = common::TempDir::new("split");
+    let stdin_contents = "region,kind,amount\nwest,a,1\nwest,b,2\neast,a,3\nwest,a,4\n";
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "kind",
+            "-v",
+            "amount",
+            "--split-by",
+            "region",
+        ])
+        .arg("--output-dir")
+        .arg(dir.path())
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout("");
+    let mut files: Vec<String> = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(files, vec!["east.csv", "west.csv"]);
+    assert_eq!(
+        fs::read_to_string(dir.join("west.csv")).unwrap(),
+        ",total\na,5\nb,2\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.join("east.csv")).unwrap(),
+        ",total\na,3\n"
+    );
+    // `a/b` and `a_b` would both go in `a_b.csv`
+    Command::new(program_path!())
+        .args(vec!["sum", "-v", "amount", "--split-by", "region"])
+        .arg("--output-dir")
+        .arg(dir.path())
+        .write_stdin("region,amount\na/b,1\na_b,2\n")
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_null_skip_rows() {
+    let stdin_contents = "region,kind,amount\nwest,a,1\n,a,2\nwest,,3\n,,4\n";
+    Command::new(program_path!())
+        .args(vec!["sum", "-r", "region", "-v", "amount"])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nwest,4\n,6\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "region",
+            "-v",
+            "amount",
+            "--null-skip-rows",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",total\nwest,4\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "region",
+            "-c",
+            "kind",
+            "-v",
+            "amount",
+            "--null-skip-rows",
+            "--null-skip-mode",
+            "all",
+        ])
+        .write_stdin(stdin_contents)
+        .assert()
+        .success()
+        .stdout(",,a\nwest,3,1\n,,2\n");
+    Command::new(program_path!())
+        .args(vec![
+            "sum",
+            "-r",
+            "region",
+            "-v",
+            "amount",
+            "--null-skip-rows",
+        ])
+        .write_stdin("region,amount\n,1\n")
+        .assert()
+        .failure()
+        .code(2);
+}
+
+#[test]
+fn test_fast_median_skips_nan() {
+    Command::new(program_path!())
+        .args(vec!["median", "-r", "a", "-v", "b", "--fast-median"])
+        .write_stdin("a,b\nx,NaN\nx,1\nx,3\nx,2\nx,NaN\nx,5\nx,4\ny,NaN\n")
+        .assert()
+        .success()
+        .stdout(",total\nx,3\ny,\n");
+}
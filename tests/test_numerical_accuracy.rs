@@ -35,12 +35,14 @@ fn get_actual_result(filename: &str, aggfunc: &str) -> f64 {
     // the match formatting is required to get these tests to work in Travis CI
     let program_name = program_path!();
     let output = Command::new(program_name)
-        .args(&[aggfunc, filename, "-v", "0"])
+        .args([aggfunc, filename, "-v", "0"])
         .output()
         .expect("Process failed to execute")
         .stdout;
     let stroutput = str::from_utf8(&output).unwrap();
-    let mut rdr = csv::Reader::from_reader(stroutput.as_bytes());
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(stroutput.as_bytes());
     let mut iter = rdr.deserialize();
     let item: NumericRecord = iter.next().unwrap().unwrap();
     item.1